@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use crate::errors::PacketSnifferError;
+use crate::options::CaptureOptions;
+use crate::quic::is_quic;
+
+/// Magic cookie contained in every STUN message since RFC 5389.
+const STUN_MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xa4, 0x42];
+/// Magic cookie that precedes the options of a DHCPv4 message.
+const DHCP_MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+/// Methods recognized at the beginning of an HTTP/1.x request.
+const HTTP_METHODS: [&str; 9] = ["GET ", "POST ", "PUT ", "DELETE ", "HEAD ", "OPTIONS ", "PATCH ", "CONNECT ", "TRACE "];
+/// Methods recognized at the beginning of a SIP request.
+const SIP_METHODS: [&str; 6] = ["INVITE sip:", "REGISTER sip:", "OPTIONS sip:", "ACK sip:", "BYE sip:", "CANCEL sip:"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Struct representing a user-defined payload signature. A payload matches when it contains `pattern` at byte `offset`
+/// and, if they are set, the transport protocol and one of the ports are the given ones.
+pub struct AppSignature {
+    /// Name of the application protocol assigned to the matching flows
+    pub name: String,
+    /// Position of the pattern from the beginning of the payload
+    pub offset: usize,
+    /// Bytes that the payload has to contain
+    pub pattern: Vec<u8>,
+    /// Transport protocol the signature applies to, e.g. "TCP"; `None` for any protocol
+    pub transport: Option<String>,
+    /// Port, source or destination, the signature applies to; `None` for any port
+    pub port: Option<u16>,
+}
+
+impl AppSignature {
+    /// Create a new AppSignature struct, matching any transport protocol and port.
+    pub fn new(name: String, offset: usize, pattern: Vec<u8>) -> AppSignature {
+        AppSignature{name, offset, pattern, transport: None, port: None}
+    }
+
+    /// Function that checks if the signature matches the payload of a packet.
+    fn matches(&self, payload: &[u8], transport: &str, source_port: &str, destination_port: &str) -> bool {
+        if let Some(t) = &self.transport {
+            if !t.eq_ignore_ascii_case(transport) {
+                return false;
+            }
+        }
+        if let Some(port) = self.port {
+            let port = port.to_string();
+            if source_port != port && destination_port != port {
+                return false;
+            }
+        }
+        // An offset close to usize::MAX cannot match, and must not overflow
+        let end = match self.offset.checked_add(self.pattern.len()) {
+            Some(end) => end,
+            None => return false
+        };
+        match payload.get(self.offset..end) {
+            Some(bytes) => bytes == self.pattern.as_slice(),
+            None => false
+        }
+    }
+}
+
+/// An `AppSignature` can be parsed from a string with format `NAME:OFFSET:HEX[:TRANSPORT[:PORT]]`,
+/// e.g. "MQTT:0:10" or "Custom:4:cafe:UDP:9000".
+impl FromStr for AppSignature {
+    type Err = PacketSnifferError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PacketSnifferError::InvalidSignature(s.to_string());
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() < 3 || fields.len() > 5 || fields[0].is_empty() || fields[2].is_empty() {
+            return Err(invalid());
+        }
+        let offset = fields[1].parse::<usize>().map_err(|_| invalid())?;
+        let pattern = hex::decode(fields[2]).map_err(|_| invalid())?;
+        let mut signature = AppSignature::new(fields[0].to_string(), offset, pattern);
+        if let Some(transport) = fields.get(3).filter(|t| !t.is_empty()) {
+            signature.transport = Some(transport.to_uppercase());
+        }
+        if let Some(port) = fields.get(4) {
+            signature.port = Some(port.parse::<u16>().map_err(|_| invalid())?);
+        }
+        Ok(signature)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+///Struct keeping the classification state of a flow: the detected application protocol and the payload bytes
+/// already inspected in each direction.
+pub struct FlowClassifier {
+    /// Application protocol detected in the flow
+    protocol: Option<String>,
+    /// Payload bytes inspected so far, for each sender (address and port)
+    inspected: HashMap<String, usize>,
+}
+
+impl FlowClassifier {
+    /// Inspects the payload of a packet sent by `sender`, as long as the flow is not classified and the sender
+    /// has not yet sent `options.inspected_bytes` bytes of payload.
+    pub fn inspect(&mut self, sender: String, transport: &str, source_port: &str, destination_port: &str, payload: &[u8], options: &CaptureOptions) {
+        if self.protocol.is_some() || payload.is_empty() {
+            return;
+        }
+        let inspected = self.inspected.entry(sender).or_insert(0);
+        if *inspected >= options.inspected_bytes {
+            return;
+        }
+        let window = &payload[..payload.len().min(options.inspected_bytes - *inspected)];
+        *inspected += window.len();
+        self.protocol = classify_payload(window, transport, source_port, destination_port, &options.signatures);
+    }
+
+    /// Application protocol detected in the flow, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Merges the classification of a later part of the same flow, keeping the protocol already detected.
+    pub fn merge(&mut self, other: FlowClassifier) {
+        if self.protocol.is_none() {
+            self.protocol = other.protocol;
+        }
+        for (sender, inspected) in other.inspected {
+            *self.inspected.entry(sender).or_insert(0) += inspected;
+        }
+    }
+}
+
+/// Function that recognizes the application protocol of a payload, independently of the port numbers.
+/// The user-defined `signatures` are checked first, in order, then the built-in signatures and heuristics.
+pub fn classify_payload(payload: &[u8], transport: &str, source_port: &str, destination_port: &str, signatures: &[AppSignature]) -> Option<String> {
+    if let Some(signature) = signatures.iter().find(|s| s.matches(payload, transport, source_port, destination_port)) {
+        return Some(signature.name.clone());
+    }
+    let name = match transport {
+        "TCP" => classify_tcp(payload),
+        "UDP" => classify_udp(payload),
+        _ => None
+    };
+    name.map(|n| n.to_string())
+}
+
+/// Function that applies the built-in signatures of the protocols carried by TCP.
+fn classify_tcp(payload: &[u8]) -> Option<&'static str> {
+    if is_tls_record(payload) {
+        return Some("TLS");
+    }
+    if payload.starts_with(b"SSH-") {
+        return Some("SSH");
+    }
+    if payload.starts_with(b"PRI * HTTP/2.0") {
+        return Some("HTTP/2");
+    }
+    if payload.starts_with(b"HTTP/1.") || HTTP_METHODS.iter().any(|m| payload.starts_with(m.as_bytes())) {
+        return Some("HTTP");
+    }
+    if payload.starts_with(b"SIP/2.0 ") || SIP_METHODS.iter().any(|m| payload.starts_with(m.as_bytes())) {
+        return Some("SIP");
+    }
+    // SMB messages are carried in NetBIOS session messages, with the protocol id after the 4-byte header
+    if payload.len() >= 8 && payload[0] == 0x00 && (&payload[4..8] == b"\xffSMB" || &payload[4..8] == b"\xfeSMB") {
+        return Some("SMB");
+    }
+    // RDP starts with a TPKT header followed by an X.224 Connection Request or Confirm
+    if payload.len() >= 6 && payload[0] == 0x03 && payload[1] == 0x00 && (payload[5] == 0xe0 || payload[5] == 0xd0) {
+        return Some("RDP");
+    }
+    if payload.starts_with(b"\x13BitTorrent protocol") {
+        return Some("BitTorrent");
+    }
+    if is_stun_message(payload) {
+        return Some("STUN");
+    }
+    if payload.starts_with(b"EHLO ") || payload.starts_with(b"HELO ") {
+        return Some("SMTP");
+    }
+    if payload.starts_with(b"220") {
+        let banner = String::from_utf8_lossy(&payload[..payload.len().min(128)]).to_uppercase();
+        if banner.contains("SMTP") {
+            return Some("SMTP");
+        }
+        if banner.contains("FTP") {
+            return Some("FTP");
+        }
+    }
+    if payload.starts_with(b"* OK") {
+        return Some("IMAP");
+    }
+    if payload.starts_with(b"+OK") {
+        return Some("POP3");
+    }
+    None
+}
+
+/// Function that applies the built-in signatures of the protocols carried by UDP.
+fn classify_udp(payload: &[u8]) -> Option<&'static str> {
+    if is_quic(payload) {
+        return Some("QUIC");
+    }
+    if is_stun_message(payload) {
+        return Some("STUN");
+    }
+    // DTLS records, used by WebRTC for the key exchange, have versions 0xfeff (1.0) and 0xfefd (1.2)
+    if payload.len() >= 13 && (20..=23).contains(&payload[0]) && payload[1] == 0xfe && (payload[2] == 0xff || payload[2] == 0xfd) {
+        return Some("DTLS");
+    }
+    if payload.starts_with(b"d1:ad2:id20:") || payload.starts_with(b"d1:rd2:id20:") {
+        return Some("BitTorrent");
+    }
+    if payload.starts_with(b"M-SEARCH * HTTP/1.1") || payload.starts_with(b"NOTIFY * HTTP/1.1") {
+        return Some("SSDP");
+    }
+    if payload.starts_with(b"SIP/2.0 ") || SIP_METHODS.iter().any(|m| payload.starts_with(m.as_bytes())) {
+        return Some("SIP");
+    }
+    if payload.len() >= 240 && payload[236..240] == DHCP_MAGIC_COOKIE {
+        return Some("DHCP");
+    }
+    if is_dns_message(payload) {
+        return Some("DNS");
+    }
+    None
+}
+
+/// Function that checks if a payload starts with a TLS record of a known content type and version.
+fn is_tls_record(payload: &[u8]) -> bool {
+    payload.len() >= 5 && (20..=23).contains(&payload[0]) && payload[1] == 0x03 && payload[2] <= 0x04
+}
+
+/// Function that checks if a payload is a STUN message, used also by WebRTC and TURN.
+fn is_stun_message(payload: &[u8]) -> bool {
+    if payload.len() < 20 || payload[0] & 0xc0 != 0 || payload[4..8] != STUN_MAGIC_COOKIE {
+        return false;
+    }
+    let length = u16::from_be_bytes([payload[2], payload[3]]) as usize;
+    length & 0x03 == 0 && length + 20 <= payload.len()
+}
+
+/// Function that checks if a payload looks like a DNS message with a single, well formed question.
+fn is_dns_message(payload: &[u8]) -> bool {
+    if payload.len() < 17 {
+        return false;
+    }
+    let opcode = (payload[2] >> 3) & 0x0f;
+    let questions = u16::from_be_bytes([payload[4], payload[5]]);
+    if opcode > 5 || questions != 1 {
+        return false;
+    }
+    // The question name is a sequence of labels terminated by an empty one
+    let mut offset = 12;
+    loop {
+        let len = match payload.get(offset) {
+            Some(len) => *len as usize,
+            None => return false
+        };
+        if len == 0 {
+            return offset > 12 && offset + 5 <= payload.len();
+        }
+        if len > 63 {
+            return false;
+        }
+        offset += len + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_matches_at_its_offset() {
+        let signature: AppSignature = "Custom:2:cafe:UDP".parse().unwrap();
+        assert!(signature.matches(&[0, 0, 0xca, 0xfe], "UDP", "1000", "2000"));
+        assert!(!signature.matches(&[0, 0, 0xca], "UDP", "1000", "2000"));
+        assert!(!signature.matches(&[0, 0, 0xca, 0xfe], "TCP", "1000", "2000"));
+    }
+
+    #[test]
+    fn huge_offset_does_not_overflow() {
+        let signature: AppSignature = format!("Custom:{}:cafe", usize::MAX).parse().unwrap();
+        assert!(!signature.matches(&[0xca, 0xfe], "UDP", "1000", "2000"));
+    }
+
+    #[test]
+    fn classifies_tcp_protocols() {
+        assert_eq!(classify_tcp(&[0x16, 0x03, 0x01, 0x00, 0x05]), Some("TLS"));
+        assert_eq!(classify_tcp(b"SSH-2.0-OpenSSH_9.0\r\n"), Some("SSH"));
+        assert_eq!(classify_tcp(b"\x00\x00\x00\x45\xffSMBr"), Some("SMB"));
+        assert_eq!(classify_tcp(b"\x00\x00\x00\x45\xfeSMB@"), Some("SMB"));
+        // TPKT header and X.224 Connection Request
+        assert_eq!(classify_tcp(&[0x03, 0x00, 0x00, 0x13, 0x0e, 0xe0, 0x00, 0x00]), Some("RDP"));
+        assert_eq!(classify_tcp(b"\x13BitTorrent protocol\x00\x00\x00\x00\x00\x10\x00\x05"), Some("BitTorrent"));
+        assert_eq!(classify_tcp(b"220 mail.example.com ESMTP Postfix\r\n"), Some("SMTP"));
+        assert_eq!(classify_tcp(b"EHLO client.example.com\r\n"), Some("SMTP"));
+        assert_eq!(classify_tcp(b"220 (vsFTPd 3.0.3)\r\n"), Some("FTP"));
+    }
+
+    #[test]
+    fn rejects_look_alike_tcp_payloads() {
+        // Unknown TLS version and content type
+        assert_eq!(classify_tcp(&[0x16, 0x03, 0x05, 0x00, 0x05]), None);
+        assert_eq!(classify_tcp(&[0x18, 0x03, 0x01, 0x00, 0x05]), None);
+        assert_eq!(classify_tcp(b"SSH 2.0"), None);
+        // NetBIOS session keepalive, and a message too short to contain the protocol id
+        assert_eq!(classify_tcp(b"\x85\x00\x00\x00\xffSMB"), None);
+        assert_eq!(classify_tcp(b"\x00\x00\x00\x45\xffSM"), None);
+        // X.224 Data instead of a connection request
+        assert_eq!(classify_tcp(&[0x03, 0x00, 0x00, 0x13, 0x0e, 0xf0, 0x00, 0x00]), None);
+        assert_eq!(classify_tcp(b"\x13BitTorrent"), None);
+        // A banner that names neither protocol
+        assert_eq!(classify_tcp(b"220 service ready\r\n"), None);
+    }
+
+    /// Returns a DNS query for example.com with the given question count.
+    fn dns_query(questions: u8) -> Vec<u8> {
+        let mut message = vec![0x12, 0x34, 0x01, 0x00, 0x00, questions, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        message.extend(b"\x07example\x03com\x00");
+        message.extend([0x00, 0x01, 0x00, 0x01]);
+        message
+    }
+
+    #[test]
+    fn classifies_udp_protocols() {
+        // Binding request without attributes
+        let mut stun = vec![0x00, 0x01, 0x00, 0x00];
+        stun.extend(STUN_MAGIC_COOKIE);
+        stun.extend([0x55; 12]);
+        assert_eq!(classify_udp(&stun), Some("STUN"));
+        // DTLS 1.2 handshake record
+        assert_eq!(classify_udp(&[0x16, 0xfe, 0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x10]), Some("DTLS"));
+        let mut dhcp = vec![0; 240];
+        dhcp[0] = 0x01;
+        dhcp[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE);
+        assert_eq!(classify_udp(&dhcp), Some("DHCP"));
+        assert_eq!(classify_udp(&dns_query(1)), Some("DNS"));
+    }
+
+    #[test]
+    fn rejects_look_alike_udp_payloads() {
+        // STUN with a length longer than the message, and with a wrong magic cookie
+        let mut stun = vec![0x00, 0x01, 0x00, 0x04];
+        stun.extend(STUN_MAGIC_COOKIE);
+        stun.extend([0x55; 12]);
+        assert_eq!(classify_udp(&stun), None);
+        stun[3] = 0x00;
+        stun[4] = 0x22;
+        assert_eq!(classify_udp(&stun), None);
+        // DTLS with an unknown version
+        assert_eq!(classify_udp(&[0x16, 0xfe, 0xfe, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x10]), None);
+        let mut dhcp = vec![0; 240];
+        dhcp[232..236].copy_from_slice(&DHCP_MAGIC_COOKIE);
+        assert_eq!(classify_udp(&dhcp), None);
+    }
+
+    #[test]
+    fn dns_heuristic_needs_a_single_well_formed_question() {
+        assert!(is_dns_message(&dns_query(1)));
+        assert!(!is_dns_message(&dns_query(2)));
+        // Opcode 6 is not assigned
+        let mut query = dns_query(1);
+        query[2] = 0x30;
+        assert!(!is_dns_message(&query));
+        // Label longer than 63 bytes
+        let mut query = dns_query(1);
+        query[12] = 0x40;
+        assert!(!is_dns_message(&query));
+        // Missing type and class
+        let query = dns_query(1);
+        assert!(!is_dns_message(&query[..query.len() - 1]));
+        // Root name
+        let mut root = dns_query(1)[..12].to_vec();
+        root.extend([0x00, 0x00, 0x01, 0x00, 0x01]);
+        assert!(!is_dns_message(&root));
+    }
+
+    #[test]
+    fn inspection_stops_after_inspected_bytes_per_direction() {
+        let options = CaptureOptions{inspected_bytes: 8, ..Default::default()};
+        let mut classifier = FlowClassifier::default();
+        classifier.inspect("10.0.0.1 40000".to_string(), "TCP", "40000", "22", &[0xaa; 6], &options);
+        // Only the first 2 bytes of the banner fit in the window of the sender
+        classifier.inspect("10.0.0.1 40000".to_string(), "TCP", "40000", "22", b"SSH-2.0-OpenSSH_9.0\r\n", &options);
+        classifier.inspect("10.0.0.1 40000".to_string(), "TCP", "40000", "22", b"SSH-2.0-OpenSSH_9.0\r\n", &options);
+        assert_eq!(classifier.protocol(), None);
+        assert_eq!(classifier.inspected["10.0.0.1 40000"], 8);
+        // The other direction has its own window
+        classifier.inspect("10.0.0.2 22".to_string(), "TCP", "22", "40000", b"SSH-2.0-OpenSSH_9.0\r\n", &options);
+        assert_eq!(classifier.protocol(), Some("SSH"));
+        assert_eq!(classifier.inspected["10.0.0.2 22"], 8);
+    }
+}
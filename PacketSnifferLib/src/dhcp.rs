@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use crate::report::{mac_address_to_string, MacAddress};
+
+/// Port of the DHCPv4 server.
+pub const DHCPV4_SERVER_PORT: u16 = 67;
+/// Port of the DHCPv4 client.
+pub const DHCPV4_CLIENT_PORT: u16 = 68;
+/// Port of the DHCPv6 client.
+pub const DHCPV6_CLIENT_PORT: u16 = 546;
+/// Port of the DHCPv6 server.
+pub const DHCPV6_SERVER_PORT: u16 = 547;
+/// Magic cookie preceding the DHCPv4 options.
+const DHCPV4_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Maximum number of past leases remembered for a single device and address family.
+const MAX_LEASE_HISTORY: usize = 16;
+/// Lease time meaning that the lease never expires.
+const INFINITE_LEASE_TIME: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Default)]
+///Struct which reports info relative to a DHCPv4 or DHCPv6 message.
+pub struct DhcpInfo {
+    /// "DHCPv4" or "DHCPv6"
+    pub version: String,
+    /// Message type, e.g. "DISCOVER" or "ACK"
+    pub message_type: String,
+    /// Hardware address of the client
+    pub client_mac: Option<[u8; 6]>,
+    /// Address requested by the client
+    pub requested_ip: Option<String>,
+    /// Address assigned to the client
+    pub assigned_ip: Option<String>,
+    /// Hostname of the client
+    pub hostname: Option<String>,
+    /// Lease time in seconds
+    pub lease_time: Option<u32>,
+    /// Identifier of the server (its address for DHCPv4, its DUID for DHCPv6)
+    pub server_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+///Struct representing the address leased to a device.
+pub struct DhcpLease {
+    /// Hardware address of the device
+    pub mac: String,
+    /// "DHCPv4" or "DHCPv6"
+    pub version: String,
+    /// Leased address
+    pub ip: String,
+    /// Hostname announced by the device
+    pub hostname: Option<String>,
+    /// Identifier of the server that granted the lease
+    pub server_id: Option<String>,
+    /// Lease time in seconds
+    pub lease_time: Option<u32>,
+    /// Timestamp (in seconds) at which the lease was first granted
+    pub first_seen: u64,
+    /// Timestamp (in seconds) at which the lease was last granted or renewed
+    pub last_seen: u64,
+    /// True if the device released the address
+    pub released: bool,
+    /// True if the lease time elapsed since the lease was last granted or renewed
+    pub expired: bool,
+}
+
+#[derive(Debug, Default)]
+///Struct that keeps, for each device, the addresses leased to it over time and its hostname. DHCPv4 and DHCPv6 leases
+///are kept apart, so that a device has a current lease for each address family.
+pub struct LeaseTable {
+    /// Leases of each device and version of DHCP, the current one last
+    leases: HashMap<(String, String), Vec<DhcpLease>>,
+    /// Hostnames announced by the devices, also before they obtain a lease
+    hostnames: HashMap<String, String>,
+}
+
+/// Function that returns the name of a DHCPv4 message type.
+fn dhcpv4_message_type(t: u8) -> String {
+    match t {
+        1 => "DISCOVER".to_string(),
+        2 => "OFFER".to_string(),
+        3 => "REQUEST".to_string(),
+        4 => "DECLINE".to_string(),
+        5 => "ACK".to_string(),
+        6 => "NAK".to_string(),
+        7 => "RELEASE".to_string(),
+        8 => "INFORM".to_string(),
+        _ => format!("Unknown ({})", t)
+    }
+}
+
+/// Function that returns the name of a DHCPv6 message type.
+fn dhcpv6_message_type(t: u8) -> String {
+    match t {
+        1 => "SOLICIT".to_string(),
+        2 => "ADVERTISE".to_string(),
+        3 => "REQUEST".to_string(),
+        4 => "CONFIRM".to_string(),
+        5 => "RENEW".to_string(),
+        6 => "REBIND".to_string(),
+        7 => "REPLY".to_string(),
+        8 => "RELEASE".to_string(),
+        9 => "DECLINE".to_string(),
+        10 => "RECONFIGURE".to_string(),
+        11 => "INFORMATION-REQUEST".to_string(),
+        12 => "RELAY-FORW".to_string(),
+        13 => "RELAY-REPL".to_string(),
+        _ => format!("Unknown ({})", t)
+    }
+}
+
+/// Function that reads an IPv4 address from a slice of at least 4 bytes.
+fn ipv4(data: &[u8]) -> Option<Ipv4Addr> {
+    let b = data.get(0..4)?;
+    Some(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+}
+
+/// Function that parses a DHCPv4 message.
+pub fn parse_dhcpv4(payload: &[u8]) -> Option<DhcpInfo> {
+    if payload.len() < 240 || payload[236..240] != DHCPV4_MAGIC_COOKIE {
+        return None;
+    }
+    let mut info = DhcpInfo{version: "DHCPv4".to_string(), ..Default::default()};
+    // Ethernet hardware addresses only
+    if payload[1] == 1 && payload[2] == 6 {
+        let mut mac = [0u8; 6];
+        mac.copy_from_slice(&payload[28..34]);
+        info.client_mac = Some(mac);
+    }
+    let yiaddr = ipv4(&payload[16..20])?;
+    if !yiaddr.is_unspecified() {
+        info.assigned_ip = Some(yiaddr.to_string());
+    }
+    let mut pos = 240;
+    while pos < payload.len() {
+        let code = payload[pos];
+        if code == 0 {
+            pos += 1;
+            continue;
+        }
+        if code == 255 || pos + 1 >= payload.len() {
+            break;
+        }
+        let len = payload[pos + 1] as usize;
+        let data = match payload.get(pos + 2..pos + 2 + len) {
+            Some(d) => d,
+            None => break
+        };
+        match code {
+            53 if len >= 1 => info.message_type = dhcpv4_message_type(data[0]),
+            50 => info.requested_ip = ipv4(data).map(|a| a.to_string()),
+            51 if len >= 4 => info.lease_time = Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]])),
+            54 => info.server_id = ipv4(data).map(|a| a.to_string()),
+            12 => info.hostname = Some(String::from_utf8_lossy(data).to_string()),
+            _ => {}
+        }
+        pos += 2 + len;
+    }
+    if info.message_type.is_empty() {
+        info.message_type = "BOOTP".to_string();
+    }
+    Some(info)
+}
+
+/// Function that reads the hardware address of a DUID, when it contains an Ethernet address (DUID-LLT and DUID-LL).
+fn duid_mac(duid: &[u8]) -> Option<[u8; 6]> {
+    if duid.len() < 4 {
+        return None;
+    }
+    let duid_type = u16::from_be_bytes([duid[0], duid[1]]);
+    let hw_type = u16::from_be_bytes([duid[2], duid[3]]);
+    let address = match duid_type {
+        1 => duid.get(8..)?,
+        3 => duid.get(4..)?,
+        _ => return None
+    };
+    if hw_type != 1 || address.len() != 6 {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    mac.copy_from_slice(address);
+    Some(mac)
+}
+
+/// Function that decodes a domain name in DNS wire format, as used by the Client FQDN option.
+fn wire_domain_name(data: &[u8]) -> String {
+    let mut labels = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let len = data[pos] as usize;
+        if len == 0 || pos + 1 + len > data.len() {
+            break;
+        }
+        labels.push(String::from_utf8_lossy(&data[pos + 1..pos + 1 + len]).to_string());
+        pos += 1 + len;
+    }
+    labels.join(".")
+}
+
+/// Function that returns the options (code, data) of a DHCPv6 message or of an encapsulating option.
+fn dhcpv6_options(data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut options = Vec::new();
+    let mut pos = 0;
+    while pos + 4 <= data.len() {
+        let code = u16::from_be_bytes([data[pos], data[pos + 1]]);
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        match data.get(pos + 4..pos + 4 + len) {
+            Some(option) => options.push((code, option)),
+            None => break
+        }
+        pos += 4 + len;
+    }
+    options
+}
+
+/// Function that parses a DHCPv6 message. Relay messages are not decoded.
+pub fn parse_dhcpv6(payload: &[u8]) -> Option<DhcpInfo> {
+    if payload.len() < 4 {
+        return None;
+    }
+    let mut info = DhcpInfo{version: "DHCPv6".to_string(), message_type: dhcpv6_message_type(payload[0]), ..Default::default()};
+    if payload[0] == 12 || payload[0] == 13 {
+        return Some(info);
+    }
+    for (code, data) in dhcpv6_options(&payload[4..]) {
+        match code {
+            // Client identifier
+            1 => info.client_mac = duid_mac(data),
+            // Server identifier
+            2 => info.server_id = Some(hex::encode(data)),
+            // Identity association for non-temporary addresses
+            3 if data.len() >= 12 => {
+                for (sub_code, address) in dhcpv6_options(&data[12..]) {
+                    if sub_code == 5 && address.len() >= 24 {
+                        let mut bytes = [0u8; 16];
+                        bytes.copy_from_slice(&address[..16]);
+                        let ip = Ipv6Addr::from(bytes).to_string();
+                        let valid = u32::from_be_bytes([address[20], address[21], address[22], address[23]]);
+                        if payload[0] == 7 || payload[0] == 2 {
+                            info.assigned_ip = Some(ip);
+                            info.lease_time = Some(valid);
+                        } else {
+                            info.requested_ip = Some(ip);
+                        }
+                    }
+                }
+            },
+            // Client FQDN
+            39 if !data.is_empty() => info.hostname = Some(wire_domain_name(&data[1..])),
+            _ => {}
+        }
+    }
+    Some(info)
+}
+
+/// Function that parses a DHCP message carried by UDP, choosing DHCPv4 or DHCPv6 according to the ports.
+pub fn parse_dhcp(source_port: &str, destination_port: &str, payload: &[u8]) -> Option<DhcpInfo> {
+    let ports = (source_port.parse::<u16>().ok()?, destination_port.parse::<u16>().ok()?);
+    match ports {
+        (DHCPV4_CLIENT_PORT, DHCPV4_SERVER_PORT) | (DHCPV4_SERVER_PORT, DHCPV4_CLIENT_PORT) | (DHCPV4_SERVER_PORT, DHCPV4_SERVER_PORT) => parse_dhcpv4(payload),
+        (DHCPV6_CLIENT_PORT, DHCPV6_SERVER_PORT) | (DHCPV6_SERVER_PORT, DHCPV6_CLIENT_PORT) | (DHCPV6_SERVER_PORT, DHCPV6_SERVER_PORT) => parse_dhcpv6(payload),
+        _ => None
+    }
+}
+
+/// The function takes as parameter a `DhcpInfo` struct and generate a String containing its formatted informations.
+pub fn dhcp_info_to_string(info: &DhcpInfo) -> String {
+    let mut dhcp_string = "".to_owned();
+    dhcp_string.push_str(&format!("{} {}", info.version, info.message_type));
+    if let Some(mac) = info.client_mac {
+        dhcp_string.push_str("; Client MAC: ");
+        dhcp_string.push_str(&mac_address_to_string(MacAddress::new(mac)));
+    }
+    if let Some(ip) = &info.requested_ip {
+        dhcp_string.push_str("; Requested IP: ");
+        dhcp_string.push_str(ip);
+    }
+    if let Some(ip) = &info.assigned_ip {
+        dhcp_string.push_str("; Assigned IP: ");
+        dhcp_string.push_str(ip);
+    }
+    if let Some(hostname) = &info.hostname {
+        dhcp_string.push_str("; Hostname: ");
+        dhcp_string.push_str(hostname);
+    }
+    if let Some(lease_time) = info.lease_time {
+        dhcp_string.push_str(&format!("; Lease time: {} s", lease_time));
+    }
+    if let Some(server) = &info.server_id {
+        dhcp_string.push_str("; Server identifier: ");
+        dhcp_string.push_str(server);
+    }
+    dhcp_string
+}
+
+impl LeaseTable {
+    /// Create a new empty LeaseTable struct.
+    pub fn new() -> LeaseTable {
+        LeaseTable{leases: HashMap::new(), hostnames: HashMap::new()}
+    }
+
+    /// Registers a DHCP message observed at timestamp `ts` (in seconds). `link_mac` is the source address of the frame,
+    /// used when the message does not carry the address of the client.
+    pub fn register(&mut self, info: &DhcpInfo, link_mac: [u8; 6], ts: u64) {
+        let client_mac = info.client_mac.unwrap_or(link_mac);
+        let mac = mac_address_to_string(MacAddress::new(client_mac));
+        let key = (mac.clone(), info.version.clone());
+        if let Some(hostname) = &info.hostname {
+            if !hostname.is_empty() {
+                self.hostnames.insert(mac.clone(), hostname.clone());
+                for lease in self.leases.iter_mut().filter(|(key, _)| key.0 == mac).filter_map(|(_, h)| h.last_mut()) {
+                    lease.hostname = Some(hostname.clone());
+                }
+            }
+        }
+        let granted = match (info.version.as_str(), info.message_type.as_str()) {
+            ("DHCPv4", "ACK") | ("DHCPv6", "REPLY") => true,
+            ("DHCPv4", "RELEASE") | ("DHCPv6", "RELEASE") => {
+                if let Some(lease) = self.leases.get_mut(&key).and_then(|h| h.last_mut()) {
+                    lease.released = true;
+                    lease.last_seen = ts;
+                }
+                false
+            },
+            _ => false
+        };
+        let ip = match (&info.assigned_ip, granted) {
+            (Some(ip), true) => ip.clone(),
+            _ => return
+        };
+        let hostname = self.hostnames.get(&mac).cloned();
+        let history = self.leases.entry(key).or_default();
+        match history.last_mut() {
+            Some(lease) if lease.ip == ip => {
+                lease.last_seen = ts;
+                lease.lease_time = info.lease_time.or(lease.lease_time);
+                lease.server_id = info.server_id.clone().or(lease.server_id.take());
+                lease.released = false;
+                lease.expired = false;
+                if hostname.is_some() {
+                    lease.hostname = hostname;
+                }
+            },
+            _ => {
+                history.push(DhcpLease{mac, version: info.version.clone(), ip, hostname, server_id: info.server_id.clone(), lease_time: info.lease_time, first_seen: ts, last_seen: ts, released: false, expired: false});
+                if history.len() > MAX_LEASE_HISTORY {
+                    history.remove(0);
+                }
+            }
+        }
+    }
+
+    /// Marks as expired the leases whose lease time elapsed at timestamp `now` (in seconds) since they were last granted
+    /// or renewed. An expired lease becomes current again if the server renews it.
+    pub fn expire_leases(&mut self, now: u64) {
+        for lease in self.leases.values_mut().flat_map(|h| h.iter_mut()) {
+            if let Some(lease_time) = lease.lease_time {
+                if lease_time != INFINITE_LEASE_TIME && lease.last_seen + lease_time as u64 <= now {
+                    lease.expired = true;
+                }
+            }
+        }
+    }
+
+    /// Returns the current lease of each device, one for each version of DHCP.
+    pub fn current_leases(&self) -> Vec<DhcpLease> {
+        let mut leases: Vec<DhcpLease> = self.leases.values().filter_map(|h| h.last().cloned()).collect();
+        leases.sort_by(|a, b| a.mac.cmp(&b.mac).then(a.version.cmp(&b.version)));
+        leases
+    }
+
+    /// Returns all the leases granted to the device with hardware address `mac`, of both versions of DHCP, the oldest first.
+    pub fn history(&self, mac: &str) -> Vec<DhcpLease> {
+        let mac = mac.to_uppercase();
+        let mut history: Vec<DhcpLease> = self.leases.iter()
+            .filter(|(key, _)| key.0 == mac)
+            .flat_map(|(_, h)| h.iter().cloned())
+            .collect();
+        history.sort_by(|a, b| a.first_seen.cmp(&b.first_seen).then(a.version.cmp(&b.version)));
+        history
+    }
+
+    /// Returns the device that currently holds address `ip`, if it obtained it through DHCP and the lease is neither
+    /// released nor expired.
+    pub fn device_for_ip(&self, ip: &str) -> Option<DhcpLease> {
+        self.leases.values()
+            .filter_map(|h| h.last())
+            .filter(|l| l.ip == ip && !l.released && !l.expired)
+            .max_by_key(|l| l.last_seen)
+            .cloned()
+    }
+
+    /// Generates the DHCP section of a report, listing the current leases of each device.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----DHCP leases-----\n");
+        for lease in self.current_leases() {
+            s.push_str(&format!("{} -> {} ({}); Hostname: {}; Lease time: {}; Server: {}; First seen: {}; Last seen: {}{}\n",
+                                lease.mac,
+                                lease.ip,
+                                lease.version,
+                                lease.hostname.as_deref().unwrap_or("Unknown"),
+                                lease.lease_time.map(|t| format!("{} s", t)).unwrap_or_else(|| "Unknown".to_string()),
+                                lease.server_id.as_deref().unwrap_or("Unknown"),
+                                lease.first_seen,
+                                lease.last_seen,
+                                if lease.released { "; released" } else if lease.expired { "; expired" } else { "" }));
+        }
+        s.push('\n');
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+    fn ack(version: &str, ip: &str, lease_time: u32) -> DhcpInfo {
+        let message_type = if version == "DHCPv4" { "ACK" } else { "REPLY" };
+        DhcpInfo{version: version.to_string(), message_type: message_type.to_string(), client_mac: Some(MAC), assigned_ip: Some(ip.to_string()), lease_time: Some(lease_time), ..Default::default()}
+    }
+
+    #[test]
+    fn keeps_a_current_lease_per_family() {
+        let mut table = LeaseTable::new();
+        table.register(&ack("DHCPv4", "192.168.1.10", 3600), MAC, 10);
+        table.register(&ack("DHCPv6", "2001:db8::10", 3600), MAC, 20);
+        assert_eq!(table.current_leases().len(), 2);
+        assert_eq!(table.device_for_ip("192.168.1.10").unwrap().version, "DHCPv4");
+        assert_eq!(table.device_for_ip("2001:db8::10").unwrap().version, "DHCPv6");
+        assert_eq!(table.history("02:00:00:00:00:01").len(), 2);
+
+        let release = DhcpInfo{version: "DHCPv6".to_string(), message_type: "RELEASE".to_string(), client_mac: Some(MAC), ..Default::default()};
+        table.register(&release, MAC, 30);
+        assert!(table.device_for_ip("2001:db8::10").is_none());
+        assert!(table.device_for_ip("192.168.1.10").is_some());
+    }
+
+    #[test]
+    fn leases_expire_after_their_lease_time() {
+        let mut table = LeaseTable::new();
+        table.register(&ack("DHCPv4", "192.168.1.10", 100), MAC, 0);
+        let other = DhcpInfo{client_mac: Some([0x02, 0, 0, 0, 0, 0x02]), ..ack("DHCPv4", "192.168.1.11", INFINITE_LEASE_TIME)};
+        table.register(&other, MAC, 0);
+        table.expire_leases(99);
+        assert!(table.device_for_ip("192.168.1.10").is_some());
+        table.expire_leases(100);
+        assert!(table.device_for_ip("192.168.1.10").is_none());
+        assert!(table.device_for_ip("192.168.1.11").is_some());
+        // A renewal makes the lease current again
+        table.register(&ack("DHCPv4", "192.168.1.10", 100), MAC, 150);
+        assert!(table.device_for_ip("192.168.1.10").is_some());
+    }
+}
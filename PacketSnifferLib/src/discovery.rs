@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use etherparse::{LinkSlice, SlicedPacket};
+use crate::report::{mac_address_to_string, MacAddress};
+
+/// EtherType of LLDP.
+pub const LLDP_ETHER_TYPE: u16 = 0x88cc;
+/// Multicast destination address of CDP.
+const CDP_DESTINATION: [u8; 6] = [0x01, 0x00, 0x0c, 0xcc, 0xcc, 0xcc];
+/// LLC/SNAP header of CDP: DSAP, SSAP, control, Cisco OUI and protocol ID.
+const CDP_SNAP_HEADER: [u8; 8] = [0xaa, 0xaa, 0x03, 0x00, 0x00, 0x0c, 0x20, 0x00];
+/// Hold time in seconds of a neighbor that did not advertise one.
+const DEFAULT_HOLD_TIME_S: u64 = 180;
+/// Maximum number of TLVs read from a single frame.
+const MAX_TLVS: usize = 64;
+
+#[derive(Debug, Clone, Default)]
+///Struct which reports info relative to an LLDP or CDP advertisement.
+pub struct DiscoveryInfo {
+    /// Protocol, "LLDP" or "CDP"
+    pub protocol: String,
+    /// Chassis ID (LLDP) or Device ID (CDP)
+    pub chassis_id: String,
+    /// Port ID of the neighbor's port connected to the link
+    pub port_id: String,
+    /// Description of the port
+    pub port_description: Option<String>,
+    /// System name
+    pub system_name: Option<String>,
+    /// System description (LLDP) or platform (CDP)
+    pub system_description: Option<String>,
+    /// Port VLAN ID (LLDP) or native VLAN (CDP)
+    pub vlan: Option<u16>,
+    /// Management addresses
+    pub management_addresses: Vec<String>,
+    /// Time in seconds the advertisement is valid for
+    pub hold_time: Option<u16>,
+}
+
+#[derive(Debug, Clone)]
+///Struct representing a switch or other device that advertised itself through LLDP or CDP.
+pub struct DiscoveryNeighbor {
+    /// Last advertisement received from the neighbor
+    pub info: DiscoveryInfo,
+    /// Source MAC address of the advertisements
+    pub mac: String,
+    /// Timestamp (in seconds) of the first advertisement
+    pub first_seen: u64,
+    /// Timestamp (in seconds) of the last advertisement
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Default)]
+///Struct that keeps the neighbors learned from LLDP and CDP until their hold time expires.
+pub struct DiscoveryTable {
+    /// Neighbors by protocol, chassis ID and port ID
+    neighbors: HashMap<(String, String, String), DiscoveryNeighbor>,
+}
+
+/// Function that converts a text TLV into a String, removing the trailing NUL bytes.
+fn text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches('\0').trim().to_string()
+}
+
+/// Function that formats an address given its IANA address family: 1 for IPv4, 2 for IPv6, 6 for 802 MAC addresses.
+fn network_address(family: u8, address: &[u8]) -> Option<String> {
+    match (family, address.len()) {
+        (1, 4) => Some(Ipv4Addr::new(address[0], address[1], address[2], address[3]).to_string()),
+        (2, 16) => Some(Ipv6Addr::from(<[u8; 16]>::try_from(address).ok()?).to_string()),
+        (6, 6) => Some(mac_address_to_string(MacAddress::new(address.try_into().ok()?))),
+        _ => None
+    }
+}
+
+/// Function that formats the value of a Chassis ID or Port ID TLV, given the subtype that identifies a MAC or network
+/// address (`mac_subtype` and `address_subtype`).
+fn lldp_id(data: &[u8], mac_subtype: u8, address_subtype: u8) -> String {
+    let (subtype, value) = match data.split_first() {
+        Some((subtype, value)) => (*subtype, value),
+        None => return "".to_string()
+    };
+    if subtype == mac_subtype && value.len() == 6 {
+        return network_address(6, value).unwrap_or_default();
+    }
+    if subtype == address_subtype && !value.is_empty() {
+        if let Some(address) = network_address(value[0], &value[1..]) {
+            return address;
+        }
+    }
+    text(value)
+}
+
+/// Function that parses the TLVs of an LLDPDU.
+pub fn parse_lldp(mut payload: &[u8]) -> Option<DiscoveryInfo> {
+    let mut info = DiscoveryInfo{protocol: "LLDP".to_string(), ..Default::default()};
+    for _ in 0..MAX_TLVS {
+        if payload.len() < 2 {
+            break;
+        }
+        let header = u16::from_be_bytes([payload[0], payload[1]]);
+        let tlv_type = (header >> 9) as u8;
+        let value = match payload.get(2..2 + (header & 0x01ff) as usize) {
+            Some(value) => value,
+            None => break
+        };
+        match tlv_type {
+            0 => break,
+            1 => info.chassis_id = lldp_id(value, 4, 5),
+            2 => info.port_id = lldp_id(value, 3, 4),
+            3 if value.len() >= 2 => info.hold_time = Some(u16::from_be_bytes([value[0], value[1]])),
+            4 => info.port_description = Some(text(value)),
+            5 => info.system_name = Some(text(value)),
+            6 => info.system_description = Some(text(value)),
+            8 if value.len() >= 2 => {
+                // The address string length includes the address family
+                let length = value[0] as usize;
+                if let Some(address) = value.get(2..1 + length).and_then(|a| network_address(value[1], a)) {
+                    info.management_addresses.push(address);
+                }
+            },
+            // IEEE 802.1 organizationally specific TLV, subtype 1 is the Port VLAN ID
+            127 if value.len() >= 6 && value[0..4] == [0x00, 0x80, 0xc2, 0x01] => {
+                info.vlan = Some(u16::from_be_bytes([value[4], value[5]]));
+            },
+            _ => {}
+        }
+        payload = &payload[2 + value.len()..];
+    }
+    // Chassis ID and Port ID are mandatory
+    if info.chassis_id.is_empty() || info.port_id.is_empty() {
+        return None;
+    }
+    Some(info)
+}
+
+/// Function that reads the addresses of a CDP Addresses or Management Addresses TLV.
+fn cdp_addresses(value: &[u8]) -> Vec<String> {
+    let mut addresses = Vec::new();
+    if value.len() < 4 {
+        return addresses;
+    }
+    let count = u32::from_be_bytes([value[0], value[1], value[2], value[3]]) as usize;
+    let mut records = &value[4..];
+    for _ in 0..count.min(MAX_TLVS) {
+        if records.len() < 2 {
+            break;
+        }
+        let protocol_len = records[1] as usize;
+        let address_start = 2 + protocol_len + 2;
+        if records.len() < address_start {
+            break;
+        }
+        let address_len = u16::from_be_bytes([records[address_start - 2], records[address_start - 1]]) as usize;
+        let address = match records.get(address_start..address_start + address_len) {
+            Some(address) => address,
+            None => break
+        };
+        // NLPID 0xcc is IPv4, an 802.2 protocol ending with EtherType 0x86dd is IPv6
+        let protocol = &records[2..2 + protocol_len];
+        let family = match (records[0], protocol) {
+            (1, [0xcc]) => 1,
+            (2, [.., 0x86, 0xdd]) => 2,
+            _ => 0
+        };
+        if let Some(address) = network_address(family, address) {
+            addresses.push(address);
+        }
+        records = &records[address_start + address_len..];
+    }
+    addresses
+}
+
+/// Function that parses a CDP packet, following its LLC/SNAP header.
+pub fn parse_cdp(payload: &[u8]) -> Option<DiscoveryInfo> {
+    if payload.len() < 12 || payload[0..8] != CDP_SNAP_HEADER {
+        return None;
+    }
+    let mut info = DiscoveryInfo{protocol: "CDP".to_string(), hold_time: Some(payload[9] as u16), ..Default::default()};
+    let mut tlvs = &payload[12..];
+    for _ in 0..MAX_TLVS {
+        if tlvs.len() < 4 {
+            break;
+        }
+        let tlv_type = u16::from_be_bytes([tlvs[0], tlvs[1]]);
+        // The length includes the type and length fields
+        let length = u16::from_be_bytes([tlvs[2], tlvs[3]]) as usize;
+        if length < 4 {
+            break;
+        }
+        let value = match tlvs.get(4..length) {
+            Some(value) => value,
+            None => break
+        };
+        match tlv_type {
+            0x0001 => info.chassis_id = text(value),
+            0x0002 => {
+                for address in cdp_addresses(value) {
+                    if !info.management_addresses.contains(&address) {
+                        info.management_addresses.push(address);
+                    }
+                }
+            },
+            0x0003 => info.port_id = text(value),
+            0x0006 => info.system_description = Some(text(value)),
+            0x000a if value.len() >= 2 => info.vlan = Some(u16::from_be_bytes([value[0], value[1]])),
+            0x0016 => {
+                for address in cdp_addresses(value) {
+                    if !info.management_addresses.contains(&address) {
+                        info.management_addresses.push(address);
+                    }
+                }
+            },
+            _ => {}
+        }
+        tlvs = &tlvs[length..];
+    }
+    if info.chassis_id.is_empty() {
+        return None;
+    }
+    // CDP has no separate system name, the Device ID is the hostname of the neighbor
+    info.system_name = Some(info.chassis_id.clone());
+    Some(info)
+}
+
+/// Function that recognizes LLDP frames, by their EtherType, and CDP frames, by their destination address, and decodes them.
+pub fn parse_discovery(packet: &SlicedPacket) -> Option<DiscoveryInfo> {
+    let header = match &packet.link {
+        Some(LinkSlice::Ethernet2(header)) => header,
+        _ => return None
+    };
+    if packet.ip.is_some() {
+        return None;
+    }
+    if header.ether_type() == LLDP_ETHER_TYPE {
+        return parse_lldp(packet.payload);
+    }
+    // CDP uses 802.3 frames, whose EtherType field holds the length of the frame
+    if header.destination() == CDP_DESTINATION && header.ether_type() < 0x0600 {
+        return parse_cdp(packet.payload);
+    }
+    None
+}
+
+/// The function takes as parameter a `DiscoveryInfo` struct and generate a String containing its formatted informations.
+pub fn discovery_info_to_string(info: &DiscoveryInfo) -> String {
+    let mut discovery_string = format!("{} Chassis: {}; Port: {}", info.protocol, info.chassis_id, info.port_id);
+    if let Some(description) = &info.port_description {
+        discovery_string.push_str(&format!(" ({})", description));
+    }
+    if let Some(name) = &info.system_name {
+        discovery_string.push_str(&format!("; System name: {}", name));
+    }
+    if let Some(description) = &info.system_description {
+        discovery_string.push_str(&format!("; System description: {}", description.replace('\n', " ")));
+    }
+    if let Some(vlan) = info.vlan {
+        discovery_string.push_str(&format!("; VLAN: {}", vlan));
+    }
+    if !info.management_addresses.is_empty() {
+        discovery_string.push_str("; Management addresses: ");
+        discovery_string.push_str(info.management_addresses.join(", ").as_str());
+    }
+    discovery_string
+}
+
+impl DiscoveryTable {
+    /// Create a new empty DiscoveryTable struct.
+    pub fn new() -> DiscoveryTable {
+        DiscoveryTable{neighbors: HashMap::new()}
+    }
+
+    /// Registers an advertisement sent by the device with link-layer address `mac` at timestamp `ts` (in seconds).
+    pub fn register(&mut self, info: DiscoveryInfo, mac: [u8; 6], ts: u64) {
+        let key = (info.protocol.clone(), info.chassis_id.clone(), info.port_id.clone());
+        let mac = mac_address_to_string(MacAddress::new(mac));
+        let neighbor = self.neighbors.entry(key).or_insert_with(|| DiscoveryNeighbor{info: info.clone(), mac: mac.clone(), first_seen: ts, last_seen: ts});
+        neighbor.info = info;
+        neighbor.mac = mac;
+        neighbor.last_seen = ts;
+    }
+
+    /// Removes, at timestamp `now` (in seconds), the neighbors whose hold time expired without a new advertisement.
+    pub fn purge_expired(&mut self, now: u64) {
+        self.neighbors.retain(|_, n| {
+            let hold_time = n.info.hold_time.map(|h| h as u64).unwrap_or(DEFAULT_HOLD_TIME_S);
+            n.last_seen + hold_time >= now
+        });
+    }
+
+    /// Returns the known neighbors, sorted by protocol, chassis ID and port ID.
+    pub fn neighbors(&self) -> Vec<DiscoveryNeighbor> {
+        let mut keys: Vec<&(String, String, String)> = self.neighbors.keys().collect();
+        keys.sort();
+        keys.into_iter().map(|k| self.neighbors[k].clone()).collect()
+    }
+
+    /// Generates the neighbors section of a report, listing each LLDP or CDP neighbor with the port it advertised.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----LLDP/CDP neighbors-----\n");
+        for neighbor in self.neighbors() {
+            s.push_str(&format!("{}; MAC: {}; First seen: {}; Last seen: {}\n",
+                                discovery_info_to_string(&neighbor.info),
+                                neighbor.mac,
+                                neighbor.first_seen,
+                                neighbor.last_seen));
+        }
+        s.push('\n');
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SWITCH: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+
+    /// Returns an LLDP TLV with the 7-bit type `tlv_type` and `value`.
+    fn lldp_tlv(tlv_type: u8, value: &[u8]) -> Vec<u8> {
+        let mut tlv = (((tlv_type as u16) << 9) | value.len() as u16).to_be_bytes().to_vec();
+        tlv.extend_from_slice(value);
+        tlv
+    }
+
+    /// Returns a CDP TLV with `tlv_type` and `value`, whose length includes the type and length fields.
+    fn cdp_tlv(tlv_type: u16, value: &[u8]) -> Vec<u8> {
+        let mut tlv = tlv_type.to_be_bytes().to_vec();
+        tlv.extend_from_slice(&(4 + value.len() as u16).to_be_bytes());
+        tlv.extend_from_slice(value);
+        tlv
+    }
+
+    /// Returns a CDP packet with the hold time `hold_time`, following its LLC/SNAP header, made of `tlvs`.
+    fn cdp(hold_time: u8, tlvs: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = CDP_SNAP_HEADER.to_vec();
+        packet.extend_from_slice(&[2, hold_time, 0, 0]);
+        for tlv in tlvs {
+            packet.extend_from_slice(tlv);
+        }
+        packet
+    }
+
+    /// Returns the value of a CDP Addresses TLV holding an IPv4 and an IPv6 address.
+    fn cdp_address_records() -> Vec<u8> {
+        let mut value = 2u32.to_be_bytes().to_vec();
+        value.extend_from_slice(&[1, 1, 0xcc, 0, 4, 192, 0, 2, 1]);
+        value.extend_from_slice(&[2, 8, 0xaa, 0xaa, 0x03, 0, 0, 0, 0x86, 0xdd, 0, 16]);
+        value.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        value
+    }
+
+    #[test]
+    fn lldp_ids_with_mac_and_network_address_subtypes() {
+        let mut frame = lldp_tlv(1, &[4, 0x02, 0, 0, 0, 0, 0x01]);
+        frame.extend(lldp_tlv(2, &[4, 1, 192, 0, 2, 7]));
+        frame.extend(lldp_tlv(3, &[0, 120]));
+        frame.extend(lldp_tlv(0, &[]));
+        let info = parse_lldp(&frame).unwrap();
+        assert_eq!(info.chassis_id, "02:00:00:00:00:01");
+        assert_eq!(info.port_id, "192.0.2.7");
+        assert_eq!(info.hold_time, Some(120));
+        // Interface name subtype, and a network address of an unknown family read as text
+        let mut frame = lldp_tlv(1, &[5, b'x', b'-', b'1']);
+        frame.extend(lldp_tlv(2, b"\x05Gi0/1\0"));
+        let info = parse_lldp(&frame).unwrap();
+        assert_eq!((info.chassis_id.as_str(), info.port_id.as_str()), ("x-1", "Gi0/1"));
+    }
+
+    #[test]
+    fn lldp_management_address_and_vlan() {
+        let mut frame = lldp_tlv(1, &[7, b's', b'w', b'1']);
+        frame.extend(lldp_tlv(2, &[7, b'1']));
+        frame.extend(lldp_tlv(5, b"sw1.example.com"));
+        // Address string length, family, address, interface numbering subtype, interface number and OID length
+        frame.extend(lldp_tlv(8, &[5, 1, 192, 0, 2, 1, 2, 0, 0, 0, 3, 0]));
+        let mut ipv6 = vec![17, 2];
+        ipv6.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        ipv6.extend_from_slice(&[2, 0, 0, 0, 3, 0]);
+        frame.extend(lldp_tlv(8, &ipv6));
+        frame.extend(lldp_tlv(127, &[0x00, 0x80, 0xc2, 0x01, 0x00, 0x64]));
+        // IEEE 802.3 organizationally specific TLV, ignored
+        frame.extend(lldp_tlv(127, &[0x00, 0x12, 0x0f, 0x01, 0x03, 0x6c, 0x00, 0x00, 0x10]));
+        let info = parse_lldp(&frame).unwrap();
+        assert_eq!(info.management_addresses, vec!["192.0.2.1", "2001:db8::1"]);
+        assert_eq!(info.vlan, Some(100));
+        assert_eq!(discovery_info_to_string(&info), "LLDP Chassis: sw1; Port: 1; System name: sw1.example.com; VLAN: 100; Management addresses: 192.0.2.1, 2001:db8::1");
+    }
+
+    #[test]
+    fn lldp_requires_chassis_and_port() {
+        assert!(parse_lldp(&lldp_tlv(1, &[7, b's', b'w'])).is_none());
+        // A TLV longer than the frame stops the parsing
+        let mut frame = lldp_tlv(1, &[7, b's', b'w']);
+        frame.extend_from_slice(&[0x04, 0x10, 7]);
+        assert!(parse_lldp(&frame).is_none());
+    }
+
+    #[test]
+    fn cdp_addresses_of_both_families() {
+        assert_eq!(cdp_addresses(&cdp_address_records()), vec!["192.0.2.1", "2001:db8::1"]);
+        // The count is larger than the records present
+        let mut value = cdp_address_records();
+        value[3] = 5;
+        assert_eq!(cdp_addresses(&value).len(), 2);
+        assert!(cdp_addresses(&[0, 0, 0]).is_empty());
+    }
+
+    #[test]
+    fn parses_cdp_packets() {
+        let packet = cdp(180, &[
+            cdp_tlv(0x0001, b"router1"),
+            cdp_tlv(0x0002, &cdp_address_records()),
+            cdp_tlv(0x0003, b"GigabitEthernet0/1"),
+            cdp_tlv(0x0006, b"cisco C9300"),
+            cdp_tlv(0x000a, &[0, 10]),
+            cdp_tlv(0x0016, &cdp_address_records()),
+        ]);
+        let info = parse_cdp(&packet).unwrap();
+        assert_eq!(info.hold_time, Some(180));
+        assert_eq!((info.chassis_id.as_str(), info.port_id.as_str()), ("router1", "GigabitEthernet0/1"));
+        assert_eq!(info.system_name.as_deref(), Some("router1"));
+        assert_eq!(info.system_description.as_deref(), Some("cisco C9300"));
+        assert_eq!(info.vlan, Some(10));
+        assert_eq!(info.management_addresses, vec!["192.0.2.1", "2001:db8::1"]);
+        assert!(parse_cdp(&cdp(180, &[cdp_tlv(0x0003, b"Gi0/1")])).is_none());
+        assert!(parse_cdp(&packet[1..]).is_none());
+    }
+
+    #[test]
+    fn neighbors_expire_after_their_hold_time() {
+        let mut table = DiscoveryTable::new();
+        let lldp = DiscoveryInfo{protocol: "LLDP".to_string(), chassis_id: "sw1".to_string(), port_id: "1".to_string(), hold_time: Some(120), ..Default::default()};
+        let without_hold_time = DiscoveryInfo{chassis_id: "sw2".to_string(), hold_time: None, ..lldp.clone()};
+        table.register(lldp.clone(), SWITCH, 0);
+        table.register(without_hold_time, SWITCH, 0);
+        table.purge_expired(120);
+        assert_eq!(table.neighbors().len(), 2);
+        table.purge_expired(121);
+        assert_eq!(table.neighbors()[0].info.chassis_id, "sw2");
+        table.purge_expired(DEFAULT_HOLD_TIME_S + 1);
+        assert!(table.neighbors().is_empty());
+        // A new advertisement renews the hold time and keeps the first timestamp
+        table.register(lldp.clone(), SWITCH, 200);
+        table.register(lldp, SWITCH, 300);
+        table.purge_expired(420);
+        let neighbor = &table.neighbors()[0];
+        assert_eq!((neighbor.first_seen, neighbor.last_seen, neighbor.mac.as_str()), (200, 300, "02:00:00:00:00:01"));
+    }
+}
@@ -0,0 +1,670 @@
+use std::collections::{HashMap, HashSet};
+use crate::report::{DnsAnswerData, DnsInfo, LLMNR_PORT, MDNS_PORT};
+use crate::stream::TcpStream;
+
+/// Maximum number of hostnames remembered for a single address.
+const MAX_HOSTNAMES_PER_ADDRESS: usize = 8;
+/// Maximum number of addresses in the passive DNS cache.
+const MAX_PASSIVE_DNS_ADDRESSES: usize = 16384;
+/// Longest TTL (in seconds) a passive DNS record is kept for, one week.
+const MAX_PASSIVE_DNS_TTL: u64 = 7 * 24 * 3600;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Struct identifying a DNS transaction: a query and its response share the same key.
+pub struct DnsTransactionKey {
+    /// Address of the host that sent the query
+    pub client: String,
+    /// Address of the host that answered the query
+    pub server: String,
+    /// Dns packet header Id
+    pub id: u16,
+    /// Questions of the message, formatted as "name type"
+    pub question: String,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Struct containing the resolution latencies observed for a domain name.
+pub struct DnsLatencyStats {
+    /// Number of answered queries
+    pub answers: u32,
+    /// Sum of the latencies, in microseconds
+    pub total_us: u64,
+    /// Highest latency, in microseconds
+    pub max_us: u64,
+}
+
+impl DnsLatencyStats {
+    /// Average latency in microseconds.
+    pub fn average_us(&self) -> u64 {
+        if self.answers == 0 {
+            return 0;
+        }
+        self.total_us / self.answers as u64
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+/// Struct counting the failed resolutions of a client or of a domain name.
+pub struct DnsFailureStats {
+    /// Responses with code NXDOMAIN
+    pub nxdomain: u32,
+    /// Responses with code SERVFAIL
+    pub servfail: u32,
+    /// Queries that did not receive a response before the timeout
+    pub unanswered: u32,
+}
+
+impl DnsFailureStats {
+    /// Total number of failures.
+    pub fn total(&self) -> u32 {
+        self.nxdomain + self.servfail + self.unanswered
+    }
+}
+
+#[derive(Debug)]
+/// Struct that pairs DNS queries with their responses, measuring the resolution latency and counting the failures.
+/// Statistics refer to the current report interval, while the pending queries are kept until answered or expired.
+/// The mDNS and LLMNR messages are not registered: their queries are sent to a multicast group and can be answered by
+/// any host, so they cannot be paired.
+pub struct DnsTracker {
+    /// Queries waiting for a response, with the timestamp (in microseconds) at which they were sent
+    pending: HashMap<DnsTransactionKey, u64>,
+    /// Time (in milliseconds) after which a query without response is considered unanswered
+    timeout_ms: u64,
+    /// Latencies for each domain name
+    latencies: HashMap<String, DnsLatencyStats>,
+    /// Failures for each domain name
+    domain_failures: HashMap<String, DnsFailureStats>,
+    /// Failures for each client
+    client_failures: HashMap<String, DnsFailureStats>,
+    /// Responses that did not match any query
+    unmatched_responses: u32,
+    /// Messages over TCP that could not be decoded because their segments were lost or were not all captured
+    truncated_tcp_messages: u32,
+}
+
+/// Function that builds the question part of a transaction key, formatting each question as "name type".
+pub fn dns_question_key(info: &DnsInfo) -> String {
+    let mut questions = Vec::new();
+    for (i, name) in info.queries.iter().enumerate() {
+        match info.query_type.get(i) {
+            Some(qtype) => questions.push(format!("{} {:?}", name, qtype)),
+            None => questions.push(name.clone())
+        }
+    }
+    questions.join(", ")
+}
+
+impl DnsTracker {
+    /// Create a new DnsTracker struct. `timeout_ms` is the time after which a query without response is counted as unanswered.
+    pub fn new(timeout_ms: u64) -> DnsTracker {
+        DnsTracker{pending: HashMap::new(), timeout_ms, latencies: HashMap::new(), domain_failures: HashMap::new(), client_failures: HashMap::new(), unmatched_responses: 0, truncated_tcp_messages: 0}
+    }
+
+    /// Registers a DNS message sent from `source` to `destination` at timestamp `ts_us` (in microseconds).
+    /// Queries are kept pending, responses are matched with the pending query of the same transaction.
+    pub fn register(&mut self, source: &str, destination: &str, info: &DnsInfo, ts_us: u64) {
+        let domain = info.queries.first().cloned().unwrap_or_default();
+        if !info.is_response {
+            let key = DnsTransactionKey{client: source.to_string(), server: destination.to_string(), id: info.id, question: dns_question_key(info)};
+            self.pending.insert(key, ts_us);
+            return;
+        }
+        let key = DnsTransactionKey{client: destination.to_string(), server: source.to_string(), id: info.id, question: dns_question_key(info)};
+        match self.pending.remove(&key) {
+            Some(query_ts) => {
+                let latency = ts_us.saturating_sub(query_ts);
+                let stats = self.latencies.entry(domain.clone()).or_default();
+                stats.answers += 1;
+                stats.total_us += latency;
+                if latency > stats.max_us {
+                    stats.max_us = latency;
+                }
+            },
+            None => self.unmatched_responses += 1
+        }
+        let (nxdomain, servfail) = match info.response_code {
+            simple_dns::RCODE::NameError => (1, 0),
+            simple_dns::RCODE::ServerFailure => (0, 1),
+            _ => return
+        };
+        for stats in [self.domain_failures.entry(domain).or_default(), self.client_failures.entry(destination.to_string()).or_default()] {
+            stats.nxdomain += nxdomain;
+            stats.servfail += servfail;
+        }
+    }
+
+    /// Counts as unanswered the pending queries older than the timeout at timestamp `now_us` (in microseconds), the one
+    /// of the newest captured packet.
+    pub fn expire_pending(&mut self, now_us: u64) {
+        let deadline = now_us.saturating_sub(self.timeout_ms.saturating_mul(1000));
+        let expired: Vec<DnsTransactionKey> = self.pending.iter()
+            .filter(|(_, ts)| **ts < deadline)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.pending.remove(&key);
+            let domain = key.question.split(' ').next().unwrap_or("").to_string();
+            self.domain_failures.entry(domain).or_default().unanswered += 1;
+            self.client_failures.entry(key.client).or_default().unanswered += 1;
+        }
+    }
+
+    /// Counts `count` messages over TCP that could not be decoded.
+    pub fn register_truncated(&mut self, count: u32) {
+        self.truncated_tcp_messages += count;
+    }
+
+    /// Latencies observed in the current interval for each domain name.
+    pub fn latencies(&self) -> &HashMap<String, DnsLatencyStats> {
+        &self.latencies
+    }
+
+    /// Failures observed in the current interval for each domain name.
+    pub fn domain_failures(&self) -> &HashMap<String, DnsFailureStats> {
+        &self.domain_failures
+    }
+
+    /// Failures observed in the current interval for each client.
+    pub fn client_failures(&self) -> &HashMap<String, DnsFailureStats> {
+        &self.client_failures
+    }
+
+    /// Clears the statistics of the current interval, keeping the pending queries.
+    pub fn reset_stats(&mut self) {
+        self.latencies.clear();
+        self.domain_failures.clear();
+        self.client_failures.clear();
+        self.unmatched_responses = 0;
+        self.truncated_tcp_messages = 0;
+    }
+
+    /// Generates the DNS section of a report, listing the `top_n` slowest and most failing names and clients.
+    pub fn to_report_string(&self, top_n: usize) -> String {
+        let mut s = "".to_owned();
+        let answered: u32 = self.latencies.values().map(|l| l.answers).sum();
+        let unanswered: u32 = self.domain_failures.values().map(|f| f.unanswered).sum();
+        s.push_str("-----DNS transactions-----\n");
+        s.push_str(&format!("Answered queries: {}; Unanswered queries: {}; Pending queries: {}; Unmatched responses: {}; Truncated TCP messages: {}\n",
+                            answered, unanswered, self.pending.len(), self.unmatched_responses, self.truncated_tcp_messages));
+
+        let mut slowest: Vec<(&String, &DnsLatencyStats)> = self.latencies.iter().collect();
+        slowest.sort_by(|a, b| b.1.max_us.cmp(&a.1.max_us).then(a.0.cmp(b.0)));
+        s.push_str("Slowest names:\n");
+        for (name, stats) in slowest.into_iter().take(top_n) {
+            s.push_str(&format!("{}: max {:.3} ms, avg {:.3} ms, answers: {}\n",
+                                name, stats.max_us as f64 / 1000.0, stats.average_us() as f64 / 1000.0, stats.answers));
+        }
+
+        s.push_str("Most failing names:\n");
+        s.push_str(&failures_to_string(&self.domain_failures, top_n));
+        s.push_str("Failures per client:\n");
+        s.push_str(&failures_to_string(&self.client_failures, top_n));
+        s.push('\n');
+        s
+    }
+}
+
+/// Function that formats the `top_n` entries with most failures of a map.
+fn failures_to_string(failures: &HashMap<String, DnsFailureStats>, top_n: usize) -> String {
+    let mut s = "".to_owned();
+    let mut sorted: Vec<(&String, &DnsFailureStats)> = failures.iter().filter(|(_, f)| f.total() > 0).collect();
+    sorted.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then(a.0.cmp(b.0)));
+    for (name, stats) in sorted.into_iter().take(top_n) {
+        s.push_str(&format!("{}: NXDOMAIN: {}, SERVFAIL: {}, unanswered: {}\n", name, stats.nxdomain, stats.servfail, stats.unanswered));
+    }
+    s
+}
+
+#[derive(Debug, Clone)]
+/// Struct representing a hostname learned for an address from an observed DNS answer.
+pub struct PassiveDnsEntry {
+    /// Hostname resolving to the address, either directly or through a CNAME chain
+    pub hostname: String,
+    /// Timestamp (in seconds) of the last answer containing the hostname
+    pub last_seen: u64,
+    /// Timestamp (in seconds) after which the record is expired, according to its TTL
+    pub expires: u64,
+}
+
+#[derive(Debug, Default)]
+/// Struct that maps the addresses to the hostnames learned from the A, AAAA and CNAME answers seen on the network.
+/// Contrary to the reports, the cache is kept for the whole capture and its entries expire according to their TTL, capped
+/// at one week. At most `MAX_PASSIVE_DNS_ADDRESSES` addresses are kept: when a new one does not fit, a tenth of them is
+/// dropped, the expired ones first and then the least recently seen.
+pub struct PassiveDnsCache {
+    /// Hostnames learned for each address
+    entries: HashMap<String, Vec<PassiveDnsEntry>>,
+}
+
+/// Function that normalizes a domain name, removing the trailing dot and converting it to lowercase.
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+impl PassiveDnsCache {
+    /// Create a new empty PassiveDnsCache struct.
+    pub fn new() -> PassiveDnsCache {
+        PassiveDnsCache{entries: HashMap::new()}
+    }
+
+    /// Registers the answers of a DNS response observed at timestamp `ts` (in seconds).
+    /// An address is associated to the name of its record and to all the names that point to it through the CNAME records of the same response.
+    pub fn register(&mut self, info: &DnsInfo, ts: u64) {
+        if !info.is_response {
+            return;
+        }
+        let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+        for answer in &info.answers {
+            if let DnsAnswerData::CNAME(target) = &answer.data {
+                aliases.entry(normalize_name(target)).or_default().push(normalize_name(&answer.name));
+            }
+        }
+        for answer in &info.answers {
+            let address = match &answer.data {
+                DnsAnswerData::A(address) => address.to_string(),
+                DnsAnswerData::AAAA(address) => address.to_string(),
+                _ => continue
+            };
+            let expires = ts + (answer.ttl as u64).min(MAX_PASSIVE_DNS_TTL);
+            for hostname in names_with_aliases(&normalize_name(&answer.name), &aliases) {
+                self.insert(&address, hostname, ts, expires);
+            }
+        }
+    }
+
+    /// Inserts or refreshes a hostname for an address, dropping the least recently seen one when the address has too many.
+    fn insert(&mut self, address: &str, hostname: String, ts: u64, expires: u64) {
+        if self.entries.len() >= MAX_PASSIVE_DNS_ADDRESSES && !self.entries.contains_key(address) {
+            self.evict(ts);
+        }
+        let hostnames = self.entries.entry(address.to_string()).or_default();
+        match hostnames.iter_mut().find(|e| e.hostname == hostname) {
+            Some(entry) => {
+                entry.last_seen = ts;
+                entry.expires = expires;
+            },
+            None => hostnames.push(PassiveDnsEntry{hostname, last_seen: ts, expires})
+        }
+        if hostnames.len() > MAX_HOSTNAMES_PER_ADDRESS {
+            hostnames.sort_by_key(|e| std::cmp::Reverse(e.last_seen));
+            hostnames.truncate(MAX_HOSTNAMES_PER_ADDRESS);
+        }
+    }
+
+    /// Drops a tenth of the addresses to make room for a new one at timestamp `ts` (in seconds): the ones whose entries are
+    /// all expired first, then the ones seen least recently.
+    fn evict(&mut self, ts: u64) {
+        let mut candidates: Vec<(bool, u64, String)> = self.entries.iter().map(|(address, hostnames)| {
+            let valid = hostnames.iter().any(|e| e.expires >= ts);
+            let last_seen = hostnames.iter().map(|e| e.last_seen).max().unwrap_or(0);
+            (valid, last_seen, address.clone())
+        }).collect();
+        candidates.sort_unstable();
+        for (_, _, address) in candidates.into_iter().take(MAX_PASSIVE_DNS_ADDRESSES / 10) {
+            self.entries.remove(&address);
+        }
+    }
+
+    /// Returns the hostnames of `address` that are valid at timestamp `ts` (in seconds), the most recent first.
+    pub fn lookup(&self, address: &str, ts: u64) -> Vec<String> {
+        let mut valid: Vec<&PassiveDnsEntry> = match self.entries.get(address) {
+            Some(hostnames) => hostnames.iter().filter(|e| e.expires >= ts).collect(),
+            None => return Vec::new()
+        };
+        valid.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then(a.hostname.cmp(&b.hostname)));
+        valid.into_iter().map(|e| e.hostname.clone()).collect()
+    }
+
+    /// Returns the addresses that `hostname` resolves to at timestamp `ts` (in seconds).
+    pub fn lookup_addresses(&self, hostname: &str, ts: u64) -> Vec<String> {
+        let hostname = normalize_name(hostname);
+        let mut addresses: Vec<String> = self.entries.iter()
+            .filter(|(_, hostnames)| hostnames.iter().any(|e| e.hostname == hostname && e.expires >= ts))
+            .map(|(address, _)| address.clone())
+            .collect();
+        addresses.sort();
+        addresses
+    }
+
+    /// Returns all the entries of the cache, grouped by address.
+    pub fn entries(&self) -> &HashMap<String, Vec<PassiveDnsEntry>> {
+        &self.entries
+    }
+
+    /// Removes the entries expired at timestamp `now` (in seconds).
+    pub fn purge_expired(&mut self, now: u64) {
+        for hostnames in self.entries.values_mut() {
+            hostnames.retain(|e| e.expires >= now);
+        }
+        self.entries.retain(|_, hostnames| !hostnames.is_empty());
+    }
+}
+
+/// Function that returns `name` together with all the names pointing to it through a chain of aliases.
+fn names_with_aliases(name: &str, aliases: &HashMap<String, Vec<String>>) -> Vec<String> {
+    let mut names = vec![name.to_string()];
+    let mut visited: HashSet<String> = HashSet::new();
+    visited.insert(name.to_string());
+    let mut i = 0;
+    while i < names.len() {
+        if let Some(sources) = aliases.get(&names[i]) {
+            for source in sources {
+                if visited.insert(source.clone()) {
+                    names.push(source.clone());
+                }
+            }
+        }
+        i += 1;
+    }
+    names
+}
+
+/// Length of the header of a DNS message: a shorter length prefix cannot start a message.
+const DNS_HEADER_LEN: usize = 12;
+/// Maximum number of bytes of a DNS message over TCP waiting for the rest of its segments: the length prefix and the
+/// longest message.
+pub const MAX_TCP_DNS_BUFFER: usize = 2 + u16::MAX as usize;
+
+/// Function that checks if a message from `source_port` to `destination_port` is mDNS or LLMNR, whose queries are sent
+/// to a multicast group. Only the port of the server side is considered, the destination of a query and the source of a
+/// response, since a unicast DNS client can pick 5353 or 5355 as its ephemeral port.
+pub fn is_multicast_dns(source_port: &str, destination_port: &str, is_response: bool) -> bool {
+    let server_port = if is_response { source_port } else { destination_port };
+    matches!(server_port.parse::<u16>(), Ok(MDNS_PORT) | Ok(LLMNR_PORT))
+}
+
+/// Function that splits the bytes of a DNS over TCP stream into the DNS messages it contains.
+/// Over TCP each message is preceded by a 2-byte length field, and one segment can carry several messages.
+/// It stops at the first incomplete message, returning the complete messages and the number of bytes they take.
+pub fn split_tcp_dns_messages(payload: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    while offset + 2 <= payload.len() {
+        let len = u16::from_be_bytes([payload[offset], payload[offset + 1]]) as usize;
+        if len < DNS_HEADER_LEN || offset + 2 + len > payload.len() {
+            break;
+        }
+        messages.push(&payload[offset + 2..offset + 2 + len]);
+        offset += 2 + len;
+    }
+    (messages, offset)
+}
+
+/// Function that takes the complete DNS messages buffered in a TCP `stream`, consuming their bytes, and returns them
+/// together with the number of messages that could not be decoded: the ones whose segments were lost and the ones
+/// that started before the capture. A stream that is not synchronized is decoded only if its buffered bytes are exactly
+/// a sequence of complete messages, otherwise they are the continuation of a message and are dropped.
+pub fn take_tcp_dns_messages(stream: &mut TcpStream) -> (Vec<Vec<u8>>, u32) {
+    let truncated = if stream.take_discarded() { 1 } else { 0 };
+    let (messages, consumed) = split_tcp_dns_messages(stream.data());
+    let rest = &stream.data()[consumed..];
+    let invalid_prefix = rest.len() >= 2 && (u16::from_be_bytes([rest[0], rest[1]]) as usize) < DNS_HEADER_LEN;
+    if invalid_prefix || (!stream.is_synchronized() && (messages.is_empty() || !rest.is_empty())) {
+        // The message was already counted when the stream started to wait for the start of a message
+        let counted = stream.is_resynchronizing();
+        stream.skip();
+        return (Vec::new(), if counted { truncated } else { truncated + 1 });
+    }
+    let messages = messages.into_iter().map(|m| m.to_vec()).collect();
+    stream.consume(consumed);
+    (messages, truncated)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::DnsAnswer;
+    use crate::stream::{StreamKey, StreamTable, TcpSegment};
+    use std::net::Ipv4Addr;
+
+    /// Returns a DNS message of `len` bytes preceded by its length prefix.
+    fn prefixed_message(len: usize, fill: u8) -> Vec<u8> {
+        let mut message = (len as u16).to_be_bytes().to_vec();
+        message.extend(std::iter::repeat(fill).take(len));
+        message
+    }
+
+    fn key() -> StreamKey {
+        StreamKey{source: "10.0.0.2 53".to_string(), destination: "10.0.0.1 40000".to_string()}
+    }
+
+    fn segment(sequence: u32, syn: bool) -> TcpSegment {
+        TcpSegment{sequence, syn, fin: false, rst: false}
+    }
+
+    fn message(id: u16, is_response: bool, name: &str, response_code: simple_dns::RCODE) -> DnsInfo {
+        DnsInfo{id, is_response, opcode: simple_dns::OPCODE::StandardQuery, response_code, queries: vec![name.to_string()],
+            query_type: vec![simple_dns::QTYPE::A], query_class: Vec::new(), responses: Vec::new(), response_class: Vec::new(), answers: Vec::new()}
+    }
+
+    fn query(id: u16, name: &str) -> DnsInfo {
+        message(id, false, name, simple_dns::RCODE::NoError)
+    }
+
+    fn response(id: u16, name: &str, response_code: simple_dns::RCODE) -> DnsInfo {
+        message(id, true, name, response_code)
+    }
+
+    #[test]
+    fn pairs_query_with_response() {
+        let mut tracker = DnsTracker::new(2000);
+        tracker.register("10.0.0.1", "10.0.0.53", &query(7, "example.com"), 1_000_000);
+        tracker.register("10.0.0.1", "10.0.0.53", &query(8, "example.org"), 1_000_000);
+        tracker.register("10.0.0.53", "10.0.0.1", &response(7, "example.com", simple_dns::RCODE::NoError), 1_025_000);
+        // Same question, but a different id: it does not answer the pending query
+        tracker.register("10.0.0.53", "10.0.0.1", &response(9, "example.org", simple_dns::RCODE::NoError), 1_030_000);
+        let stats = &tracker.latencies()["example.com"];
+        assert_eq!((stats.answers, stats.max_us, stats.average_us()), (1, 25_000, 25_000));
+        assert!(!tracker.latencies().contains_key("example.org"));
+        assert_eq!(tracker.pending.len(), 1);
+        assert_eq!(tracker.unmatched_responses, 1);
+    }
+
+    #[test]
+    fn measures_max_and_average_latency() {
+        let mut tracker = DnsTracker::new(2000);
+        for (id, latency) in [(1, 10_000), (2, 30_000)] {
+            tracker.register("10.0.0.1", "10.0.0.53", &query(id, "example.com"), 0);
+            tracker.register("10.0.0.53", "10.0.0.1", &response(id, "example.com", simple_dns::RCODE::NoError), latency);
+        }
+        let stats = &tracker.latencies()["example.com"];
+        assert_eq!((stats.answers, stats.max_us, stats.average_us()), (2, 30_000, 20_000));
+    }
+
+    #[test]
+    fn counts_nxdomain_and_servfail() {
+        let mut tracker = DnsTracker::new(2000);
+        tracker.register("10.0.0.1", "10.0.0.53", &query(1, "missing.example"), 0);
+        tracker.register("10.0.0.53", "10.0.0.1", &response(1, "missing.example", simple_dns::RCODE::NameError), 1000);
+        tracker.register("10.0.0.2", "10.0.0.53", &query(2, "missing.example"), 0);
+        tracker.register("10.0.0.53", "10.0.0.2", &response(2, "missing.example", simple_dns::RCODE::ServerFailure), 1000);
+        tracker.register("10.0.0.1", "10.0.0.53", &query(3, "example.com"), 0);
+        tracker.register("10.0.0.53", "10.0.0.1", &response(3, "example.com", simple_dns::RCODE::NoError), 1000);
+        let domain = &tracker.domain_failures()["missing.example"];
+        assert_eq!((domain.nxdomain, domain.servfail, domain.unanswered), (1, 1, 0));
+        assert!(!tracker.domain_failures().contains_key("example.com"));
+        let client = &tracker.client_failures()["10.0.0.1"];
+        assert_eq!((client.nxdomain, client.servfail), (1, 0));
+        let client = &tracker.client_failures()["10.0.0.2"];
+        assert_eq!((client.nxdomain, client.servfail), (0, 1));
+    }
+
+    #[test]
+    fn pending_queries_expire_after_timeout() {
+        let mut tracker = DnsTracker::new(100);
+        tracker.register("10.0.0.1", "10.0.0.53", &query(1, "example.com"), 0);
+        tracker.expire_pending(100_000);
+        assert_eq!(tracker.pending.len(), 1);
+        tracker.expire_pending(100_001);
+        assert!(tracker.pending.is_empty());
+        assert_eq!(tracker.domain_failures()["example.com"].unanswered, 1);
+        assert_eq!(tracker.client_failures()["10.0.0.1"].unanswered, 1);
+        // A late response no longer has a query to answer
+        tracker.register("10.0.0.53", "10.0.0.1", &response(1, "example.com", simple_dns::RCODE::NoError), 200_000);
+        assert_eq!(tracker.unmatched_responses, 1);
+        assert!(tracker.latencies().is_empty());
+    }
+
+    #[test]
+    fn huge_timeout_does_not_overflow() {
+        let mut tracker = DnsTracker::new(u64::MAX);
+        tracker.register("10.0.0.1", "10.0.0.53", &query(1, "example.com"), 0);
+        tracker.expire_pending(u64::MAX);
+        assert_eq!(tracker.pending.len(), 1);
+    }
+
+    fn answer(name: &str, ttl: u32, data: DnsAnswerData) -> DnsAnswer {
+        DnsAnswer{name: name.to_string(), ttl, data}
+    }
+
+    fn answers(answers: Vec<DnsAnswer>) -> DnsInfo {
+        DnsInfo{answers, ..response(1, "", simple_dns::RCODE::NoError)}
+    }
+
+    fn a_record(name: &str, ttl: u32, address: [u8; 4]) -> DnsAnswer {
+        answer(name, ttl, DnsAnswerData::A(Ipv4Addr::from(address)))
+    }
+
+    #[test]
+    fn cname_chain_aliases_the_address() {
+        let mut cache = PassiveDnsCache::new();
+        cache.register(&answers(vec![
+            answer("www.example.com.", 300, DnsAnswerData::CNAME("cdn.example.net.".to_string())),
+            answer("cdn.example.net.", 300, DnsAnswerData::CNAME("Edge.CDN.net.".to_string())),
+            a_record("edge.cdn.net.", 60, [192, 0, 2, 1]),
+        ]), 100);
+        let mut hostnames = cache.lookup("192.0.2.1", 100);
+        hostnames.sort();
+        assert_eq!(hostnames, vec!["cdn.example.net", "edge.cdn.net", "www.example.com"]);
+        assert_eq!(cache.lookup_addresses("WWW.Example.com.", 100), vec!["192.0.2.1"]);
+        // Queries are not learned
+        cache.register(&DnsInfo{is_response: false, ..answers(vec![a_record("other.example", 60, [192, 0, 2, 2])])}, 100);
+        assert!(cache.lookup("192.0.2.2", 100).is_empty());
+    }
+
+    #[test]
+    fn entries_expire_with_their_ttl() {
+        let mut cache = PassiveDnsCache::new();
+        cache.register(&answers(vec![a_record("example.com", 60, [192, 0, 2, 1])]), 100);
+        assert_eq!(cache.lookup("192.0.2.1", 160), vec!["example.com"]);
+        assert!(cache.lookup("192.0.2.1", 161).is_empty());
+        assert!(cache.lookup_addresses("example.com", 161).is_empty());
+        cache.purge_expired(160);
+        assert_eq!(cache.entries().len(), 1);
+        cache.purge_expired(161);
+        assert!(cache.entries().is_empty());
+    }
+
+    #[test]
+    fn long_ttl_is_capped() {
+        let mut cache = PassiveDnsCache::new();
+        cache.register(&answers(vec![a_record("example.com", 1 << 31, [192, 0, 2, 1])]), 100);
+        assert_eq!(cache.entries()["192.0.2.1"][0].expires, 100 + MAX_PASSIVE_DNS_TTL);
+    }
+
+    #[test]
+    fn keeps_the_most_recent_hostnames_of_an_address() {
+        let mut cache = PassiveDnsCache::new();
+        for i in 0..MAX_HOSTNAMES_PER_ADDRESS as u64 + 2 {
+            cache.register(&answers(vec![a_record(&format!("host{}.example", i), 3600, [192, 0, 2, 1])]), 100 + i);
+        }
+        let hostnames = cache.lookup("192.0.2.1", 200);
+        assert_eq!(hostnames.len(), MAX_HOSTNAMES_PER_ADDRESS);
+        assert_eq!(hostnames[0], "host9.example");
+        assert!(!hostnames.contains(&"host0.example".to_string()));
+        assert!(!hostnames.contains(&"host1.example".to_string()));
+    }
+
+    #[test]
+    fn full_cache_evicts_expired_then_least_recently_seen_addresses() {
+        let mut cache = PassiveDnsCache::new();
+        cache.register(&answers(vec![a_record("expired.example", 10, [10, 255, 255, 255])]), 0);
+        for i in 1..MAX_PASSIVE_DNS_ADDRESSES as u32 {
+            cache.register(&answers(vec![a_record("example.com", 3600, (i << 8).to_be_bytes())]), i as u64);
+        }
+        assert_eq!(cache.entries().len(), MAX_PASSIVE_DNS_ADDRESSES);
+        let ts = MAX_PASSIVE_DNS_ADDRESSES as u64;
+        cache.register(&answers(vec![a_record("new.example", 3600, [192, 0, 2, 1])]), ts);
+        assert_eq!(cache.entries().len(), MAX_PASSIVE_DNS_ADDRESSES - MAX_PASSIVE_DNS_ADDRESSES / 10 + 1);
+        assert!(!cache.entries().contains_key("10.255.255.255"));
+        // The valid addresses seen least recently are dropped, the recent ones are kept
+        assert!(!cache.entries().contains_key("0.0.1.0"));
+        assert!(cache.entries().contains_key(&Ipv4Addr::from(((ts as u32 - 1) << 8).to_be_bytes()).to_string()));
+        assert_eq!(cache.lookup("192.0.2.1", ts), vec!["new.example"]);
+    }
+
+    #[test]
+    fn multicast_dns_is_decided_by_the_server_port() {
+        // Unicast DNS from a client that picked 5353 as ephemeral port
+        assert!(!is_multicast_dns("5353", "53", false));
+        assert!(!is_multicast_dns("53", "5353", true));
+        // mDNS query and LLMNR response
+        assert!(is_multicast_dns("5353", "5353", false));
+        assert!(is_multicast_dns("5355", "50000", true));
+        assert!(is_multicast_dns("50000", "5355", false));
+    }
+
+    #[test]
+    fn splits_several_messages_of_a_segment() {
+        let mut payload = prefixed_message(12, 1);
+        payload.extend(prefixed_message(20, 2));
+        let (messages, consumed) = split_tcp_dns_messages(&payload);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], &[1; 12][..]);
+        assert_eq!(messages[1], &[2; 20][..]);
+        assert_eq!(consumed, payload.len());
+    }
+
+    #[test]
+    fn stops_at_incomplete_message() {
+        let mut payload = prefixed_message(12, 1);
+        payload.extend(&prefixed_message(100, 2)[..50]);
+        let (messages, consumed) = split_tcp_dns_messages(&payload);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(consumed, 14);
+    }
+
+    #[test]
+    fn reassembles_message_split_across_segments() {
+        let mut table = StreamTable::new(MAX_TCP_DNS_BUFFER);
+        let message = prefixed_message(3000, 7);
+        table.register(key(), segment(999, true), &[], 0);
+        let stream = table.register(key(), segment(1000, false), &message[..1460], 0).unwrap();
+        assert_eq!(take_tcp_dns_messages(stream), (Vec::new(), 0));
+        let stream = table.register(key(), segment(2460, false), &message[1460..2920], 0).unwrap();
+        assert_eq!(take_tcp_dns_messages(stream), (Vec::new(), 0));
+        let mut last = message[2920..].to_vec();
+        last.extend(prefixed_message(12, 8));
+        let stream = table.register(key(), segment(3920, false), &last, 0).unwrap();
+        let (messages, truncated) = take_tcp_dns_messages(stream);
+        assert_eq!(messages, vec![vec![7; 3000], vec![8; 12]]);
+        assert_eq!(truncated, 0);
+        assert!(stream.data().is_empty());
+    }
+
+    #[test]
+    fn continuation_without_start_is_truncated() {
+        let mut table = StreamTable::new(MAX_TCP_DNS_BUFFER);
+        let message = prefixed_message(3000, 7);
+        let stream = table.register(key(), segment(5000, false), &message[1460..2920], 0).unwrap();
+        assert_eq!(take_tcp_dns_messages(stream), (Vec::new(), 1));
+        // The stream is decoded again from the first segment that starts with a message
+        let stream = table.register(key(), segment(6460, false), &message[2920..], 0).unwrap();
+        assert_eq!(take_tcp_dns_messages(stream), (Vec::new(), 0));
+        let stream = table.register(key(), segment(6542, false), &prefixed_message(12, 8), 0).unwrap();
+        assert_eq!(take_tcp_dns_messages(stream), (vec![vec![8; 12]], 0));
+    }
+
+    #[test]
+    fn lost_segment_is_truncated() {
+        let mut table = StreamTable::new(MAX_TCP_DNS_BUFFER);
+        let message = prefixed_message(3000, 7);
+        table.register(key(), segment(999, true), &[], 0);
+        let stream = table.register(key(), segment(1000, false), &message[..1460], 0).unwrap();
+        assert_eq!(take_tcp_dns_messages(stream), (Vec::new(), 0));
+        let stream = table.register(key(), segment(3920, false), &message[2920..], 0).unwrap();
+        assert_eq!(take_tcp_dns_messages(stream), (Vec::new(), 1));
+    }
+}
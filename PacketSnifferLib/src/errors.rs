@@ -15,7 +15,11 @@ pub enum PacketSnifferError {
     /// Refers to this error message " File error: x".
     FileError(String),
     /// Refers to this error message" Invalid filter: {}, check https://biot.com/capstats/bpf.html to know the syntax. "
-    InvalidFilter(String, String)
+    InvalidFilter(String, String),
+    /// Refers to this error message " Invalid signature: x, the format is NAME:OFFSET:HEX[:TRANSPORT[:PORT]]".
+    InvalidSignature(String),
+    /// Refers to this error message " Invalid subnet: x, the format is [NAME=]ADDRESS/PREFIX".
+    InvalidSubnet(String)
 }
 
 impl fmt::Display for PacketSnifferError {
@@ -26,7 +30,9 @@ impl fmt::Display for PacketSnifferError {
             PacketSnifferError::InactivableCapture(device_name, error_string) => {write!(f, "Cannot capture from device: {}, check if the name is correct or if you have permissions.\nDetailed error: {}", device_name, error_string)},
             PacketSnifferError::UnavailableDeviceList(error_string) => {write!(f, "Cannot get device list, check if you have permission or if there are available devices.\nDetailed error: {}", error_string)},
             PacketSnifferError::FileError(err) => {write!(f, "File error: {}", err)},
-            PacketSnifferError::InvalidFilter(filter, error_string) => {write!(f, "Invalid filter: {}, check https://biot.com/capstats/bpf.html to know the syntax.\nDetailed error: {}", filter, error_string)}
+            PacketSnifferError::InvalidFilter(filter, error_string) => {write!(f, "Invalid filter: {}, check https://biot.com/capstats/bpf.html to know the syntax.\nDetailed error: {}", filter, error_string)},
+            PacketSnifferError::InvalidSignature(signature) => {write!(f, "Invalid signature: {}, the format is NAME:OFFSET:HEX[:TRANSPORT[:PORT]]", signature)},
+            PacketSnifferError::InvalidSubnet(subnet) => {write!(f, "Invalid subnet: {}, the format is [NAME=]ADDRESS/PREFIX", subnet)}
         }
     }
 }
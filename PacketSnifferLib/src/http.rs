@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use crate::report::{AddressPortPair, Report};
+
+/// Methods recognized at the beginning of an HTTP/1.x request.
+const HTTP_METHODS: [&str; 9] = ["GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS", "PATCH", "CONNECT", "TRACE"];
+/// Maximum number of requests and responses remembered for a single flow in a report.
+const MAX_HTTP_MESSAGES_PER_FLOW: usize = 50;
+
+#[derive(Debug, Clone)]
+///Struct containing the metadata of an HTTP/1.x request.
+pub struct HttpRequest {
+    /// Request method
+    pub method: String,
+    /// Value of the Host header
+    pub host: Option<String>,
+    /// Requested URI path, without the query string
+    pub path: String,
+    /// Value of the User-Agent header
+    pub user_agent: Option<String>,
+    /// Value of the Content-Length header
+    pub content_length: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the metadata of an HTTP/1.x response.
+pub struct HttpResponse {
+    /// Protocol version, e.g. "HTTP/1.1"
+    pub version: String,
+    /// Status code
+    pub status_code: u16,
+    /// Value of the Content-Length header
+    pub content_length: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+///Enum representing an HTTP/1.x message.
+pub enum HttpMessage {
+    /// Message sent by the client
+    Request(HttpRequest),
+    /// Message sent by the server
+    Response(HttpResponse),
+}
+
+#[derive(Debug, Clone, Default)]
+///Struct which reports info relative to the HTTP messages of a flow.
+pub struct HttpInfo {
+    /// Requests observed in the flow
+    pub requests: Vec<HttpRequest>,
+    /// Responses observed in the flow
+    pub responses: Vec<HttpResponse>,
+    /// Messages not stored because the flow reached the limit
+    pub dropped_messages: u32,
+    /// Endpoint ("address:port") that sent the responses
+    pub server: Option<String>,
+}
+
+impl HttpInfo {
+    /// Adds a message to the struct, counting it as dropped when the flow has already too many messages.
+    pub fn add(&mut self, message: HttpMessage) {
+        if self.requests.len() + self.responses.len() >= MAX_HTTP_MESSAGES_PER_FLOW {
+            self.dropped_messages += 1;
+            return;
+        }
+        match message {
+            HttpMessage::Request(request) => self.requests.push(request),
+            HttpMessage::Response(response) => self.responses.push(response)
+        }
+    }
+
+    /// Merges the messages of a later part of the same flow, counting as dropped the ones over the limit.
+    pub fn merge(&mut self, other: HttpInfo) {
+        self.dropped_messages += other.dropped_messages;
+        if other.server.is_some() {
+            self.server = other.server;
+        }
+        for request in other.requests {
+            self.add(HttpMessage::Request(request));
+        }
+        for response in other.responses {
+            self.add(HttpMessage::Response(response));
+        }
+    }
+}
+
+/// Function that returns the headers of a message as (lowercase name, value) pairs, stopping at the end of the header block.
+fn parse_headers<'a>(lines: impl Iterator<Item = &'a str>) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    for line in lines {
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    headers
+}
+
+/// Function that checks if a TCP payload starts like an HTTP/1.x message: with a method followed by a space, or with the
+/// version of a status line.
+fn starts_like_http(payload: &[u8]) -> bool {
+    payload.starts_with(b"HTTP/1.") || HTTP_METHODS.iter().any(|method| {
+        payload.starts_with(method.as_bytes()) && payload.get(method.len()) == Some(&b' ')
+    })
+}
+
+/// Function that parses the beginning of a TCP payload as an HTTP/1.x request or response.
+/// It returns `None` if the payload does not start with a request line or a status line, which is checked on the raw
+/// bytes before decoding anything.
+pub fn parse_http(payload: &[u8]) -> Option<HttpMessage> {
+    if !starts_like_http(payload) {
+        return None;
+    }
+    // Only the header block is needed, the body can be binary
+    let end = payload.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4).unwrap_or(payload.len());
+    let text = String::from_utf8_lossy(&payload[..end]);
+    let mut lines = text.split("\r\n");
+    let first_line = lines.next()?;
+    let mut parts = first_line.splitn(3, ' ');
+    let first = parts.next()?;
+    let second = parts.next()?;
+    let third = parts.next()?;
+
+    if first.starts_with("HTTP/1.") {
+        let status_code = second.parse::<u16>().ok()?;
+        let headers = parse_headers(lines);
+        return Some(HttpMessage::Response(HttpResponse{
+            version: first.to_string(),
+            status_code,
+            content_length: headers.get("content-length").and_then(|v| v.parse().ok()),
+        }));
+    }
+    if HTTP_METHODS.contains(&first) && third.starts_with("HTTP/1.") {
+        let headers = parse_headers(lines);
+        let path = second.split('?').next().unwrap_or(second).to_string();
+        return Some(HttpMessage::Request(HttpRequest{
+            method: first.to_string(),
+            host: headers.get("host").cloned(),
+            path,
+            user_agent: headers.get("user-agent").cloned(),
+            content_length: headers.get("content-length").and_then(|v| v.parse().ok()),
+        }));
+    }
+    None
+}
+
+/// The function takes as parameter an `HttpInfo` struct and generate a String containing its formatted informations, one message per line.
+pub fn http_info_to_string(info: &HttpInfo) -> String {
+    let mut http_string = "".to_owned();
+    for request in &info.requests {
+        http_string.push_str(&format!("Request: {} {}{}; User-Agent: {}; Content-Length: {}\n",
+                                      request.method,
+                                      request.host.as_deref().unwrap_or(""),
+                                      request.path,
+                                      request.user_agent.as_deref().unwrap_or("None"),
+                                      content_length_to_string(request.content_length)));
+    }
+    for response in &info.responses {
+        http_string.push_str(&format!("Response: {} {}; Content-Length: {}\n",
+                                      response.version,
+                                      response.status_code,
+                                      content_length_to_string(response.content_length)));
+    }
+    if info.dropped_messages > 0 {
+        http_string.push_str(&format!("Messages not shown: {}\n", info.dropped_messages));
+    }
+    http_string
+}
+
+/// Function that formats an optional content length.
+fn content_length_to_string(content_length: Option<u64>) -> String {
+    match content_length {
+        Some(len) => len.to_string(),
+        None => "None".to_string()
+    }
+}
+
+/// Function that generates the HTTP section of a report, listing the `top_n` most requested endpoints (host and path)
+/// and the `top_n` servers that answered plaintext HTTP with most responses.
+pub fn http_summary_to_string<'a>(report: impl IntoIterator<Item = (&'a AddressPortPair, &'a Report)>, top_n: usize) -> String {
+    let mut endpoints: HashMap<String, u32> = HashMap::new();
+    let mut servers: HashMap<String, u32> = HashMap::new();
+    for (_, flow) in report {
+        let info = match flow.http_info() {
+            Some(info) => info,
+            None => continue
+        };
+        for request in &info.requests {
+            let endpoint = format!("{} {}{}", request.method, request.host.as_deref().unwrap_or(""), request.path);
+            *endpoints.entry(endpoint).or_insert(0) += 1;
+        }
+        if let Some(server) = &info.server {
+            if !info.responses.is_empty() {
+                *servers.entry(server.clone()).or_insert(0) += info.responses.len() as u32;
+            }
+        }
+    }
+
+    let mut s = "".to_owned();
+    s.push_str("-----HTTP-----\n");
+    s.push_str("Most requested endpoints:\n");
+    let mut endpoints: Vec<(String, u32)> = endpoints.into_iter().collect();
+    endpoints.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    for (endpoint, count) in endpoints.into_iter().take(top_n) {
+        s.push_str(&format!("{}: {} requests\n", endpoint, count));
+    }
+    s.push_str("Plaintext HTTP servers:\n");
+    let mut servers: Vec<(String, u32)> = servers.into_iter().collect();
+    servers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    for (server, count) in servers.into_iter().take(top_n) {
+        s.push_str(&format!("{}: {} responses\n", server, count));
+    }
+    s.push('\n');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::LinkInfo;
+
+    fn request(payload: &[u8]) -> HttpRequest {
+        match parse_http(payload) {
+            Some(HttpMessage::Request(request)) => request,
+            other => panic!("not a request: {:?}", other)
+        }
+    }
+
+    fn response(payload: &[u8]) -> HttpResponse {
+        match parse_http(payload) {
+            Some(HttpMessage::Response(response)) => response,
+            other => panic!("not a response: {:?}", other)
+        }
+    }
+
+    fn flow() -> Report {
+        let link = LinkInfo{source_mac: [0; 6], destination_mac: [0; 6], ether_type: "Ipv4".to_string()};
+        Report::new(0, 100, "TCP".to_string(), "IPv4".to_string(), link, String::new(), String::new())
+    }
+
+    fn pair(first: (&str, &str), second: (&str, &str)) -> AddressPortPair {
+        AddressPortPair::new(first.0.to_string(), first.1.to_string(), second.0.to_string(), second.1.to_string())
+    }
+
+    #[test]
+    fn parses_request_line_and_headers() {
+        let request = request(b"GET /search?q=rust HTTP/1.1\r\nHOST: example.com\r\nuser-agent: curl/8.0\r\nContent-Length: 12\r\n\r\nbody");
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.host.as_deref(), Some("example.com"));
+        assert_eq!(request.path, "/search");
+        assert_eq!(request.user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!(request.content_length, Some(12));
+    }
+
+    #[test]
+    fn parses_status_line_with_binary_body() {
+        let mut payload = b"HTTP/1.0 404 Not Found\r\nContent-Length: 4\r\n\r\n".to_vec();
+        payload.extend([0xff, 0xfe, 0x00, 0x80]);
+        let response = response(&payload);
+        assert_eq!(response.version, "HTTP/1.0");
+        assert_eq!(response.status_code, 404);
+        assert_eq!(response.content_length, Some(4));
+    }
+
+    #[test]
+    fn headers_end_at_the_blank_line() {
+        let request = request(b"POST /upload HTTP/1.1\r\nHost: example.com\r\n\r\nUser-Agent: not-a-header");
+        assert_eq!(request.user_agent, None);
+        assert_eq!(request.content_length, None);
+    }
+
+    #[test]
+    fn rejects_payloads_that_are_not_http() {
+        assert!(parse_http(b"GETX / HTTP/1.1\r\n\r\n").is_none());
+        assert!(parse_http(b"GET / SPDY/3\r\n\r\n").is_none());
+        assert!(parse_http(b"HTTP/1.1 OK\r\n\r\n").is_none());
+        assert!(parse_http(b"HTTP/2 200\r\n\r\n").is_none());
+        assert!(parse_http(&[0x16, 0x03, 0x01, 0x00, 0x05]).is_none());
+    }
+
+    #[test]
+    fn formats_messages_of_a_flow() {
+        let mut info = HttpInfo::default();
+        info.add(parse_http(b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap());
+        info.add(parse_http(b"HTTP/1.1 200 OK\r\nContent-Length: 512\r\n\r\n").unwrap());
+        assert_eq!(http_info_to_string(&info), "Request: GET example.com/index.html; User-Agent: None; Content-Length: None\n\
+                                                Response: HTTP/1.1 200; Content-Length: 512\n");
+    }
+
+    #[test]
+    fn summary_counts_endpoints_and_the_responding_server() {
+        let mut report = HashMap::new();
+        // The server listens on a port higher than the client's
+        let mut high_port_server = flow();
+        high_port_server.add_http_message(parse_http(b"GET /api HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap(), "10.0.0.1:80");
+        high_port_server.add_http_message(parse_http(b"HTTP/1.1 200 OK\r\n\r\n").unwrap(), "10.0.0.2:8080");
+        high_port_server.add_http_message(parse_http(b"HTTP/1.1 304 Not Modified\r\n\r\n").unwrap(), "10.0.0.2:8080");
+        report.insert(pair(("10.0.0.1", "80"), ("10.0.0.2", "8080")), high_port_server);
+        let mut other = flow();
+        other.add_http_message(parse_http(b"GET /api HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap(), "10.0.0.3:50000");
+        other.add_http_message(parse_http(b"GET /other HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap(), "10.0.0.3:50000");
+        report.insert(pair(("10.0.0.3", "50000"), ("10.0.0.4", "80")), other);
+        assert_eq!(http_summary_to_string(&report, 10), "-----HTTP-----\n\
+                                                        Most requested endpoints:\n\
+                                                        GET example.com/api: 2 requests\n\
+                                                        GET example.com/other: 1 requests\n\
+                                                        Plaintext HTTP servers:\n\
+                                                        10.0.0.2:8080: 2 responses\n\n");
+        assert!(http_summary_to_string(&report, 1).contains("Most requested endpoints:\nGET example.com/api: 2 requests\nPlaintext"));
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use etherparse::{IcmpEchoHeader, Icmpv4Type, Icmpv6Type, TransportSlice};
+
+#[derive(Debug, Clone)]
+///Struct containing the header of the packet quoted by an ICMP error message.
+pub struct QuotedPacket {
+    /// Source address of the packet that triggered the error
+    pub source_address: String,
+    /// Destination address of the packet that triggered the error
+    pub destination_address: String,
+    /// Transport layer protocol, e.g. "UDP"
+    pub protocol: String,
+    /// Source port, for TCP and UDP
+    pub source_port: Option<String>,
+    /// Destination port, for TCP and UDP
+    pub destination_port: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to an ICMP or ICMPv6 error message.
+pub struct IcmpError {
+    /// Meaning of the type and code, e.g. "port unreachable"
+    pub description: String,
+    /// Next-hop MTU of "fragmentation needed" and "packet too big" errors
+    pub mtu: Option<u32>,
+    /// Packet that triggered the error
+    pub original: QuotedPacket,
+}
+
+/// Function that returns the name of a transport protocol given its IP protocol number.
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "Icmpv4".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        58 => "Icmpv6".to_string(),
+        _ => format!("IP protocol {}", protocol)
+    }
+}
+
+/// Function that skips the IPv6 extension headers that follow the fixed header, and returns the protocol of the upper
+/// layer header and the bytes that follow. When the chain is truncated, or the packet is a fragment other than the first
+/// one, the protocol of the last header reached is returned without the bytes of the upper layer header.
+fn skip_ipv6_extensions(mut next_header: u8, mut data: &[u8]) -> (u8, &[u8]) {
+    loop {
+        let len = match next_header {
+            // Hop-by-Hop Options, Routing and Destination Options
+            0 | 43 | 60 => match data.get(1) {
+                Some(len) => (*len as usize + 1) * 8,
+                None => return (next_header, &[])
+            },
+            // Fragment
+            44 => {
+                match data.get(2..4) {
+                    Some(offset) if u16::from_be_bytes([offset[0], offset[1]]) >> 3 == 0 => {},
+                    _ => return (data.first().copied().unwrap_or(next_header), &[])
+                }
+                8
+            },
+            // Authentication Header
+            51 => match data.get(1) {
+                Some(len) => (*len as usize + 2) * 4,
+                None => return (next_header, &[])
+            },
+            _ => return (next_header, data)
+        };
+        match (data.first(), data.get(len..)) {
+            (Some(header), Some(rest)) => {
+                next_header = *header;
+                data = rest;
+            },
+            _ => return (next_header, &[])
+        }
+    }
+}
+
+/// Function that parses the IP header quoted by an ICMP error, together with the ports of TCP and UDP.
+/// The quoted packet is usually truncated, so only the first 8 bytes of the transport header are read.
+pub fn parse_quoted_packet(data: &[u8]) -> Option<QuotedPacket> {
+    let (source_address, destination_address, protocol, transport) = match data.first()? >> 4 {
+        4 => {
+            let header_len = (data[0] & 0x0f) as usize * 4;
+            if header_len < 20 || data.len() < header_len {
+                return None;
+            }
+            let source: [u8; 4] = data[12..16].try_into().ok()?;
+            let destination: [u8; 4] = data[16..20].try_into().ok()?;
+            (Ipv4Addr::from(source).to_string(), Ipv4Addr::from(destination).to_string(), data[9], &data[header_len..])
+        },
+        6 => {
+            if data.len() < 40 {
+                return None;
+            }
+            let source: [u8; 16] = data[8..24].try_into().ok()?;
+            let destination: [u8; 16] = data[24..40].try_into().ok()?;
+            let (protocol, transport) = skip_ipv6_extensions(data[6], &data[40..]);
+            (Ipv6Addr::from(source).to_string(), Ipv6Addr::from(destination).to_string(), protocol, transport)
+        },
+        _ => return None
+    };
+    let (source_port, destination_port) = if (protocol == 6 || protocol == 17) && transport.len() >= 4 {
+        (Some(u16::from_be_bytes([transport[0], transport[1]]).to_string()), Some(u16::from_be_bytes([transport[2], transport[3]]).to_string()))
+    } else {
+        (None, None)
+    };
+    Some(QuotedPacket{source_address, destination_address, protocol: protocol_name(protocol), source_port, destination_port})
+}
+
+/// Function that returns the meaning of an ICMPv4 error type and code, or `None` if the message is not an error.
+fn icmpv4_error_description(type_u8: u8, code_u8: u8) -> Option<String> {
+    let description = match (type_u8, code_u8) {
+        (3, 0) => "network unreachable",
+        (3, 1) => "host unreachable",
+        (3, 2) => "protocol unreachable",
+        (3, 3) => "port unreachable",
+        (3, 4) => "fragmentation needed",
+        (3, 5) => "source route failed",
+        (3, 6) => "destination network unknown",
+        (3, 7) => "destination host unknown",
+        (3, 9) | (3, 10) | (3, 13) => "administratively prohibited",
+        (3, _) => "destination unreachable",
+        (5, _) => "redirect",
+        (11, 0) => "TTL exceeded in transit",
+        (11, _) => "fragment reassembly time exceeded",
+        (12, _) => "parameter problem",
+        _ => return None
+    };
+    Some(description.to_string())
+}
+
+/// Function that returns the meaning of an ICMPv6 error type and code, or `None` if the message is not an error.
+fn icmpv6_error_description(type_u8: u8, code_u8: u8) -> Option<String> {
+    let description = match (type_u8, code_u8) {
+        (1, 0) => "no route to destination",
+        (1, 1) => "administratively prohibited",
+        (1, 2) => "beyond scope of source address",
+        (1, 3) => "address unreachable",
+        (1, 4) => "port unreachable",
+        (1, 5) => "source address failed policy",
+        (1, 6) => "reject route to destination",
+        (1, _) => "destination unreachable",
+        (2, _) => "packet too big",
+        (3, 0) => "hop limit exceeded in transit",
+        (3, _) => "fragment reassembly time exceeded",
+        (4, _) => "parameter problem",
+        _ => return None
+    };
+    Some(description.to_string())
+}
+
+/// Function that decodes an ICMP or ICMPv6 error message, together with the header of the packet that triggered it.
+/// It returns `None` for the other messages.
+pub fn parse_icmp_error(transport: &Option<TransportSlice>) -> Option<IcmpError> {
+    let (description, mtu, quoted) = match transport {
+        Some(TransportSlice::Icmpv4(slice)) => {
+            let description = icmpv4_error_description(slice.type_u8(), slice.code_u8())?;
+            let bytes = slice.bytes5to8();
+            // The next-hop MTU is in the low 16 bits of the rest of the header (RFC 1191)
+            let mtu = if slice.type_u8() == 3 && slice.code_u8() == 4 { Some(u16::from_be_bytes([bytes[2], bytes[3]]) as u32) } else { None };
+            (description, mtu, slice.payload())
+        },
+        Some(TransportSlice::Icmpv6(slice)) => {
+            let description = icmpv6_error_description(slice.type_u8(), slice.code_u8())?;
+            let mtu = if slice.type_u8() == 2 { Some(u32::from_be_bytes(slice.bytes5to8())) } else { None };
+            (description, mtu, slice.payload())
+        },
+        _ => return None
+    };
+    Some(IcmpError{description, mtu, original: parse_quoted_packet(quoted)?})
+}
+
+/// Function that formats an address and an optional port.
+fn endpoint_to_string(address: &str, port: &Option<String>) -> String {
+    match port {
+        Some(port) if address.contains(':') => format!("[{}]:{}", address, port),
+        Some(port) => format!("{}:{}", address, port),
+        None => address.to_string()
+    }
+}
+
+/// The function takes as parameter an `IcmpError` struct and generate a String describing it with the destination of
+/// the original packet, e.g. "port unreachable for 10.0.0.5:5000/UDP".
+pub fn icmp_error_to_string(error: &IcmpError) -> String {
+    let mut error_string = error.description.clone();
+    if let Some(mtu) = error.mtu {
+        error_string.push_str(&format!(" (MTU {})", mtu));
+    }
+    error_string.push_str(&format!(" for {}/{}", endpoint_to_string(&error.original.destination_address, &error.original.destination_port), error.original.protocol));
+    error_string
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///Struct containing an ICMP or ICMPv6 echo message.
+pub struct EchoMessage {
+    /// True for echo requests, false for echo replies
+    pub is_request: bool,
+    /// Identifier and sequence number
+    pub header: IcmpEchoHeader,
+}
+
+/// Function that decodes an ICMP or ICMPv6 echo request or reply. It returns `None` for the other messages.
+pub fn parse_echo(transport: &Option<TransportSlice>) -> Option<EchoMessage> {
+    let (is_request, header) = match transport {
+        Some(TransportSlice::Icmpv4(slice)) => match slice.icmp_type() {
+            Icmpv4Type::EchoRequest(header) => (true, header),
+            Icmpv4Type::EchoReply(header) => (false, header),
+            _ => return None
+        },
+        Some(TransportSlice::Icmpv6(slice)) => match slice.icmp_type() {
+            Icmpv6Type::EchoRequest(header) => (true, header),
+            Icmpv6Type::EchoReply(header) => (false, header),
+            _ => return None
+        },
+        _ => return None
+    };
+    Some(EchoMessage{is_request, header})
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Struct identifying an echo request: its reply has the same key, with the addresses swapped.
+pub struct EchoKey {
+    /// Address of the host that sent the request
+    pub source: String,
+    /// Address of the host that should reply
+    pub destination: String,
+    /// Echo identifier
+    pub id: u16,
+    /// Echo sequence number
+    pub seq: u16,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Struct containing the ping statistics of a pair of hosts.
+pub struct PingStats {
+    /// Echo requests answered or lost, so that `requests` is the sum of `replies` and `lost`
+    pub requests: u32,
+    /// Echo replies matched with a request
+    pub replies: u32,
+    /// Requests that did not receive a reply before the timeout
+    pub lost: u32,
+    /// Sum of the round-trip times, in microseconds
+    pub total_us: u64,
+    /// Lowest round-trip time, in microseconds
+    pub min_us: u64,
+    /// Highest round-trip time, in microseconds
+    pub max_us: u64,
+}
+
+impl PingStats {
+    /// Average round-trip time in microseconds.
+    pub fn average_us(&self) -> u64 {
+        if self.replies == 0 {
+            return 0;
+        }
+        self.total_us / self.replies as u64
+    }
+
+    /// Percentage of the requests, either answered or lost, that did not receive a reply.
+    pub fn loss_percent(&self) -> f64 {
+        let completed = self.replies + self.lost;
+        if completed == 0 {
+            return 0.0;
+        }
+        self.lost as f64 * 100.0 / completed as f64
+    }
+}
+
+#[derive(Debug)]
+/// Struct that pairs echo requests with their replies, measuring the round-trip time and the loss of each pair of hosts.
+/// Statistics refer to the requests answered or lost in the current report interval, whatever the interval they were sent
+/// in: a request is counted once, when its reply arrives or its timeout elapses. The pending requests are kept until
+/// answered or expired.
+pub struct PingTracker {
+    /// Requests waiting for a reply, with the timestamp (in microseconds) at which they were sent
+    pending: HashMap<EchoKey, u64>,
+    /// Time (in milliseconds) after which a request without reply is counted as lost
+    timeout_ms: u64,
+    /// Statistics for each (source, destination) pair
+    stats: HashMap<(String, String), PingStats>,
+}
+
+impl PingTracker {
+    /// Create a new PingTracker struct. `timeout_ms` is the time after which a request without reply is counted as lost.
+    pub fn new(timeout_ms: u64) -> PingTracker {
+        PingTracker{pending: HashMap::new(), timeout_ms, stats: HashMap::new()}
+    }
+
+    /// Registers an echo message sent from `source` to `destination` at timestamp `ts_us` (in microseconds).
+    pub fn register(&mut self, source: &str, destination: &str, echo: &EchoMessage, ts_us: u64) {
+        if echo.is_request {
+            let key = EchoKey{source: source.to_string(), destination: destination.to_string(), id: echo.header.id, seq: echo.header.seq};
+            self.pending.insert(key, ts_us);
+            return;
+        }
+        let key = EchoKey{source: destination.to_string(), destination: source.to_string(), id: echo.header.id, seq: echo.header.seq};
+        // Duplicated replies and replies to requests sent before the capture are ignored
+        if let Some(request_ts) = self.pending.remove(&key) {
+            let rtt = ts_us.saturating_sub(request_ts);
+            let stats = self.stats.entry((key.source, key.destination)).or_default();
+            if stats.replies == 0 || rtt < stats.min_us {
+                stats.min_us = rtt;
+            }
+            if rtt > stats.max_us {
+                stats.max_us = rtt;
+            }
+            stats.requests += 1;
+            stats.replies += 1;
+            stats.total_us += rtt;
+        }
+    }
+
+    /// Counts as lost the pending requests older than the timeout at timestamp `now_us` (in microseconds), the timestamp
+    /// of the last captured packet.
+    pub fn expire_pending(&mut self, now_us: u64) {
+        let deadline = now_us.saturating_sub(self.timeout_ms.saturating_mul(1000));
+        let expired: Vec<EchoKey> = self.pending.iter()
+            .filter(|(_, ts)| **ts < deadline)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.pending.remove(&key);
+            let stats = self.stats.entry((key.source, key.destination)).or_default();
+            stats.requests += 1;
+            stats.lost += 1;
+        }
+    }
+
+    /// Statistics of the requests answered or lost in the current interval for each (source, destination) pair.
+    pub fn stats(&self) -> &HashMap<(String, String), PingStats> {
+        &self.stats
+    }
+
+    /// Clears the statistics of the current interval, keeping the pending requests.
+    pub fn reset_stats(&mut self) {
+        self.stats.clear();
+    }
+
+    /// Generates the ping section of a report, with the round-trip times and the loss of each pair of hosts.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----Ping-----\n");
+        let mut pairs: Vec<(&(String, String), &PingStats)> = self.stats.iter().collect();
+        pairs.sort_by(|a, b| a.0.cmp(b.0));
+        for ((source, destination), stats) in pairs {
+            s.push_str(&format!("{} -> {}: requests: {}, replies: {}, lost: {} ({:.1}%), rtt min/avg/max: {:.3}/{:.3}/{:.3} ms\n",
+                                source,
+                                destination,
+                                stats.requests,
+                                stats.replies,
+                                stats.lost,
+                                stats.loss_percent(),
+                                stats.min_us as f64 / 1000.0,
+                                stats.average_us() as f64 / 1000.0,
+                                stats.max_us as f64 / 1000.0));
+        }
+        s.push_str(&format!("Pending requests: {}\n", self.pending.len()));
+        s.push('\n');
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::{Icmpv4Slice, Icmpv6Slice};
+
+    /// Returns an IPv4 header of `options` 4-byte words of options, from 10.0.0.1 to 10.0.0.5, followed by the first
+    /// bytes of a UDP header from port 40000 to port 5000.
+    fn quoted_ipv4_udp(options: u8) -> Vec<u8> {
+        let mut packet = vec![0x45 + options, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 5];
+        packet.extend(vec![1; options as usize * 4]);
+        packet.extend_from_slice(&[0x9c, 0x40, 0x13, 0x88, 0, 8, 0, 0]);
+        packet
+    }
+
+    /// Returns an ICMP message with the given type, code and rest of the header, quoting `quoted`.
+    fn icmp_message(type_u8: u8, code_u8: u8, rest: [u8; 4], quoted: &[u8]) -> Vec<u8> {
+        let mut message = vec![type_u8, code_u8, 0, 0];
+        message.extend_from_slice(&rest);
+        message.extend_from_slice(quoted);
+        message
+    }
+
+    #[test]
+    fn reads_quoted_ipv4_header_and_ports() {
+        let quoted = parse_quoted_packet(&quoted_ipv4_udp(0)).unwrap();
+        assert_eq!((quoted.source_address.as_str(), quoted.destination_address.as_str()), ("10.0.0.1", "10.0.0.5"));
+        assert_eq!(quoted.protocol, "UDP");
+        assert_eq!((quoted.source_port.as_deref(), quoted.destination_port.as_deref()), (Some("40000"), Some("5000")));
+        // The options are skipped according to the header length
+        let quoted = parse_quoted_packet(&quoted_ipv4_udp(2)).unwrap();
+        assert_eq!(quoted.destination_port.as_deref(), Some("5000"));
+        // The ports are read only for TCP and UDP
+        let mut packet = quoted_ipv4_udp(0);
+        packet[9] = 1;
+        let quoted = parse_quoted_packet(&packet).unwrap();
+        assert_eq!((quoted.protocol.as_str(), quoted.destination_port), ("Icmpv4", None));
+        // A header length shorter than the fixed header, and a truncated header
+        let mut packet = quoted_ipv4_udp(0);
+        packet[0] = 0x44;
+        assert!(parse_quoted_packet(&packet).is_none());
+        assert!(parse_quoted_packet(&quoted_ipv4_udp(0)[..19]).is_none());
+    }
+
+    #[test]
+    fn formats_port_unreachable() {
+        let message = icmp_message(3, 3, [0; 4], &quoted_ipv4_udp(0));
+        let error = parse_icmp_error(&Some(TransportSlice::Icmpv4(Icmpv4Slice::from_slice(&message).unwrap()))).unwrap();
+        assert_eq!(error.mtu, None);
+        assert_eq!(icmp_error_to_string(&error), "port unreachable for 10.0.0.5:5000/UDP");
+    }
+
+    #[test]
+    fn fragmentation_needed_carries_the_next_hop_mtu() {
+        let message = icmp_message(3, 4, [0, 0, 0x05, 0xdc], &quoted_ipv4_udp(0));
+        let error = parse_icmp_error(&Some(TransportSlice::Icmpv4(Icmpv4Slice::from_slice(&message).unwrap()))).unwrap();
+        assert_eq!(error.mtu, Some(1500));
+        assert_eq!(icmp_error_to_string(&error), "fragmentation needed (MTU 1500) for 10.0.0.5:5000/UDP");
+        // Echo requests are not errors
+        let message = icmp_message(8, 0, [0, 1, 0, 1], &[]);
+        assert!(parse_icmp_error(&Some(TransportSlice::Icmpv4(Icmpv4Slice::from_slice(&message).unwrap()))).is_none());
+    }
+
+    #[test]
+    fn formats_ipv6_endpoints_in_brackets() {
+        let mut quoted = ipv6_header(17);
+        quoted.extend_from_slice(&[0x9c, 0x40, 0x00, 0x35, 0, 8, 0, 0]);
+        let message = icmp_message(2, 0, [0, 0, 0x05, 0x00], &quoted);
+        let error = parse_icmp_error(&Some(TransportSlice::Icmpv6(Icmpv6Slice::from_slice(&message).unwrap()))).unwrap();
+        assert_eq!(icmp_error_to_string(&error), "packet too big (MTU 1280) for [2001:db8::2]:53/UDP");
+        // Without a port the address is not enclosed in brackets
+        let mut quoted = ipv6_header(58);
+        quoted.extend_from_slice(&[128, 0, 0, 0, 0, 1, 0, 1]);
+        let message = icmp_message(1, 3, [0; 4], &quoted);
+        let error = parse_icmp_error(&Some(TransportSlice::Icmpv6(Icmpv6Slice::from_slice(&message).unwrap()))).unwrap();
+        assert_eq!(icmp_error_to_string(&error), "address unreachable for 2001:db8::2/Icmpv6");
+    }
+
+    /// Returns the fixed IPv6 header of a packet from 2001:db8::1 to 2001:db8::2, with `next_header` as first header.
+    fn ipv6_header(next_header: u8) -> Vec<u8> {
+        let mut header = vec![0x60, 0, 0, 0, 0, 0, next_header, 64];
+        header.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        header.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        header
+    }
+
+    #[test]
+    fn reads_ports_after_ipv6_extension_headers() {
+        let mut packet = ipv6_header(0);
+        // Hop-by-Hop Options of 8 bytes, followed by a Fragment header of the first fragment and a UDP header
+        packet.extend_from_slice(&[44, 0, 1, 4, 0, 0, 0, 0]);
+        packet.extend_from_slice(&[17, 0, 0, 1, 0, 0, 0, 1]);
+        packet.extend_from_slice(&[0x9c, 0x40, 0x00, 0x35, 0, 8, 0, 0]);
+        let quoted = parse_quoted_packet(&packet).unwrap();
+        assert_eq!(quoted.protocol, "UDP");
+        assert_eq!(quoted.source_port.as_deref(), Some("40000"));
+        assert_eq!(quoted.destination_port.as_deref(), Some("53"));
+    }
+
+    #[test]
+    fn reads_ports_after_ipv6_authentication_header() {
+        let mut packet = ipv6_header(51);
+        // Authentication Header of 24 bytes, followed by a TCP header
+        packet.extend_from_slice(&[6, 4, 0, 0]);
+        packet.extend_from_slice(&[0; 20]);
+        packet.extend_from_slice(&[0x01, 0xbb, 0x9c, 0x40, 0, 0, 0, 0]);
+        let quoted = parse_quoted_packet(&packet).unwrap();
+        assert_eq!(quoted.protocol, "TCP");
+        assert_eq!(quoted.source_port.as_deref(), Some("443"));
+        assert_eq!(quoted.destination_port.as_deref(), Some("40000"));
+    }
+
+    fn echo(is_request: bool, seq: u16) -> EchoMessage {
+        EchoMessage{is_request, header: IcmpEchoHeader{id: 1, seq}}
+    }
+
+    #[test]
+    fn pings_are_counted_when_answered_or_lost() {
+        let mut tracker = PingTracker::new(1000);
+        tracker.register("10.0.0.1", "10.0.0.2", &echo(true, 1), 0);
+        tracker.register("10.0.0.1", "10.0.0.2", &echo(true, 2), 500_000);
+        // The first report is written before any reply or timeout
+        tracker.expire_pending(900_000);
+        assert!(tracker.stats().is_empty());
+        tracker.reset_stats();
+        tracker.register("10.0.0.2", "10.0.0.1", &echo(false, 2), 510_000);
+        tracker.expire_pending(1_200_000);
+        let stats = &tracker.stats()[&("10.0.0.1".to_string(), "10.0.0.2".to_string())];
+        assert_eq!((stats.requests, stats.replies, stats.lost), (2, 1, 1));
+        assert_eq!(stats.average_us(), 10_000);
+        assert_eq!(stats.loss_percent(), 50.0);
+    }
+
+    #[test]
+    fn huge_timeout_does_not_overflow() {
+        let mut tracker = PingTracker::new(u64::MAX);
+        tracker.register("10.0.0.1", "10.0.0.2", &echo(true, 1), 0);
+        tracker.expire_pending(u64::MAX);
+        assert!(tracker.stats().is_empty());
+    }
+
+    #[test]
+    fn later_fragments_have_no_ports() {
+        let mut packet = ipv6_header(44);
+        packet.extend_from_slice(&[17, 0, 0, 0x08, 0, 0, 0, 1]);
+        packet.extend_from_slice(&[0x9c, 0x40, 0x00, 0x35, 0, 8, 0, 0]);
+        let quoted = parse_quoted_packet(&packet).unwrap();
+        assert_eq!(quoted.protocol, "UDP");
+        assert!(quoted.source_port.is_none());
+    }
+}
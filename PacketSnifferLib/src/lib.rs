@@ -23,8 +23,68 @@ pub mod report;
 /// Mod containing a struct to manage most common errors.
 pub mod errors;
 
+/// Mod containing the struct with the settings of a capture.
+pub mod options;
 
-use etherparse::{SlicedPacket};
+/// Mod containing structs and functions that analyze the observed DNS messages.
+pub mod dns;
+
+/// Mod containing structs and functions that decode the TLS handshake messages.
+pub mod tls;
+
+/// Mod containing structs and functions that extract the metadata of plaintext HTTP/1.x messages.
+pub mod http;
+
+/// Mod containing structs and functions that detect QUIC and decrypt the ClientHello of its Initial packets.
+pub mod quic;
+
+/// Mod containing structs and functions that decode DHCPv4 and DHCPv6 and keep the table of the leases.
+pub mod dhcp;
+
+/// Mod containing lightweight decoders of the UDP management protocols: NTP, SNMP and syslog.
+pub mod management;
+
+/// Mod containing structs and functions that decode the ICMP and ICMPv6 messages.
+pub mod icmp;
+
+/// Mod containing structs and functions that decode the ICMPv6 Neighbor Discovery messages and keep the table of the IPv6 neighbors.
+pub mod ndp;
+
+/// Mod containing structs and functions that decode IGMP and MLD and keep the membership of the multicast groups.
+pub mod multicast;
+
+/// Mod containing parsers of the transport protocols that are not decoded by etherparse: SCTP and DCCP.
+pub mod transport;
+
+/// Mod containing structs and functions that decode LLDP and CDP advertisements and keep the table of the neighbors.
+pub mod discovery;
+
+/// Mod containing structs and functions that identify IPsec (ESP, AH, IKE) and WireGuard traffic and aggregate it per security association.
+pub mod vpn;
+
+/// Mod containing structs and functions that decode the radiotap headers and the 802.11 frames captured in monitor mode.
+pub mod wifi;
+
+/// Mod containing structs and functions that recognize the tunnels and extract the packets they carry.
+pub mod tunnel;
+
+/// Mod containing the reassembly of the byte streams of the TCP connections.
+pub mod stream;
+
+/// Mod containing the engine that recognizes the application protocol of a flow from its payload.
+pub mod classify;
+
+/// Mod containing the functions that rank the flows of a report and summarize its top talkers.
+pub mod summary;
+
+/// Mod containing structs and functions that aggregate the traffic per host and per subnet.
+pub mod rollup;
+
+/// Mod containing the struct that groups the stateful tables built during a capture.
+pub mod trackers;
+
+
+use etherparse::{SlicedPacket, TransportSlice};
 use pcap::{Capture, Device, Packet};
 use std::collections::{HashMap};
 use std::sync::{Arc, Condvar, Mutex};
@@ -34,12 +94,35 @@ use std::string::String;
 use std::time::Duration;
 use report::*;
 use crate::errors::PacketSnifferError;
+use crate::options::{AggregationOptions, CaptureOptions, EvictionPolicy, FlowOrder, FlowTimeouts, ReportMode};
+use crate::rollup::Rollup;
+use crate::trackers::Trackers;
+use crate::dns::{is_multicast_dns, take_tcp_dns_messages, PassiveDnsEntry};
+use crate::tls::{reassemble_tls_handshake, TlsReassembly};
+use crate::http::parse_http;
+use crate::quic::{is_quic, is_version_negotiation, parse_quic_datagram};
+use crate::management::parse_management;
+use crate::classify::AppSignature;
+use crate::icmp::{icmp_error_to_string, parse_echo, parse_icmp_error};
+use crate::ndp::{ndp_info_to_string, parse_ndp, NeighborEntry, RouterInfo};
+use crate::multicast::{membership_message_to_string, parse_igmp, parse_mld, MulticastGroup};
+use crate::transport::parse_other_transport;
+use crate::discovery::{parse_discovery, DiscoveryNeighbor};
+use crate::vpn::{parse_vpn, vpn_message_to_string, SecurityAssociation};
+use crate::wifi::{parse_80211, parse_radiotap, BssEntry, StationEntry, LINKTYPE_IEEE802_11, LINKTYPE_IEEE802_11_RADIOTAP};
+use crate::tunnel::{decapsulate, InnerPacket, TunnelInfo};
+use crate::stream::{StreamKey, TcpSegment};
+use crate::dhcp::{dhcp_info_to_string, parse_dhcp, DhcpLease, DHCPV4_SERVER_PORT, DHCPV6_SERVER_PORT};
 ///Struct useful to manage info about sniffed packages and to control the capture flow.
 pub struct PacketCatcher{
+    /// Settings applied to the next capture
+    options: CaptureOptions,
     /// Arc that has a condition variable and a Mutex with the value to stop and resume the capture process
     cv_m: Arc<(Condvar,Mutex<bool>)>,
     /// Field that contains an ```Arc< Mutex<HashMap<AddressPortPair, Report>>>``` object used in order to contain packets informations.
     report_map: Arc< Mutex<HashMap<AddressPortPair, Report>>>,
+    /// Field that contains an ```Arc<Mutex<Trackers>>``` object with the tables built from the observed traffic.
+    trackers: Arc<Mutex<Trackers>>,
     /// Arc that contains a mutex that has a value to stop the capturing process
     stop: Arc<Mutex<bool>>,
     /// `Option<JoinHandle<()>>` relative to capture process.
@@ -53,7 +136,103 @@ impl PacketCatcher {
     /// Create a new  PacketCatcher struct.
     pub fn new() -> PacketCatcher {
         let report_map = Arc::new(Mutex::new(HashMap::new()));
-        PacketCatcher{cv_m: Arc::new((Condvar::new(), Mutex::new(false))), report_map, stop: Arc::new(Mutex::new(false)), h_cap: None, h_write: None}
+        let options = CaptureOptions::default();
+        let trackers = Arc::new(Mutex::new(Trackers::new(&options)));
+        PacketCatcher{options, cv_m: Arc::new((Condvar::new(), Mutex::new(false))), report_map, trackers, stop: Arc::new(Mutex::new(false)), h_cap: None, h_write: None}
+    }
+
+    /// Sets the ports, besides 53 (DNS), 5353 (mDNS) and 5355 (LLMNR), on which UDP and TCP traffic is decoded as DNS.
+    /// It has to be called before `capture`.
+    pub fn set_extra_dns_ports(&mut self, ports: Vec<u16>) {
+        self.options.extra_dns_ports = ports;
+    }
+
+    /// Sets the ports on which WireGuard transport data is recognized also when the handshake of its session was not
+    /// captured, e.g. 51820. On the other ports, transport data is recognized only after a handshake.
+    /// It has to be called before `capture`.
+    pub fn set_wireguard_ports(&mut self, ports: Vec<u16>) {
+        self.options.wireguard_ports = ports;
+    }
+
+    /// Sets the time in milliseconds after which a DNS query without response is counted as unanswered.
+    /// It has to be called before `capture`.
+    pub fn set_dns_timeout(&mut self, timeout_ms: u64) {
+        self.options.dns_timeout_ms = timeout_ms;
+    }
+
+    /// Sets the time in milliseconds after which an ICMP echo request without reply is counted as lost.
+    /// It has to be called before `capture`.
+    pub fn set_ping_timeout(&mut self, timeout_ms: u64) {
+        self.options.ping_timeout_ms = timeout_ms;
+    }
+
+    /// Sets the number of entries listed in the ranked sections of each report, e.g. the slowest DNS names or the top talkers
+    /// of the summary.
+    /// It has to be called before `capture`.
+    pub fn set_top_n(&mut self, top_n: usize) {
+        self.options.top_n = top_n;
+    }
+
+    /// Sets the number of payload bytes inspected in each direction of a flow to recognize its application protocol.
+    /// It has to be called before `capture`.
+    pub fn set_inspected_bytes(&mut self, bytes: usize) {
+        self.options.inspected_bytes = bytes;
+    }
+
+    /// Enables or disables the decapsulation of GRE, VXLAN, Geneve, IP-in-IP and MPLS. When it is enabled, which is the default,
+    /// the flows carried by a tunnel are reported in place of the tunnel itself, together with its metadata.
+    /// It has to be called before `capture`.
+    pub fn set_tunnel_decapsulation(&mut self, enabled: bool) {
+        self.options.decapsulate_tunnels = enabled;
+    }
+
+    /// Enables the flow cache with the given timeouts, or disables it with None, which is the default. With the flow cache
+    /// each report contains the flows that ended since the previous one: a flow is exported when it is idle for longer than
+    /// the idle timeout, when it is active for longer than the active timeout, or when its TCP connection is closed or reset.
+    /// The timeouts are measured from the timestamp of the last captured packet.
+    /// Without it, each report contains all the flows of the last interval.
+    /// It has to be called before `capture`.
+    pub fn set_flow_cache(&mut self, timeouts: Option<FlowTimeouts>) {
+        self.options.flow_timeouts = timeouts;
+    }
+
+    /// Sets which flows are written in each report: the ones of the last interval, which is the default, the ones since the
+    /// start of the capture with their running totals, or both in two separate parts of the report. The running totals keep
+    /// every flow of the capture, so they grow without limit unless `set_max_flows` is also called. The other sections
+    /// (DNS, traffic per host and subnet, ping, multicast) always refer to the last interval.
+    /// It has to be called before `capture`.
+    pub fn set_report_mode(&mut self, mode: ReportMode) {
+        self.options.report_mode = mode;
+    }
+
+    /// Sets the maximum number of flows kept in each report, or removes the limit with None, which is the default.
+    /// When the limit is reached, the flows chosen by `policy` are evicted and their traffic is aggregated in an "other"
+    /// bucket, written at the end of the flows together with the number of evictions in the report header.
+    /// It has to be called before `capture`.
+    pub fn set_max_flows(&mut self, max_flows: Option<usize>, policy: EvictionPolicy) {
+        self.options.max_flows = max_flows.map(|max| max.max(1));
+        self.options.eviction_policy = policy;
+    }
+
+    /// Sets the order of the flows listed in each report: by address, which is the default, by bytes, by packets or by first
+    /// timestamp. The flows that are equal for the chosen order are listed by address, so the order is always deterministic.
+    /// It has to be called before `capture`.
+    pub fn set_flow_order(&mut self, order: FlowOrder) {
+        self.options.flow_order = order;
+    }
+
+    /// Sets the views of the traffic aggregated per host and per subnet, each written in its own section of the reports.
+    /// The subnets are the ones of the given prefix lengths and the user-defined ones, e.g. "office=10.1.0.0/16".
+    /// By default no view is enabled.
+    /// It has to be called before `capture`.
+    pub fn set_aggregation(&mut self, aggregation: AggregationOptions) {
+        self.options.aggregation = aggregation;
+    }
+
+    /// Adds a user-defined signature, which is checked before the built-in ones when classifying the flows.
+    /// It has to be called before `capture`.
+    pub fn add_signature(&mut self, signature: AppSignature) {
+        self.options.signatures.push(signature);
     }
 
     /// Performs packets capture packet by packet on a specific device. It takes as parameter also the name
@@ -92,9 +271,13 @@ impl PacketCatcher {
                 Err(e) => {return Err(PacketSnifferError::InvalidFilter(filter.unwrap(), e.to_string()))}
             };
         }
+        let linktype = cap.get_datalink().0;
         let is_blocked = Arc::clone(&self.cv_m);
         let arc_map = Arc::clone(&self.report_map);
         let stop_capture = Arc::clone(&self.stop);
+        let options = self.options.clone();
+        *self.trackers.lock().unwrap() = Trackers::new(&self.options);
+        let arc_trackers = Arc::clone(&self.trackers);
         let h = thread::spawn(move || {
             let _i = 0;
             'outer: loop {
@@ -123,7 +306,8 @@ impl PacketCatcher {
                     Ok(packet) => {
 
                         let mut map = arc_map.lock().unwrap();
-                        parse_packet(packet, &mut map);
+                        let mut trackers = arc_trackers.lock().unwrap();
+                        parse_packet(packet, linktype, &mut map, &mut trackers, &options);
 
                     },
                     _ => {}
@@ -136,6 +320,8 @@ impl PacketCatcher {
         let arc_map_2 = Arc::clone(&self.report_map);
         let is_blocked_write = Arc::clone(&self.cv_m);
         let stop_capture_w = Arc::clone(&self.stop);
+        let arc_trackers_2 = Arc::clone(&self.trackers);
+        let options_w = self.options.clone();
         let h_write = thread::spawn(move || {
             loop {
                 {
@@ -155,7 +341,8 @@ impl PacketCatcher {
                 }
                 thread::sleep(Duration::from_millis(interval));
                 let mut map = arc_map_2.lock().unwrap();
-                PacketCatcher::empty_report(&mut *map, &filename);
+                let mut trackers = arc_trackers_2.lock().unwrap();
+                PacketCatcher::empty_report(&mut map, &mut trackers, &filename, &options_w);
             }
         });
         self.h_write = Some(h_write);
@@ -174,6 +361,90 @@ impl PacketCatcher {
         cvar.notify_all();
     }
 
+    /// Returns the hostnames that the observed DNS answers associate to `address`, the most recent first.
+    /// Only the records whose TTL is not expired are considered.
+    pub fn lookup_hostnames(&self, address: &str) -> Vec<String> {
+        let trackers = self.trackers.lock().unwrap();
+        trackers.passive_dns.lookup(address, trackers.now_us / 1_000_000)
+    }
+
+    /// Returns the addresses that `hostname` resolves to according to the observed DNS answers.
+    pub fn lookup_addresses(&self, hostname: &str) -> Vec<String> {
+        let trackers = self.trackers.lock().unwrap();
+        trackers.passive_dns.lookup_addresses(hostname, trackers.now_us / 1_000_000)
+    }
+
+    /// Returns a copy of the passive DNS cache, containing the hostnames learned for each address.
+    pub fn passive_dns_entries(&self) -> HashMap<String, Vec<PassiveDnsEntry>> {
+        self.trackers.lock().unwrap().passive_dns.entries().clone()
+    }
+
+    /// Returns the current DHCP lease of each device, mapping its hardware address to its address and hostname.
+    pub fn dhcp_leases(&self) -> Vec<DhcpLease> {
+        self.trackers.lock().unwrap().dhcp.current_leases()
+    }
+
+    /// Returns all the DHCP leases granted to the device with hardware address `mac` (e.g. "AA:BB:CC:DD:EE:FF"), the oldest first.
+    pub fn dhcp_lease_history(&self, mac: &str) -> Vec<DhcpLease> {
+        self.trackers.lock().unwrap().dhcp.history(mac)
+    }
+
+    /// Returns the device that currently holds address `ip` according to the observed DHCP messages.
+    pub fn device_for_ip(&self, ip: &str) -> Option<DhcpLease> {
+        self.trackers.lock().unwrap().dhcp.device_for_ip(ip)
+    }
+
+    /// Returns the IPv6 neighbors learned from the Neighbor Discovery messages, with their link-layer addresses.
+    pub fn ipv6_neighbors(&self) -> Vec<NeighborEntry> {
+        self.trackers.lock().unwrap().neighbors.neighbors()
+    }
+
+    /// Returns the IPv6 routers that sent a Router Advertisement, with the prefixes, MTU and DNS servers they advertise.
+    pub fn ipv6_routers(&self) -> Vec<RouterInfo> {
+        self.trackers.lock().unwrap().neighbors.routers()
+    }
+
+    /// Returns the multicast groups, with the hosts that joined them through IGMP or MLD and the traffic of the current interval.
+    pub fn multicast_groups(&self) -> Vec<MulticastGroup> {
+        self.trackers.lock().unwrap().multicast.groups()
+    }
+
+    /// Returns the multicast groups that `host` is currently a member of.
+    pub fn multicast_memberships(&self, host: &str) -> Vec<String> {
+        self.trackers.lock().unwrap().multicast.memberships(host)
+    }
+
+    /// Returns the switches and other devices that advertised themselves through LLDP or CDP, with the port, VLAN and
+    /// management addresses they advertised.
+    pub fn discovery_neighbors(&self) -> Vec<DiscoveryNeighbor> {
+        self.trackers.lock().unwrap().discovery.neighbors()
+    }
+
+    /// Returns the wireless stations seen in the 802.11 frames, with their traffic, BSSID and radio informations.
+    pub fn wifi_stations(&self) -> Vec<StationEntry> {
+        self.trackers.lock().unwrap().wifi.stations()
+    }
+
+    /// Returns the wireless networks seen in the 802.11 frames, with their SSID, channel, traffic and stations.
+    pub fn wifi_networks(&self) -> Vec<BssEntry> {
+        self.trackers.lock().unwrap().wifi.networks()
+    }
+
+    /// Returns the traffic of the current interval per host, if the per-host view is enabled.
+    pub fn host_rollups(&self) -> Vec<Rollup> {
+        self.trackers.lock().unwrap().rollups.hosts()
+    }
+
+    /// Returns the traffic of the current interval per subnet, if the per-subnet view is enabled.
+    pub fn subnet_rollups(&self) -> Vec<Rollup> {
+        self.trackers.lock().unwrap().rollups.subnets()
+    }
+
+    /// Returns the IPsec security associations, IKE SAs and WireGuard sessions observed, with their traffic.
+    pub fn security_associations(&self) -> Vec<SecurityAssociation> {
+        self.trackers.lock().unwrap().vpn.associations()
+    }
+
     /// The function stop definitely the packets capturing.
     pub fn stop_capture(&mut self){
         let stop_capture = Arc::clone(&self.stop);
@@ -187,12 +458,54 @@ impl PacketCatcher {
     }
 
 
-    /// The function is used in order to write on the text file the content of the parameter `map`.
-    /// It also clears the parameter HashMap to create a new report `HashMap<AddressPortPair, Report>`.
-    pub fn empty_report(map: &mut HashMap<AddressPortPair, Report>, filename: &str){
+    /// The function is used in order to write on the text file the content of the parameter `map`, together with the
+    /// sections built from `trackers`.
+    /// It also clears the parameter HashMap to create a new report `HashMap<AddressPortPair, Report>`. When the flow cache is
+    /// enabled, only the flows to be exported are written and removed. In the cumulative modes the written flows are
+    /// added to the running totals of `trackers`, each of them exactly once.
+    pub fn empty_report(map: &mut HashMap<AddressPortPair, Report>, trackers: &mut Trackers, filename: &str, options: &CaptureOptions){
         //println!("fatto");
-        write_file(filename, &*map).unwrap();
-        map.clear();
+        trackers.dns.expire_pending(trackers.now_us);
+        trackers.ping.expire_pending(trackers.now_us);
+        let delta: HashMap<AddressPortPair, Report> = match &options.flow_timeouts {
+            None => std::mem::take(map),
+            Some(timeouts) => {
+                // The timeouts are measured on the timestamps of the packets, which may differ from the clock of the host
+                let now = trackers.now_us / 1_000_000;
+                for report in map.values_mut() {
+                    report.check_export(now, timeouts);
+                }
+                let (exported, active): (HashMap<AddressPortPair, Report>, HashMap<AddressPortPair, Report>) =
+                    map.drain().partition(|(_, report)| report.export_reason().is_some());
+                map.extend(active);
+                exported
+            }
+        };
+        match options.report_mode {
+            ReportMode::Delta => write_file(filename, Some(&delta), None, None, trackers, options).unwrap(),
+            ReportMode::Cumulative => {
+                add_to_totals(trackers, delta, options);
+                write_file(filename, None, Some(&trackers.totals), Some(map), trackers, options).unwrap();
+            },
+            ReportMode::Both => {
+                add_to_totals(trackers, delta.iter().map(|(pair, report)| (pair.clone(), report.clone())).collect(), options);
+                write_file(filename, Some(&delta), Some(&trackers.totals), Some(map), trackers, options).unwrap();
+            }
+        }
+        trackers.overflow = OverflowBucket::default();
+        trackers.rollups.reset_stats();
+        trackers.dns.reset_stats();
+        trackers.ping.reset_stats();
+        trackers.passive_dns.purge_expired(trackers.now_us / 1_000_000);
+        trackers.dns_streams.purge_expired(trackers.now_us / 1_000_000);
+        trackers.tls_streams.purge_expired(trackers.now_us / 1_000_000);
+        trackers.multicast.purge_expired(trackers.now_us / 1_000_000);
+        trackers.vpn.purge_expired(trackers.now_us / 1_000_000);
+        trackers.discovery.purge_expired(trackers.now_us / 1_000_000);
+        trackers.dhcp.expire_leases(trackers.now_us / 1_000_000);
+        trackers.neighbors.purge_expired(trackers.now_us / 1_000_000);
+        trackers.wifi.purge_expired(trackers.now_us / 1_000_000);
+        trackers.multicast.reset_stats();
     }
 }
 
@@ -241,80 +554,432 @@ pub fn parse_network_adapter() -> Result<Vec<String>, PacketSnifferError> {
     Ok(vettore)
 }
 
-/// Takes as argument the `packet` to parse and saves it inside the `report_map`
-fn parse_packet(packet: Packet, report_map: &mut HashMap<AddressPortPair, Report>) {
+/// Adds the flows of `delta` to the running totals of `trackers`, merging the ones already present, together with the
+/// flows evicted in the interval. The totals are bounded by the same maximum number of flows as the report map.
+fn add_to_totals(trackers: &mut Trackers, delta: HashMap<AddressPortPair, Report>, options: &CaptureOptions) {
+    trackers.overflow_totals.merge(&trackers.overflow);
+    for (pair, report) in delta {
+        match trackers.totals.get_mut(&pair) {
+            Some(total) => total.merge(report),
+            None => {
+                if let Some(max_flows) = options.max_flows {
+                    evict_flows(&mut trackers.totals, max_flows, options.eviction_policy, &mut trackers.overflow_totals);
+                }
+                trackers.totals.insert(pair, report);
+            }
+        }
+    }
+}
+
+/// Maximum number of nested tunnels that are decapsulated.
+const MAX_TUNNEL_DEPTH: usize = 4;
+
+/// Struct containing the informations of a captured frame that are shared by the packets encapsulated in it.
+struct PacketContext {
+    /// Timestamp of the frame, in microseconds
+    ts_us: u64,
+    /// Bytes of the packet on the wire, without the headers of the tunnels that carried it
+    bytes: u32,
+    /// Link layer of the outer frame, used for the packets encapsulated without an Ethernet header
+    link: Option<LinkInfo>,
+    /// Tunnels that carried the packet, the outermost first
+    tunnels: Vec<TunnelInfo>,
+}
+
+/// Takes as argument the `packet` to parse, captured on a link of type `linktype`, and saves it inside the `report_map`
+fn parse_packet(packet: Packet, linktype: i32, report_map: &mut HashMap<AddressPortPair, Report>, trackers: &mut Trackers, options: &CaptureOptions) {
+    let ts_us = packet.header.ts.tv_sec as u64 * 1_000_000 + packet.header.ts.tv_usec as u64;
+    trackers.advance_clock(ts_us);
+    if linktype == LINKTYPE_IEEE802_11_RADIOTAP || linktype == LINKTYPE_IEEE802_11 {
+        parse_wifi_packet(&packet, linktype == LINKTYPE_IEEE802_11_RADIOTAP, ts_us, report_map, trackers, options);
+        return;
+    }
 
     match SlicedPacket::from_ethernet(&packet) {
         Err(value) => println!("Err {:?}", value),
         Ok(value) => {
-            let transport_level = parse_transport(value.transport);
-            let network_level = parse_network(value.ip);
-            let link_level = parse_link(value.link);
-            let mut dns_string = "".to_owned();
-
-            if transport_level.is_some() && network_level.is_some() && link_level.is_some() {
-
-
-                let tl = transport_level.unwrap();
-                let nl = network_level.unwrap();
-                let ll = link_level.unwrap();
-                let first_port = match tl.source_port {
-                    Some(port) => port,
-                    None => "No port".to_string()
-                };
-                let second_port = match tl.destination_port {
-                    Some(port) => port,
-                    None => "No port".to_string()
-                };
-
-
-                if tl.protocol == "UDP" &&  (first_port == "53" || second_port=="53") {
-                    match simple_dns::Packet::parse(&value.payload){
-                        Err(value1) => {
-                          if value1.to_string() != "Provided QType is invalid: 65" {
-                                println!("{:?}", value1.to_string())
-                            }
-                        },
-                        Ok(value1) => {
-                            let application_level =  parse_dns(Some(value1));
+            let context = PacketContext{ts_us, bytes: packet.header.len, link: None, tunnels: Vec::new()};
+            parse_sliced_packet(value, packet.data.len(), context, report_map, trackers, options);
+        }
+    }
+}
 
-                            dns_string = dns_info_to_string( application_level);
+/// Takes as argument an 802.11 `packet`, preceded by a radiotap header if `radiotap` is true, registers it in the table of
+/// the wireless stations and saves the unencrypted packet it carries inside the `report_map`.
+fn parse_wifi_packet(packet: &Packet, radiotap: bool, ts_us: u64, report_map: &mut HashMap<AddressPortPair, Report>, trackers: &mut Trackers, options: &CaptureOptions) {
+    let frame = if radiotap {
+        parse_radiotap(packet.data).and_then(|(radio, data)| parse_80211(data, Some(radio)))
+    } else {
+        parse_80211(packet.data, None)
+    };
+    let frame = match frame {
+        Some(frame) => frame,
+        None => return
+    };
+    trackers.wifi.register(&frame, packet.header.len, ts_us / 1_000_000);
+    if let (Some((ether_type, data)), Some(source)) = (frame.payload, frame.source) {
+        if let Ok(value) = SlicedPacket::from_ether_type(ether_type, data) {
+            let link = LinkInfo{source_mac: source, destination_mac: frame.destination, ether_type: ether_type.to_string()};
+            let bytes = packet.header.len.saturating_sub((packet.data.len() - data.len()) as u32);
+            let context = PacketContext{ts_us, bytes, link: Some(link), tunnels: Vec::new()};
+            parse_sliced_packet(value, data.len(), context, report_map, trackers, options);
+        }
+    }
+}
+
+/// Takes as argument a packet already sliced, with `data_len` captured bytes, and saves it inside the `report_map`.
+/// When the packet is a tunnel, the packet it carries is parsed in its place.
+fn parse_sliced_packet(value: SlicedPacket, data_len: usize, mut context: PacketContext, report_map: &mut HashMap<AddressPortPair, Report>, trackers: &mut Trackers, options: &CaptureOptions) {
+    let decapsulated = if options.decapsulate_tunnels && context.tunnels.len() < MAX_TUNNEL_DEPTH {
+        decapsulate(&value)
+    } else {
+        None
+    };
+    let icmp_error = parse_icmp_error(&value.transport);
+    let vpn = parse_vpn(&value, &trackers.vpn, &options.wireguard_ports);
+    let discovery = parse_discovery(&value);
+    let tcp_segment = match &value.transport {
+        Some(TransportSlice::Tcp(header)) => Some(TcpSegment{sequence: header.sequence_number(), syn: header.syn(), fin: header.fin(), rst: header.rst()}),
+        _ => None
+    };
+    let ndp = parse_ndp(&value.transport);
+    let echo = parse_echo(&value.transport);
+    let membership = parse_igmp(&value.transport, value.payload).or_else(|| parse_mld(&value.transport));
+    let (transport_level, transport_summary) = match parse_other_transport(&value.transport, value.payload) {
+        Some((info, summary)) => (Some(info), Some(summary)),
+        None => (parse_transport(value.transport), None)
+    };
+    let network_level = parse_network(value.ip);
+    let link_level = parse_link(value.link).or_else(|| context.link.clone());
+
+    if let Some((tunnel, inner)) = decapsulated {
+        let inner_len = inner.data().len();
+        let sliced = match inner {
+            InnerPacket::Ethernet(data) => SlicedPacket::from_ethernet(data),
+            InnerPacket::Ip(data) => SlicedPacket::from_ip(data)
+        };
+        if let Ok(inner_value) = sliced {
+            context.bytes = context.bytes.saturating_sub((data_len - inner_len) as u32);
+            context.link = link_level;
+            context.tunnels.push(tunnel);
+            parse_sliced_packet(inner_value, inner_len, context, report_map, trackers, options);
+            return;
+        }
+    }
+
+    if let (Some(discovery), Some(ll)) = (discovery, &link_level) {
+        trackers.discovery.register(discovery, ll.source_mac, context.ts_us / 1_000_000);
+        return;
+    }
+
+    let mut dns_string = "".to_owned();
+
+    if transport_level.is_some() && network_level.is_some() && link_level.is_some() {
+
+
+        let tl = transport_level.unwrap();
+        let nl = network_level.unwrap();
+        let ll = link_level.unwrap();
+        let ts_us = context.ts_us;
+        let first_port = match tl.source_port {
+            Some(port) => port,
+            None => "No port".to_string()
+        };
+        let second_port = match tl.destination_port {
+            Some(port) => port,
+            None => "No port".to_string()
+        };
+
+
+        if (tl.protocol == "UDP" || tl.protocol == "TCP") &&
+            (is_dns_port(&first_port, &options.extra_dns_ports) || is_dns_port(&second_port, &options.extra_dns_ports)) {
+            // Over TCP the messages are length prefixed, a segment can contain more than one of them and a message
+            // can span more than one segment
+            let messages = match tcp_segment {
+                Some(segment) => {
+                    let key = StreamKey{source: format!("{} {}", nl.source_address, first_port), destination: format!("{} {}", nl.destination_address, second_port)};
+                    let messages = match trackers.dns_streams.register(key.clone(), segment, value.payload, ts_us / 1_000_000) {
+                        Some(stream) => {
+                            let (messages, truncated) = take_tcp_dns_messages(stream);
+                            trackers.dns.register_truncated(truncated);
+                            messages
+                        },
+                        None => Vec::new()
+                    };
+                    if segment.fin || segment.rst {
+                        trackers.dns_streams.close(&key);
+                    }
+                    messages
+                },
+                None => vec![value.payload.to_vec()]
+            };
+            for message in messages {
+                match simple_dns::Packet::parse(&message){
+                    Err(value1) => {
+                        if value1.to_string() != "Provided QType is invalid: 65" {
+                            println!("{:?}", value1.to_string())
+                        }
+                    },
+                    Ok(value1) => {
+                        let application_level =  parse_dns(Some(value1));
+                        if let Some(info) = &application_level {
+                            if !is_multicast_dns(&first_port, &second_port, info.is_response) {
+                                trackers.dns.register(&nl.source_address, &nl.destination_address, info, ts_us);
+                            }
+                            trackers.passive_dns.register(info, ts_us / 1_000_000);
                         }
+
+                        dns_string.push_str(&dns_info_to_string( application_level));
                     }
                 }
+            }
+        }
 
-                let pair = AddressPortPair::new(
-                    nl.source_address,
-                    first_port,
-                    nl.destination_address,
-                    second_port,
-                );
-
-                let icmp_string = match tl.icmp_type {
-                    Some(icmp) => icmp,
-                    None => "".to_string()
-                };
-
-                let ts = packet.header.ts;
-                let bytes: u32 = packet.header.len;
-                report_map.entry(pair)
-                    .and_modify(|val| val.update_report(ts.tv_sec.unsigned_abs().into(),
-                                              bytes,
-                                              tl.protocol.clone(),
-                                              nl.protocol.clone(),
-                                              ll.clone(),
-                                              icmp_string.clone(),
-                                              dns_string.clone().to_string()))
-                    .or_insert(Report::new(
-                    ts.tv_sec.unsigned_abs().into(),
-                    bytes,
-                    tl.protocol.clone(),
-                    nl.protocol.clone(),
-                    ll.clone(),
-                    icmp_string.clone(),
-                    dns_string.clone().to_string()
-                ));
+        let pair = AddressPortPair::new(
+            nl.source_address.clone(),
+            first_port.clone(),
+            nl.destination_address.clone(),
+            second_port.clone(),
+        );
+
+        let mut icmp_string = match tl.icmp_type {
+            Some(icmp) => icmp,
+            None => "".to_string()
+        };
+        if let Some(error) = &icmp_error {
+            icmp_string.push_str("; ");
+            icmp_string.push_str(&icmp_error_to_string(error));
+        }
+        if let Some(echo) = &echo {
+            trackers.ping.register(&nl.source_address, &nl.destination_address, echo, ts_us);
+        }
+        if let Some(ndp) = &ndp {
+            trackers.neighbors.register(ndp, &nl.source_address, ll.source_mac, ts_us / 1_000_000);
+            icmp_string.push_str("; ");
+            icmp_string.push_str(&ndp_info_to_string(ndp));
+        }
+
+        let ts_sec = ts_us / 1_000_000;
+        let bytes: u32 = context.bytes;
+        if let Some(max_flows) = options.max_flows {
+            if !report_map.contains_key(&pair) {
+                evict_flows(report_map, max_flows, options.eviction_policy, &mut trackers.overflow);
             }
         }
+        let report = report_map.entry(pair)
+            .and_modify(|val| val.update_report(ts_sec,
+                                      bytes,
+                                      tl.protocol.clone(),
+                                      nl.protocol.clone(),
+                                      ll.clone(),
+                                      icmp_string.clone(),
+                                      dns_string.clone().to_string()))
+            .or_insert(Report::new(
+            ts_sec,
+            bytes,
+            tl.protocol.clone(),
+            nl.protocol.clone(),
+            ll.clone(),
+            icmp_string.clone(),
+            dns_string.clone().to_string()
+        ));
+
+        report.add_tunnels(&context.tunnels);
+        if let Some(segment) = tcp_segment {
+            report.add_tcp_flags(format!("{} {}", nl.source_address, first_port), segment.fin, segment.rst);
+        }
+        if let Some(summary) = transport_summary {
+            report.add_transport_info(summary);
+        }
+        trackers.multicast.add_traffic(&nl.destination_address, bytes);
+        trackers.rollups.register((&nl.source_address, &first_port), (&nl.destination_address, &second_port), bytes, &options.aggregation);
+        if let Some(vpn) = &vpn {
+            trackers.vpn.register(vpn, &nl.source_address, &nl.destination_address, bytes, ts_sec);
+            report.add_application_info(vpn_message_to_string(vpn));
+        }
+        if let Some(membership) = &membership {
+            trackers.multicast.register(membership, &nl.source_address, ts_sec);
+            report.add_application_info(membership_message_to_string(membership));
+        }
+        report.classify_payload(format!("{} {}", nl.source_address, first_port), &tl.protocol, &first_port, &second_port, value.payload, options);
+        if let Some(segment) = tcp_segment {
+            let key = StreamKey{source: format!("{} {}", nl.source_address, first_port), destination: format!("{} {}", nl.destination_address, second_port)};
+            match reassemble_tls_handshake(&mut trackers.tls_streams, key, segment, value.payload, ts_sec) {
+                TlsReassembly::Complete(handshake) => report.add_tls_handshake(&handshake, 't'),
+                TlsReassembly::Lost => report.mark_tls_incomplete(),
+                TlsReassembly::Incomplete => {},
+                TlsReassembly::NotHandshake => {
+                    if let Some(message) = parse_http(value.payload) {
+                        report.add_http_message(message, &format!("{}:{}", nl.source_address, first_port));
+                    }
+                }
+            }
+        }
+        if tl.protocol == "UDP" {
+            if let Some(dhcp) = parse_dhcp(&first_port, &second_port, value.payload) {
+                // Messages sent by the server are addressed to the client
+                let server_ports = [DHCPV4_SERVER_PORT.to_string(), DHCPV6_SERVER_PORT.to_string()];
+                let client_mac = if server_ports.contains(&first_port) { ll.destination_mac } else { ll.source_mac };
+                trackers.dhcp.register(&dhcp, client_mac, ts_us / 1_000_000);
+                report.add_application_info(dhcp_info_to_string(&dhcp));
+            } else if let Some(summary) = parse_management(&first_port, &second_port, value.payload) {
+                report.add_application_info(summary);
+            }
+        }
+        if tl.protocol == "UDP" && (is_quic(value.payload) || (report.quic_info().is_some() && is_version_negotiation(value.payload))) {
+            if let Some(datagram) = parse_quic_datagram(value.payload, &mut trackers.quic) {
+                report.add_quic_datagram(&datagram);
+            }
+        }
+        // The error is also shown in the flow of the packet that triggered it, if the flow is in the current report
+        if let Some(error) = icmp_error {
+            let original = AddressPortPair::new(
+                error.original.source_address.clone(),
+                error.original.source_port.clone().unwrap_or_else(|| "No port".to_string()),
+                error.original.destination_address.clone(),
+                error.original.destination_port.clone().unwrap_or_else(|| "No port".to_string()),
+            );
+            if let Some(original_report) = report_map.get_mut(&original) {
+                original_report.add_icmp_error(format!("{}, reported by {}", icmp_error_to_string(&error), nl.source_address));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::PacketBuilder;
+    use crate::tunnel::VXLAN_PORT;
+
+    fn pair(client_port: &str) -> AddressPortPair {
+        AddressPortPair::new("10.0.0.1".to_string(), client_port.to_string(), "10.0.0.2".to_string(), "80".to_string())
+    }
+
+    fn flow(ts: u64, bytes: u32) -> Report {
+        let link = LinkInfo{source_mac: [0; 6], destination_mac: [0; 6], ether_type: "Ipv4".to_string()};
+        Report::new(ts, bytes, "TCP".to_string(), "IPv4".to_string(), link, String::new(), String::new())
+    }
+
+    /// Returns the path of a report file in the temporary directory, removing the file of a previous run.
+    fn report_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(name).to_string_lossy().to_string();
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Runs two intervals of the flow cache in `mode`, returning the last report written and the trackers. Flow 40000 is
+    /// exported in the first interval and active again in the second, flow 40001 is active in both and flow 40002 is
+    /// exported in the second.
+    fn two_intervals(mode: ReportMode, filename: &str) -> (String, Trackers) {
+        let options = CaptureOptions{flow_timeouts: Some(FlowTimeouts{idle_s: 30, active_s: 120}), report_mode: mode, ..Default::default()};
+        let mut trackers = Trackers::new(&options);
+        let mut map = HashMap::new();
+        map.insert(pair("40000"), flow(60, 500));
+        map.insert(pair("40001"), flow(100, 300));
+        trackers.now_us = 100_000_000;
+        PacketCatcher::empty_report(&mut map, &mut trackers, filename, &options);
+        map.insert(pair("40000"), flow(195, 200));
+        map.get_mut(&pair("40001")).unwrap().update_report(190, 100, "TCP".to_string(), "IPv4".to_string(), LinkInfo{source_mac: [0; 6], destination_mac: [0; 6], ether_type: "Ipv4".to_string()}, String::new(), String::new());
+        map.insert(pair("40002"), flow(150, 50));
+        trackers.now_us = 200_000_000;
+        PacketCatcher::empty_report(&mut map, &mut trackers, filename, &options);
+        let content = std::fs::read_to_string(filename).unwrap();
+        let _ = std::fs::remove_file(filename);
+        let last = content.rsplit("NEW REPORT").next().unwrap().to_string();
+        (last, trackers)
+    }
+
+    /// Returns the sum of the bytes and of the packets of the flows written in the totals section of `report`.
+    fn written_totals(report: &str) -> (u64, u64) {
+        let section = report.split("-----Totals since the start of the capture-----").nth(1).unwrap();
+        let section = section.split("-----Statistics of the last interval-----").next().unwrap();
+        let sum = |label: &str| section.lines().filter_map(|line| line.strip_prefix(label)).map(|n| n.trim().parse::<u64>().unwrap()).sum();
+        (sum("Total bytes: "), sum("Total packets: "))
+    }
+
+    #[test]
+    fn active_flows_are_written_with_the_totals_once() {
+        for (mode, name) in [(ReportMode::Cumulative, "cumulative"), (ReportMode::Both, "both")] {
+            let (report, trackers) = two_intervals(mode, &report_file(&format!("packet_sniffer_{}_test.txt", name)));
+            // Flow 40000 is written as 500 + 200 bytes, flow 40001 as 400 bytes and flow 40002 as 50 bytes
+            assert_eq!(written_totals(&report), (1150, 5));
+            // The active flows are not added to the totals
+            assert_eq!(trackers.totals.len(), 2);
+            assert_eq!(trackers.totals[&pair("40000")].total_bytes(), 500);
+            assert_eq!(trackers.totals[&pair("40002")].total_packets(), 1);
+        }
+        let (report, _) = two_intervals(ReportMode::Cumulative, &report_file("packet_sniffer_cumulative_summary_test.txt"));
+        assert!(report.contains("Flows: 3; Total bytes: 1150; Total packets: 5\n"));
+    }
+
+    #[test]
+    fn header_counts_the_evictions_of_the_written_flows() {
+        for (mode, header) in [(ReportMode::Delta, "Evicted flows: 2\n"), (ReportMode::Cumulative, "Evicted flows: 9\n"), (ReportMode::Both, "Evicted flows: 2; Since the start of the capture: 9\n")] {
+            let options = CaptureOptions{max_flows: Some(10), report_mode: mode, ..Default::default()};
+            let mut trackers = Trackers::new(&options);
+            trackers.overflow.evictions = 2;
+            trackers.overflow_totals.evictions = 7;
+            let filename = report_file("packet_sniffer_evictions_test.txt");
+            PacketCatcher::empty_report(&mut HashMap::new(), &mut trackers, &filename, &options);
+            let content = std::fs::read_to_string(&filename).unwrap();
+            let _ = std::fs::remove_file(&filename);
+            assert!(content.contains(header));
+        }
+    }
+
+    #[test]
+    fn flow_cache_keeps_the_active_flows() {
+        let options = CaptureOptions{flow_timeouts: Some(FlowTimeouts{idle_s: 30, active_s: 120}), report_mode: ReportMode::Cumulative, ..Default::default()};
+        let mut trackers = Trackers::new(&options);
+        trackers.now_us = 100_000_000;
+        let mut map = HashMap::new();
+        map.insert(pair("40000"), flow(100, 500));
+        map.insert(pair("40001"), flow(60, 300));
+        let mut closed = flow(90, 200);
+        closed.add_tcp_flags("10.0.0.1:40002".to_string(), true, false);
+        closed.add_tcp_flags("10.0.0.2:80".to_string(), true, false);
+        map.insert(pair("40002"), closed);
+        let filename = report_file("packet_sniffer_flow_cache_test.txt");
+        PacketCatcher::empty_report(&mut map, &mut trackers, &filename, &options);
+        let _ = std::fs::remove_file(&filename);
+
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&pair("40000")]);
+        assert_eq!(map[&pair("40000")].export_reason(), None);
+        assert_eq!(trackers.totals.len(), 2);
+        assert_eq!(trackers.totals[&pair("40001")].export_reason(), Some("Idle timeout"));
+        assert_eq!(trackers.totals[&pair("40002")].export_reason(), Some("TCP FIN"));
+    }
+
+    #[test]
+    fn inner_flow_of_a_tunnel_gets_the_tunnel_metadata() {
+        let mut inner = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4([192, 168, 0, 1], [192, 168, 0, 2], 64)
+            .udp(40000, 9000)
+            .write(&mut inner, b"ping").unwrap();
+        // VXLAN header with the I flag and VNI 42
+        let mut vxlan = vec![0x08, 0, 0, 0, 0x00, 0x00, 0x2a, 0x00];
+        vxlan.extend(&inner);
+        let mut outer = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 3], [2, 0, 0, 0, 0, 4])
+            .ipv4([10, 0, 0, 2], [10, 0, 0, 1], 64)
+            .udp(50000, VXLAN_PORT)
+            .write(&mut outer, &vxlan).unwrap();
+
+        let options = CaptureOptions::default();
+        let mut trackers = Trackers::new(&options);
+        let mut report_map = HashMap::new();
+        let context = PacketContext{ts_us: 1_000_000, bytes: outer.len() as u32, link: None, tunnels: Vec::new()};
+        parse_sliced_packet(SlicedPacket::from_ethernet(&outer).unwrap(), outer.len(), context, &mut report_map, &mut trackers, &options);
+
+        // Only the inner flow is reported, with the bytes of the inner frame
+        assert_eq!(report_map.len(), 1);
+        let pair = AddressPortPair::new("192.168.0.1".to_string(), "40000".to_string(), "192.168.0.2".to_string(), "9000".to_string());
+        let report = &report_map[&pair];
+        assert_eq!(report.total_bytes(), inner.len() as u64);
+        let mut tunnel = TunnelInfo::new("VXLAN".to_string(), "10.0.0.2".to_string(), "10.0.0.1".to_string());
+        tunnel.vni = Some(42);
+        assert_eq!(report.tunnels().iter().collect::<Vec<_>>(), vec![&tunnel]);
     }
 }
@@ -0,0 +1,419 @@
+use std::net::Ipv4Addr;
+
+/// Port used by NTP.
+pub const NTP_PORT: u16 = 123;
+/// Port used by the SNMP agents.
+pub const SNMP_PORT: u16 = 161;
+/// Port used by the SNMP trap receivers.
+pub const SNMP_TRAP_PORT: u16 = 162;
+/// Port used by syslog over UDP.
+pub const SYSLOG_PORT: u16 = 514;
+/// Maximum number of OIDs listed for a single SNMP message.
+const MAX_SNMP_OIDS: usize = 10;
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to an NTP message.
+pub struct NtpInfo {
+    /// Protocol version
+    pub version: u8,
+    /// Association mode, e.g. "client" or "server"
+    pub mode: String,
+    /// Distance from the reference clock
+    pub stratum: u8,
+    /// Reference identifier: a clock source for stratum 0 and 1, the address of the upstream server otherwise
+    pub reference_id: String,
+}
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to an SNMP message.
+pub struct SnmpInfo {
+    /// Protocol version, e.g. "v2c"
+    pub version: String,
+    /// True if the message carries a non empty community string. The community itself is never stored.
+    pub community_present: bool,
+    /// Type of the PDU, e.g. "GetRequest"; `None` for encrypted SNMPv3 messages
+    pub pdu_type: Option<String>,
+    /// OIDs of the variable bindings
+    pub oids: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to a syslog message.
+pub struct SyslogInfo {
+    /// Facility name
+    pub facility: String,
+    /// Severity name
+    pub severity: String,
+    /// Hostname of the sender, if the header contains it
+    pub hostname: Option<String>,
+}
+
+/// Function that parses an NTP message.
+pub fn parse_ntp(payload: &[u8]) -> Option<NtpInfo> {
+    if payload.len() < 48 {
+        return None;
+    }
+    let version = (payload[0] >> 3) & 0x07;
+    let mode = match payload[0] & 0x07 {
+        1 => "symmetric active",
+        2 => "symmetric passive",
+        3 => "client",
+        4 => "server",
+        5 => "broadcast",
+        6 => "control",
+        7 => "private",
+        _ => return None
+    };
+    if version == 0 || version > 4 {
+        return None;
+    }
+    let stratum = payload[1];
+    let id = &payload[12..16];
+    let reference_id = if stratum <= 1 {
+        // Kiss codes and reference clock names are ASCII strings
+        String::from_utf8_lossy(id).trim_end_matches('\0').to_string()
+    } else {
+        Ipv4Addr::new(id[0], id[1], id[2], id[3]).to_string()
+    };
+    Some(NtpInfo{version, mode: mode.to_string(), stratum, reference_id})
+}
+
+/// Function that reads a BER element, returning its tag, its content and the remaining data.
+fn ber_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first_len = *data.get(1)? as usize;
+    let (len, header) = if first_len & 0x80 == 0 {
+        (first_len, 2)
+    } else {
+        let bytes = first_len & 0x7f;
+        if bytes == 0 || bytes > 4 {
+            return None;
+        }
+        let mut len = 0usize;
+        for b in data.get(2..2 + bytes)? {
+            len = (len << 8) | *b as usize;
+        }
+        (len, 2 + bytes)
+    };
+    let content = data.get(header..header + len)?;
+    Some((tag, content, &data[header + len..]))
+}
+
+/// Function that decodes the content of a BER object identifier.
+fn ber_oid(content: &[u8]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut value: u64 = 0;
+    for b in content {
+        value = (value << 7) | (*b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = (value / 40).min(2);
+                parts.push(first.to_string());
+                parts.push((value - first * 40).to_string());
+            } else {
+                parts.push(value.to_string());
+            }
+            value = 0;
+        }
+    }
+    parts.join(".")
+}
+
+/// Function that returns the name of an SNMP PDU type.
+fn snmp_pdu_type(tag: u8) -> String {
+    match tag {
+        0xa0 => "GetRequest".to_string(),
+        0xa1 => "GetNextRequest".to_string(),
+        0xa2 => "Response".to_string(),
+        0xa3 => "SetRequest".to_string(),
+        0xa4 => "Trap".to_string(),
+        0xa5 => "GetBulkRequest".to_string(),
+        0xa6 => "InformRequest".to_string(),
+        0xa7 => "SNMPv2-Trap".to_string(),
+        0xa8 => "Report".to_string(),
+        _ => format!("Unknown (0x{:02x})", tag)
+    }
+}
+
+/// Function that parses an SNMP message. For SNMPv3 only the version is decoded, because the PDU is usually encrypted.
+pub fn parse_snmp(payload: &[u8]) -> Option<SnmpInfo> {
+    let (tag, message, _) = ber_element(payload)?;
+    if tag != 0x30 {
+        return None;
+    }
+    let (tag, version, rest) = ber_element(message)?;
+    if tag != 0x02 || version.len() != 1 {
+        return None;
+    }
+    let version = match version[0] {
+        0 => "v1",
+        1 => "v2c",
+        3 => return Some(SnmpInfo{version: "v3".to_string(), community_present: false, pdu_type: None, oids: Vec::new()}),
+        _ => return None
+    };
+    let (tag, community, rest) = ber_element(rest)?;
+    if tag != 0x04 {
+        return None;
+    }
+    let (pdu_tag, pdu, _) = ber_element(rest)?;
+    let mut oids = Vec::new();
+    // The v1 Trap PDU has a different layout, its variable bindings are the last element
+    let mut fields = pdu;
+    let mut last = None;
+    while let Some((tag, content, rest)) = ber_element(fields) {
+        if tag == 0x30 {
+            last = Some(content);
+        }
+        fields = rest;
+    }
+    let mut bindings = last.unwrap_or(&[]);
+    while let Some((tag, binding, rest)) = ber_element(bindings) {
+        if tag == 0x30 {
+            if let Some((0x06, oid, _)) = ber_element(binding) {
+                if oids.len() < MAX_SNMP_OIDS {
+                    oids.push(ber_oid(oid));
+                }
+            }
+        }
+        bindings = rest;
+    }
+    Some(SnmpInfo{version: version.to_string(), community_present: !community.is_empty(), pdu_type: Some(snmp_pdu_type(pdu_tag)), oids})
+}
+
+/// Function that returns the name of a syslog facility.
+fn syslog_facility(facility: u32) -> String {
+    let names = ["kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv", "ftp", "ntp", "security", "console", "solaris-cron",
+        "local0", "local1", "local2", "local3", "local4", "local5", "local6", "local7"];
+    match names.get(facility as usize) {
+        Some(name) => name.to_string(),
+        None => facility.to_string()
+    }
+}
+
+/// Function that returns the name of a syslog severity.
+fn syslog_severity(severity: u32) -> String {
+    let names = ["emergency", "alert", "critical", "error", "warning", "notice", "informational", "debug"];
+    names[severity as usize % 8].to_string()
+}
+
+/// Function that parses a syslog message, both in the RFC 3164 (BSD) and in the RFC 5424 format.
+pub fn parse_syslog(payload: &[u8]) -> Option<SyslogInfo> {
+    let text = String::from_utf8_lossy(payload);
+    let text = text.strip_prefix('<')?;
+    let end = text.find('>')?;
+    if end == 0 || end > 3 {
+        return None;
+    }
+    let priority: u32 = text[..end].parse().ok()?;
+    if priority > 191 {
+        return None;
+    }
+    let header = &text[end + 1..];
+    let hostname = if header.starts_with("1 ") {
+        // RFC 5424: VERSION TIMESTAMP HOSTNAME APP-NAME ...
+        header.split(' ').nth(2).map(|h| h.to_string())
+    } else if header.len() > 16 && header.as_bytes()[15] == b' ' {
+        // RFC 3164: "Mmm dd hh:mm:ss HOSTNAME MSG"
+        header.get(16..).and_then(|h| h.split(' ').next()).map(|h| h.to_string())
+    } else {
+        None
+    };
+    let hostname = hostname.filter(|h| !h.is_empty() && h != "-");
+    Some(SyslogInfo{facility: syslog_facility(priority / 8), severity: syslog_severity(priority % 8), hostname})
+}
+
+/// The function takes as parameter an `NtpInfo` struct and generate a String containing its formatted informations.
+pub fn ntp_info_to_string(info: &NtpInfo) -> String {
+    format!("NTP v{} {}; Stratum: {}; Reference ID: {}", info.version, info.mode, info.stratum, info.reference_id)
+}
+
+/// The function takes as parameter an `SnmpInfo` struct and generate a String containing its formatted informations.
+pub fn snmp_info_to_string(info: &SnmpInfo) -> String {
+    let mut snmp_string = format!("SNMP {}", info.version);
+    if let Some(pdu_type) = &info.pdu_type {
+        snmp_string.push(' ');
+        snmp_string.push_str(pdu_type);
+        snmp_string.push_str("; Community: ");
+        snmp_string.push_str(if info.community_present { "present" } else { "absent" });
+        snmp_string.push_str("; OIDs: ");
+        snmp_string.push_str(info.oids.join(", ").as_str());
+    }
+    snmp_string
+}
+
+/// The function takes as parameter a `SyslogInfo` struct and generate a String containing its formatted informations.
+pub fn syslog_info_to_string(info: &SyslogInfo) -> String {
+    format!("Syslog; Facility: {}; Severity: {}; Hostname: {}", info.facility, info.severity, info.hostname.as_deref().unwrap_or("Unknown"))
+}
+
+/// Function that decodes a UDP payload of NTP, SNMP or syslog, chosen according to the ports, returning the summary of the message.
+pub fn parse_management(source_port: &str, destination_port: &str, payload: &[u8]) -> Option<String> {
+    let ports = [source_port.parse::<u16>().ok()?, destination_port.parse::<u16>().ok()?];
+    if ports.contains(&NTP_PORT) {
+        return parse_ntp(payload).map(|info| ntp_info_to_string(&info));
+    }
+    if ports.contains(&SNMP_PORT) || ports.contains(&SNMP_TRAP_PORT) {
+        return parse_snmp(payload).map(|info| snmp_info_to_string(&info));
+    }
+    if ports[1] == SYSLOG_PORT {
+        return parse_syslog(payload).map(|info| syslog_info_to_string(&info));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an NTP message with the given first byte, stratum and reference ID.
+    fn ntp(first: u8, stratum: u8, reference_id: [u8; 4]) -> Vec<u8> {
+        let mut message = vec![0; 48];
+        message[0] = first;
+        message[1] = stratum;
+        message[12..16].copy_from_slice(&reference_id);
+        message
+    }
+
+    /// Returns a BER element, with a short form length when it fits.
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        if content.len() > 0x7f {
+            return long_tlv(tag, content);
+        }
+        let mut element = vec![tag, content.len() as u8];
+        element.extend(content);
+        element
+    }
+
+    /// Returns a BER element with a long form length of two bytes.
+    fn long_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut element = vec![tag, 0x82];
+        element.extend((content.len() as u16).to_be_bytes());
+        element.extend(content);
+        element
+    }
+
+    /// Returns a variable binding list with a NULL value for each OID ending with `last`.
+    fn bindings(last: impl Iterator<Item = u8>) -> Vec<u8> {
+        let mut list = Vec::new();
+        for i in last {
+            let mut binding = tlv(0x06, &[0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, i, 0x00]);
+            binding.extend([0x05, 0x00]);
+            list.extend(tlv(0x30, &binding));
+        }
+        tlv(0x30, &list)
+    }
+
+    /// Returns an SNMP message of the given version and community, with a request PDU.
+    fn snmp(version: u8, community: &[u8], pdu_tag: u8, bindings: Vec<u8>) -> Vec<u8> {
+        let mut pdu = [tlv(0x02, &[1]), tlv(0x02, &[0]), tlv(0x02, &[0])].concat();
+        pdu.extend(bindings);
+        let message = [tlv(0x02, &[version]), tlv(0x04, community), tlv(pdu_tag, &pdu)].concat();
+        tlv(0x30, &message)
+    }
+
+    #[test]
+    fn ntp_reference_id_is_kiss_code_or_address() {
+        // Version 4, mode server
+        let info = parse_ntp(&ntp(0x24, 0, *b"RATE")).unwrap();
+        assert_eq!((info.version, info.mode.as_str(), info.reference_id.as_str()), (4, "server", "RATE"));
+        let info = parse_ntp(&ntp(0x24, 1, *b"GPS\0")).unwrap();
+        assert_eq!(info.reference_id, "GPS");
+        let info = parse_ntp(&ntp(0x23, 2, [192, 0, 2, 1])).unwrap();
+        assert_eq!((info.mode.as_str(), info.reference_id.as_str()), ("client", "192.0.2.1"));
+        assert_eq!(ntp_info_to_string(&info), "NTP v4 client; Stratum: 2; Reference ID: 192.0.2.1");
+    }
+
+    #[test]
+    fn rejects_invalid_ntp() {
+        assert!(parse_ntp(&ntp(0x20, 2, [0; 4])).is_none());
+        assert!(parse_ntp(&ntp(0x04, 2, [0; 4])).is_none());
+        assert!(parse_ntp(&ntp(0x2c, 2, [0; 4])).is_none());
+        assert!(parse_ntp(&ntp(0x24, 2, [0; 4])[..47]).is_none());
+    }
+
+    #[test]
+    fn parses_snmp_request() {
+        let info = parse_snmp(&snmp(1, b"public", 0xa0, bindings(1..2))).unwrap();
+        assert_eq!(snmp_info_to_string(&info), "SNMP v2c GetRequest; Community: present; OIDs: 1.3.6.1.2.1.1.1.0");
+        let info = parse_snmp(&snmp(0, b"", 0xa2, bindings(1..2))).unwrap();
+        assert_eq!((info.version.as_str(), info.community_present, info.pdu_type.as_deref()), ("v1", false, Some("Response")));
+    }
+
+    #[test]
+    fn reads_long_form_ber_lengths() {
+        let mut pdu = [tlv(0x02, &[1]), tlv(0x02, &[0]), tlv(0x02, &[0])].concat();
+        pdu.extend(long_tlv(0x30, &bindings(1..3)[2..]));
+        let message = [tlv(0x02, &[1]), tlv(0x04, b"private"), long_tlv(0xa1, &pdu)].concat();
+        let mut payload = vec![0x30, 0x81, message.len() as u8];
+        payload.extend(message);
+        let info = parse_snmp(&payload).unwrap();
+        assert_eq!(info.pdu_type.as_deref(), Some("GetNextRequest"));
+        assert_eq!(info.oids, vec!["1.3.6.1.2.1.1.1.0", "1.3.6.1.2.1.1.2.0"]);
+        // Lengths of more than 4 bytes are refused
+        assert!(parse_snmp(&[0x30, 0x85, 0, 0, 0, 0, 1, 0]).is_none());
+        // A length longer than the payload
+        assert!(parse_snmp(&payload[..payload.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn v1_trap_bindings_are_the_last_element() {
+        let enterprise = tlv(0x06, &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x09]);
+        let pdu = [enterprise, tlv(0x40, &[192, 0, 2, 1]), tlv(0x02, &[6]), tlv(0x02, &[1]), tlv(0x43, &[0]), bindings(3..4)].concat();
+        let message = [tlv(0x02, &[0]), tlv(0x04, b"public"), tlv(0xa4, &pdu)].concat();
+        let info = parse_snmp(&tlv(0x30, &message)).unwrap();
+        assert_eq!(info.pdu_type.as_deref(), Some("Trap"));
+        assert_eq!(info.oids, vec!["1.3.6.1.2.1.1.3.0"]);
+    }
+
+    #[test]
+    fn snmp_v3_is_not_decoded() {
+        // The header data and the security parameters follow the version
+        let message = [tlv(0x02, &[3]), tlv(0x30, &[0xff; 4]), tlv(0x04, &[0xff; 8])].concat();
+        let info = parse_snmp(&tlv(0x30, &message)).unwrap();
+        assert_eq!((info.version.as_str(), info.community_present, info.pdu_type.as_deref()), ("v3", false, None));
+        assert_eq!(snmp_info_to_string(&info), "SNMP v3");
+    }
+
+    #[test]
+    fn snmp_oids_are_capped() {
+        let info = parse_snmp(&snmp(1, b"public", 0xa0, bindings(1..13))).unwrap();
+        assert_eq!(info.oids.len(), MAX_SNMP_OIDS);
+        assert_eq!(info.oids[MAX_SNMP_OIDS - 1], "1.3.6.1.2.1.1.10.0");
+    }
+
+    #[test]
+    fn decodes_oid_with_large_first_arc() {
+        assert_eq!(ber_oid(&[0x88, 0x37, 0x03]), "2.999.3");
+    }
+
+    #[test]
+    fn syslog_hostname_of_both_formats() {
+        let info = parse_syslog(b"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 - message").unwrap();
+        assert_eq!((info.facility.as_str(), info.severity.as_str()), ("local4", "notice"));
+        assert_eq!(info.hostname.as_deref(), Some("mymachine.example.com"));
+        let info = parse_syslog(b"<34>Oct 11 22:14:15 mymachine su: 'su root' failed").unwrap();
+        assert_eq!((info.facility.as_str(), info.severity.as_str()), ("auth", "critical"));
+        assert_eq!(info.hostname.as_deref(), Some("mymachine"));
+        // The nil value of RFC 5424 and a header without timestamp
+        let info = parse_syslog(b"<191>1 2003-10-11T22:14:15Z - app - - - message").unwrap();
+        assert_eq!((info.facility.as_str(), info.severity.as_str(), info.hostname), ("local7", "debug", None));
+        assert_eq!(parse_syslog(b"<13>message").unwrap().hostname, None);
+    }
+
+    #[test]
+    fn rejects_invalid_syslog_priority() {
+        assert!(parse_syslog(b"<192>1 2003-10-11T22:14:15Z host app - - - message").is_none());
+        assert!(parse_syslog(b"<>message").is_none());
+        assert!(parse_syslog(b"<0013>message").is_none());
+        assert!(parse_syslog(b"13>message").is_none());
+    }
+
+    #[test]
+    fn chooses_the_protocol_by_port() {
+        let payload = b"<13>message";
+        assert!(parse_management("50000", "514", payload).is_some());
+        assert!(parse_management("514", "50000", payload).is_none());
+        assert_eq!(parse_management("123", "50000", &ntp(0x24, 2, [192, 0, 2, 1])).as_deref(),
+                   Some("NTP v4 server; Stratum: 2; Reference ID: 192.0.2.1"));
+    }
+}
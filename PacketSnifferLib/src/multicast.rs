@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use etherparse::TransportSlice;
+
+/// IP protocol number of IGMP.
+pub const IGMP_PROTOCOL: u8 = 2;
+/// Time in seconds after which a member that did not renew its report is removed from a group (RFC 2236 default).
+const MEMBERSHIP_TIMEOUT_S: u64 = 260;
+/// Maximum number of group records read from a single IGMPv3 or MLDv2 report.
+const MAX_GROUP_RECORDS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///Enum representing the effect of a membership message on a group.
+pub enum MembershipChange {
+    /// The sender joined or is still a member of the group
+    Join,
+    /// The sender left the group
+    Leave,
+    /// The sender asked which hosts are members of the group
+    Query,
+}
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to an IGMP or MLD message.
+pub struct MembershipMessage {
+    /// Protocol and version, e.g. "IGMPv3"
+    pub protocol: String,
+    /// Type of the message, e.g. "Membership Report"
+    pub message_type: String,
+    /// Groups affected by the message; a general query has the unspecified address as group
+    pub groups: Vec<(String, MembershipChange)>,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the members and the traffic of a multicast group.
+pub struct MulticastGroup {
+    /// Multicast address of the group
+    pub group: String,
+    /// Hosts that joined the group, with the timestamp (in seconds) of their last report
+    pub members: HashMap<String, u64>,
+    /// Packets sent to the group in the current interval
+    pub packets: u64,
+    /// Bytes sent to the group in the current interval
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+///Struct that keeps the membership of the multicast groups, learned from IGMP and MLD, and the traffic sent to them.
+pub struct MulticastTable {
+    /// Groups by multicast address
+    groups: HashMap<String, MulticastGroup>,
+    /// Hosts that sent a query, with the timestamp (in seconds) of their last query
+    queriers: HashMap<String, u64>,
+}
+
+/// Function that returns the effect of an IGMPv3 or MLDv2 group record, given its type and number of sources.
+fn record_change(record_type: u8, sources: usize) -> Option<MembershipChange> {
+    match record_type {
+        // MODE_IS_EXCLUDE, CHANGE_TO_EXCLUDE_MODE and ALLOW_NEW_SOURCES
+        2 | 4 | 5 => Some(MembershipChange::Join),
+        // MODE_IS_INCLUDE and CHANGE_TO_INCLUDE_MODE: an empty source list means leaving the group
+        1 | 3 if sources == 0 => Some(MembershipChange::Leave),
+        1 | 3 => Some(MembershipChange::Join),
+        _ => None
+    }
+}
+
+/// Function that reads the group records of an IGMPv3 or MLDv2 report. `address_len` is 4 for IGMP and 16 for MLD.
+fn parse_group_records(mut records: &[u8], count: usize, address_len: usize) -> Vec<(String, MembershipChange)> {
+    let mut groups = Vec::new();
+    for _ in 0..count.min(MAX_GROUP_RECORDS) {
+        if records.len() < 4 + address_len {
+            break;
+        }
+        let sources = u16::from_be_bytes([records[2], records[3]]) as usize;
+        let len = 4 + address_len + sources * address_len + records[1] as usize * 4;
+        let address = &records[4..4 + address_len];
+        let group = if address_len == 4 {
+            Ipv4Addr::new(address[0], address[1], address[2], address[3]).to_string()
+        } else {
+            let bytes: [u8; 16] = address.try_into().unwrap_or([0; 16]);
+            Ipv6Addr::from(bytes).to_string()
+        };
+        if let Some(change) = record_change(records[0], sources) {
+            groups.push((group, change));
+        }
+        records = match records.get(len..) {
+            Some(rest) => rest,
+            None => break
+        };
+    }
+    groups
+}
+
+/// Function that decodes an IGMP message, carried by IPv4 with protocol number 2 in `payload`.
+pub fn parse_igmp(transport: &Option<TransportSlice>, payload: &[u8]) -> Option<MembershipMessage> {
+    if !matches!(transport, Some(TransportSlice::Unknown(IGMP_PROTOCOL))) || payload.len() < 8 {
+        return None;
+    }
+    let group = Ipv4Addr::new(payload[4], payload[5], payload[6], payload[7]).to_string();
+    let (protocol, message_type, groups) = match payload[0] {
+        0x11 => {
+            // The version of a query is given by its length and by the maximum response time
+            let protocol = if payload.len() >= 12 { "IGMPv3" } else if payload[1] == 0 { "IGMPv1" } else { "IGMPv2" };
+            (protocol, "Membership Query", vec![(group, MembershipChange::Query)])
+        },
+        0x12 => ("IGMPv1", "Membership Report", vec![(group, MembershipChange::Join)]),
+        0x16 => ("IGMPv2", "Membership Report", vec![(group, MembershipChange::Join)]),
+        0x17 => ("IGMPv2", "Leave Group", vec![(group, MembershipChange::Leave)]),
+        0x22 => {
+            let count = u16::from_be_bytes([payload[6], payload[7]]) as usize;
+            ("IGMPv3", "Membership Report", parse_group_records(&payload[8..], count, 4))
+        },
+        _ => return None
+    };
+    Some(MembershipMessage{protocol: protocol.to_string(), message_type: message_type.to_string(), groups})
+}
+
+/// Function that decodes an MLD message, carried by ICMPv6 with types 130, 131, 132 and 143.
+pub fn parse_mld(transport: &Option<TransportSlice>) -> Option<MembershipMessage> {
+    let slice = match transport {
+        Some(TransportSlice::Icmpv6(slice)) => slice,
+        _ => return None
+    };
+    let body = slice.payload();
+    let group = || -> Option<String> {
+        let bytes: [u8; 16] = body.get(0..16)?.try_into().ok()?;
+        Some(Ipv6Addr::from(bytes).to_string())
+    };
+    let (protocol, message_type, groups) = match slice.type_u8() {
+        130 => {
+            let protocol = if body.len() >= 20 { "MLDv2" } else { "MLDv1" };
+            (protocol, "Multicast Listener Query", vec![(group()?, MembershipChange::Query)])
+        },
+        131 => ("MLDv1", "Multicast Listener Report", vec![(group()?, MembershipChange::Join)]),
+        132 => ("MLDv1", "Multicast Listener Done", vec![(group()?, MembershipChange::Leave)]),
+        143 => {
+            let header = slice.bytes5to8();
+            let count = u16::from_be_bytes([header[2], header[3]]) as usize;
+            ("MLDv2", "Multicast Listener Report", parse_group_records(body, count, 16))
+        },
+        _ => return None
+    };
+    Some(MembershipMessage{protocol: protocol.to_string(), message_type: message_type.to_string(), groups})
+}
+
+/// Function that checks if an address is an IPv4 or IPv6 multicast address.
+pub fn is_multicast_address(address: &str) -> bool {
+    match address.parse::<IpAddr>() {
+        Ok(ip) => ip.is_multicast(),
+        Err(_) => false
+    }
+}
+
+/// The function takes as parameter a `MembershipMessage` struct and generate a String containing its formatted informations.
+pub fn membership_message_to_string(message: &MembershipMessage) -> String {
+    let mut membership_string = format!("{} {}", message.protocol, message.message_type);
+    let groups: Vec<String> = message.groups.iter().map(|(group, change)| match change {
+        MembershipChange::Join => format!("join {}", group),
+        MembershipChange::Leave => format!("leave {}", group),
+        MembershipChange::Query => format!("query {}", group)
+    }).collect();
+    if !groups.is_empty() {
+        membership_string.push_str("; Groups: ");
+        membership_string.push_str(groups.join(", ").as_str());
+    }
+    membership_string
+}
+
+impl MulticastTable {
+    /// Create a new empty MulticastTable struct.
+    pub fn new() -> MulticastTable {
+        MulticastTable{groups: HashMap::new(), queriers: HashMap::new()}
+    }
+
+    /// Function that returns the entry of a group, creating it if needed.
+    fn group(&mut self, group: &str) -> &mut MulticastGroup {
+        self.groups.entry(group.to_string()).or_insert_with(|| MulticastGroup{group: group.to_string(), members: HashMap::new(), packets: 0, bytes: 0})
+    }
+
+    /// Registers an IGMP or MLD message sent by `source` at timestamp `ts` (in seconds).
+    pub fn register(&mut self, message: &MembershipMessage, source: &str, ts: u64) {
+        for (group, change) in &message.groups {
+            match change {
+                MembershipChange::Join => {
+                    self.group(group).members.insert(source.to_string(), ts);
+                },
+                MembershipChange::Leave => {
+                    if let Some(entry) = self.groups.get_mut(group) {
+                        entry.members.remove(source);
+                    }
+                },
+                MembershipChange::Query => {
+                    self.queriers.insert(source.to_string(), ts);
+                }
+            }
+        }
+    }
+
+    /// Counts a packet of `bytes` bytes sent to `destination`, if it is a multicast address.
+    pub fn add_traffic(&mut self, destination: &str, bytes: u32) {
+        if !is_multicast_address(destination) {
+            return;
+        }
+        let group = self.group(destination);
+        group.packets += 1;
+        group.bytes += bytes as u64;
+    }
+
+    /// Removes, at timestamp `now` (in seconds), the members and queriers that did not renew their report or query
+    /// within the timeout.
+    pub fn purge_expired(&mut self, now: u64) {
+        let deadline = now.saturating_sub(MEMBERSHIP_TIMEOUT_S);
+        for group in self.groups.values_mut() {
+            group.members.retain(|_, last_report| *last_report >= deadline);
+        }
+        self.queriers.retain(|_, last_query| *last_query >= deadline);
+    }
+
+    /// Clears the traffic counters of the current interval, removing the groups left without members.
+    pub fn reset_stats(&mut self) {
+        self.groups.retain(|_, group| !group.members.is_empty());
+        for group in self.groups.values_mut() {
+            group.packets = 0;
+            group.bytes = 0;
+        }
+    }
+
+    /// Returns the multicast groups with members or traffic, sorted by address.
+    pub fn groups(&self) -> Vec<MulticastGroup> {
+        let mut groups: Vec<MulticastGroup> = self.groups.values().cloned().collect();
+        groups.sort_by(|a, b| a.group.cmp(&b.group));
+        groups
+    }
+
+    /// Returns the groups that `host` is a member of, sorted by address.
+    pub fn memberships(&self, host: &str) -> Vec<String> {
+        let mut groups: Vec<String> = self.groups.values().filter(|g| g.members.contains_key(host)).map(|g| g.group.clone()).collect();
+        groups.sort();
+        groups
+    }
+
+    /// Generates the multicast section of a report, listing each group with its members and traffic, and the queriers.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----Multicast groups-----\n");
+        for group in self.groups() {
+            let mut members: Vec<&String> = group.members.keys().collect();
+            members.sort();
+            let members: Vec<&str> = members.into_iter().map(|m| m.as_str()).collect();
+            s.push_str(&format!("{}: members: {}; packets: {}; bytes: {}\n",
+                                group.group,
+                                if members.is_empty() { "None".to_string() } else { members.join(", ") },
+                                group.packets,
+                                group.bytes));
+        }
+        let mut queriers: Vec<&String> = self.queriers.keys().collect();
+        queriers.sort();
+        let queriers: Vec<&str> = queriers.into_iter().map(|q| q.as_str()).collect();
+        s.push_str(&format!("Queriers: {}\n", queriers.join(", ")));
+        s.push('\n');
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::Icmpv6Slice;
+
+    fn igmp(payload: &[u8]) -> Option<MembershipMessage> {
+        parse_igmp(&Some(TransportSlice::Unknown(IGMP_PROTOCOL)), payload)
+    }
+
+    /// Parses an ICMPv6 message made of the given type, the rest of the header and the body.
+    fn mld(type_u8: u8, rest: [u8; 4], body: &[u8]) -> Option<MembershipMessage> {
+        let mut message = vec![type_u8, 0, 0, 0];
+        message.extend_from_slice(&rest);
+        message.extend_from_slice(body);
+        parse_mld(&Some(TransportSlice::Icmpv6(Icmpv6Slice::from_slice(&message).unwrap())))
+    }
+
+    fn address(address: &str) -> [u8; 16] {
+        address.parse::<Ipv6Addr>().unwrap().octets()
+    }
+
+    fn groups(message: &MembershipMessage) -> Vec<(&str, MembershipChange)> {
+        message.groups.iter().map(|(group, change)| (group.as_str(), *change)).collect()
+    }
+
+    #[test]
+    fn igmp_query_version_is_given_by_length_and_response_time() {
+        let query = igmp(&[0x11, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!((query.protocol.as_str(), query.message_type.as_str()), ("IGMPv1", "Membership Query"));
+        assert_eq!(groups(&query), vec![("0.0.0.0", MembershipChange::Query)]);
+        assert_eq!(igmp(&[0x11, 100, 0, 0, 239, 1, 2, 3]).unwrap().protocol, "IGMPv2");
+        assert_eq!(igmp(&[0x11, 100, 0, 0, 239, 1, 2, 3, 0x02, 125, 0, 0]).unwrap().protocol, "IGMPv3");
+    }
+
+    #[test]
+    fn parses_igmp_reports_and_leaves() {
+        let report = igmp(&[0x16, 0, 0, 0, 239, 1, 2, 3]).unwrap();
+        assert_eq!(membership_message_to_string(&report), "IGMPv2 Membership Report; Groups: join 239.1.2.3");
+        let leave = igmp(&[0x17, 0, 0, 0, 239, 1, 2, 3]).unwrap();
+        assert_eq!(groups(&leave), vec![("239.1.2.3", MembershipChange::Leave)]);
+        assert!(igmp(&[0x16, 0, 0, 0, 239, 1, 2]).is_none());
+        assert!(igmp(&[0x30, 0, 0, 0, 239, 1, 2, 3]).is_none());
+        assert!(parse_igmp(&Some(TransportSlice::Unknown(17)), &[0x16, 0, 0, 0, 239, 1, 2, 3]).is_none());
+    }
+
+    #[test]
+    fn reads_igmpv3_group_records() {
+        let mut report = vec![0x22, 0, 0, 0, 0, 0, 0, 5];
+        // CHANGE_TO_EXCLUDE_MODE
+        report.extend_from_slice(&[4, 0, 0, 0, 239, 1, 1, 1]);
+        // CHANGE_TO_INCLUDE_MODE without sources, with a word of auxiliary data
+        report.extend_from_slice(&[3, 1, 0, 0, 239, 2, 2, 2, 0xaa, 0xaa, 0xaa, 0xaa]);
+        // MODE_IS_INCLUDE with a source
+        report.extend_from_slice(&[1, 0, 0, 1, 239, 3, 3, 3, 10, 0, 0, 9]);
+        // BLOCK_OLD_SOURCES does not change the membership
+        report.extend_from_slice(&[6, 0, 0, 1, 239, 4, 4, 4, 10, 0, 0, 9]);
+        // MODE_IS_EXCLUDE
+        report.extend_from_slice(&[2, 0, 0, 0, 239, 5, 5, 5]);
+        // A record after the announced count
+        report.extend_from_slice(&[2, 0, 0, 0, 239, 6, 6, 6]);
+        let report = igmp(&report).unwrap();
+        assert_eq!(report.protocol, "IGMPv3");
+        assert_eq!(groups(&report), vec![
+            ("239.1.1.1", MembershipChange::Join),
+            ("239.2.2.2", MembershipChange::Leave),
+            ("239.3.3.3", MembershipChange::Join),
+            ("239.5.5.5", MembershipChange::Join),
+        ]);
+    }
+
+    #[test]
+    fn truncated_group_record_stops_the_records() {
+        let records = [[4, 0, 0, 0, 239, 1, 1, 1], [4, 0, 0, 0, 239, 2, 2, 0]].concat();
+        assert_eq!(parse_group_records(&records[..12], 2, 4).len(), 1);
+    }
+
+    #[test]
+    fn parses_mld_messages() {
+        let query = mld(130, [0; 4], &address("::")).unwrap();
+        assert_eq!((query.protocol.as_str(), query.message_type.as_str()), ("MLDv1", "Multicast Listener Query"));
+        let mut body = address("::").to_vec();
+        body.extend_from_slice(&[0x02, 125, 0, 0]);
+        assert_eq!(mld(130, [0; 4], &body).unwrap().protocol, "MLDv2");
+        let report = mld(131, [0; 4], &address("ff05::1:3")).unwrap();
+        assert_eq!(groups(&report), vec![("ff05::1:3", MembershipChange::Join)]);
+        let done = mld(132, [0; 4], &address("ff05::1:3")).unwrap();
+        assert_eq!(groups(&done), vec![("ff05::1:3", MembershipChange::Leave)]);
+        assert!(mld(131, [0; 4], &address("ff05::1:3")[..15]).is_none());
+        assert!(mld(128, [0; 4], &[]).is_none());
+    }
+
+    #[test]
+    fn reads_mldv2_group_records() {
+        let mut body = vec![4, 0, 0, 0];
+        body.extend_from_slice(&address("ff05::1:3"));
+        body.extend_from_slice(&[3, 0, 0, 0]);
+        body.extend_from_slice(&address("ff02::fb"));
+        let report = mld(143, [0, 0, 0, 2], &body).unwrap();
+        assert_eq!((report.protocol.as_str(), report.message_type.as_str()), ("MLDv2", "Multicast Listener Report"));
+        assert_eq!(groups(&report), vec![("ff05::1:3", MembershipChange::Join), ("ff02::fb", MembershipChange::Leave)]);
+    }
+
+    #[test]
+    fn members_expire_without_reports() {
+        let mut table = MulticastTable::new();
+        let join = igmp(&[0x16, 0, 0, 0, 239, 1, 2, 3]).unwrap();
+        table.register(&join, "10.0.0.1", 0);
+        table.register(&join, "10.0.0.2", 100);
+        table.register(&igmp(&[0x11, 100, 0, 0, 0, 0, 0, 0]).unwrap(), "10.0.0.254", 0);
+        table.add_traffic("239.1.2.3", 100);
+        table.add_traffic("10.0.0.3", 100);
+        table.purge_expired(MEMBERSHIP_TIMEOUT_S);
+        assert_eq!(table.groups()[0].members.len(), 2);
+        assert_eq!(table.groups()[0].bytes, 100);
+        table.purge_expired(MEMBERSHIP_TIMEOUT_S + 1);
+        assert_eq!(table.memberships("10.0.0.1"), Vec::<String>::new());
+        assert_eq!(table.memberships("10.0.0.2"), vec!["239.1.2.3"]);
+        assert!(table.queriers.is_empty());
+        table.purge_expired(MEMBERSHIP_TIMEOUT_S + 101);
+        table.reset_stats();
+        assert!(table.groups().is_empty());
+    }
+}
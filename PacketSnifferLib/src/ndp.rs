@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use std::net::Ipv6Addr;
+use etherparse::TransportSlice;
+use crate::report::{mac_address_to_string, MacAddress};
+
+/// Maximum number of options read from a single Neighbor Discovery message.
+const MAX_NDP_OPTIONS: usize = 32;
+/// Time (in seconds) after which a neighbor that sent no Neighbor Discovery message is removed, and a router with a
+/// null lifetime that sent no Router Advertisement (the longest interval between advertisements is 1800 seconds).
+const NEIGHBOR_TIMEOUT_S: u64 = 1800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///Enum representing the type of a Neighbor Discovery message.
+pub enum NdpMessageType {
+    /// Type 133
+    RouterSolicitation,
+    /// Type 134
+    RouterAdvertisement,
+    /// Type 135
+    NeighborSolicitation,
+    /// Type 136
+    NeighborAdvertisement,
+    /// Type 137
+    Redirect,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Struct containing a Prefix Information option of a Router Advertisement.
+pub struct NdpPrefix {
+    /// Prefix in CIDR notation, e.g. "2001:db8::/64"
+    pub prefix: String,
+    /// True if the prefix can be used for on-link determination (L flag)
+    pub on_link: bool,
+    /// True if the prefix can be used for stateless address autoconfiguration (A flag)
+    pub autonomous: bool,
+    /// Valid lifetime in seconds
+    pub valid_lifetime: u32,
+    /// Preferred lifetime in seconds
+    pub preferred_lifetime: u32,
+}
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to a Neighbor Discovery message.
+pub struct NdpInfo {
+    /// Type of the message
+    pub message_type: NdpMessageType,
+    /// Target address of Neighbor Solicitations, Neighbor Advertisements and Redirects
+    pub target: Option<String>,
+    /// Destination address of Redirects
+    pub redirect_destination: Option<String>,
+    /// Source link-layer address option
+    pub source_link_address: Option<String>,
+    /// Target link-layer address option
+    pub target_link_address: Option<String>,
+    /// Router lifetime of Router Advertisements, in seconds
+    pub router_lifetime: Option<u16>,
+    /// Managed address configuration (M) flag of Router Advertisements
+    pub managed: bool,
+    /// Other configuration (O) flag of Router Advertisements
+    pub other_config: bool,
+    /// Router (R) flag of Neighbor Advertisements
+    pub router: bool,
+    /// Solicited (S) flag of Neighbor Advertisements
+    pub solicited: bool,
+    /// Override (O) flag of Neighbor Advertisements
+    pub override_flag: bool,
+    /// Prefix Information options
+    pub prefixes: Vec<NdpPrefix>,
+    /// MTU option
+    pub mtu: Option<u32>,
+    /// Recursive DNS servers option (RDNSS)
+    pub dns_servers: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+///Struct representing an IPv6 neighbor learned from Neighbor Discovery.
+pub struct NeighborEntry {
+    /// IPv6 address of the neighbor
+    pub address: String,
+    /// Link-layer address of the neighbor
+    pub mac: String,
+    /// True if the neighbor announced itself as a router
+    pub is_router: bool,
+    /// Number of times the address was announced with a different link-layer address
+    pub mac_changes: u32,
+    /// Timestamp (in seconds) at which the neighbor was first seen
+    pub first_seen: u64,
+    /// Timestamp (in seconds) at which the neighbor was last seen
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Clone)]
+///Struct representing a router learned from its Router Advertisements.
+pub struct RouterInfo {
+    /// Link-local address of the router
+    pub address: String,
+    /// Link-layer address of the router, if advertised
+    pub mac: Option<String>,
+    /// Router lifetime in seconds, 0 if the router is not a default router
+    pub lifetime: u16,
+    /// True if the hosts should use DHCPv6 for the addresses (M flag)
+    pub managed: bool,
+    /// True if the hosts should use DHCPv6 for the other configuration (O flag)
+    pub other_config: bool,
+    /// Advertised prefixes
+    pub prefixes: Vec<NdpPrefix>,
+    /// Advertised link MTU
+    pub mtu: Option<u32>,
+    /// Advertised DNS servers
+    pub dns_servers: Vec<String>,
+    /// Timestamp (in seconds) of the last advertisement
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Default)]
+///Struct that keeps the IPv6 neighbors and routers learned from the Neighbor Discovery messages.
+pub struct NeighborTable {
+    /// Neighbors by IPv6 address
+    neighbors: HashMap<String, NeighborEntry>,
+    /// Routers by IPv6 address
+    routers: HashMap<String, RouterInfo>,
+}
+
+/// Function that reads an IPv6 address from a slice of at least 16 bytes.
+fn ipv6(data: &[u8]) -> Option<Ipv6Addr> {
+    let bytes: [u8; 16] = data.get(0..16)?.try_into().ok()?;
+    Some(Ipv6Addr::from(bytes))
+}
+
+/// Function that reads a link-layer address option, when it contains an Ethernet address.
+fn link_address(data: &[u8]) -> Option<String> {
+    let bytes: [u8; 6] = data.get(0..6)?.try_into().ok()?;
+    Some(mac_address_to_string(MacAddress::new(bytes)))
+}
+
+/// Function that decodes the options of a Neighbor Discovery message into `info`.
+fn parse_ndp_options(mut options: &[u8], info: &mut NdpInfo) {
+    for _ in 0..MAX_NDP_OPTIONS {
+        if options.len() < 2 || options[1] == 0 {
+            return;
+        }
+        let len = options[1] as usize * 8;
+        let data = match options.get(2..len) {
+            Some(data) => data,
+            None => return
+        };
+        match options[0] {
+            1 => info.source_link_address = link_address(data),
+            2 => info.target_link_address = link_address(data),
+            3 if data.len() >= 30 => {
+                if let Some(prefix) = ipv6(&data[14..30]) {
+                    info.prefixes.push(NdpPrefix{
+                        prefix: format!("{}/{}", prefix, data[0]),
+                        on_link: data[1] & 0x80 != 0,
+                        autonomous: data[1] & 0x40 != 0,
+                        valid_lifetime: u32::from_be_bytes([data[2], data[3], data[4], data[5]]),
+                        preferred_lifetime: u32::from_be_bytes([data[6], data[7], data[8], data[9]]),
+                    });
+                }
+            },
+            5 if data.len() >= 6 => info.mtu = Some(u32::from_be_bytes([data[2], data[3], data[4], data[5]])),
+            25 => {
+                // Reserved and lifetime fields, then the addresses
+                let mut servers = data.get(6..).unwrap_or(&[]);
+                while let Some(server) = ipv6(servers) {
+                    info.dns_servers.push(server.to_string());
+                    servers = &servers[16..];
+                }
+            },
+            _ => {}
+        }
+        options = &options[len..];
+    }
+}
+
+/// Function that decodes an ICMPv6 Neighbor Discovery message (types 133 to 137), with its options.
+/// It returns `None` for the other messages.
+pub fn parse_ndp(transport: &Option<TransportSlice>) -> Option<NdpInfo> {
+    let slice = match transport {
+        Some(TransportSlice::Icmpv6(slice)) => slice,
+        _ => return None
+    };
+    let message_type = match slice.type_u8() {
+        133 => NdpMessageType::RouterSolicitation,
+        134 => NdpMessageType::RouterAdvertisement,
+        135 => NdpMessageType::NeighborSolicitation,
+        136 => NdpMessageType::NeighborAdvertisement,
+        137 => NdpMessageType::Redirect,
+        _ => return None
+    };
+    let header = slice.bytes5to8();
+    let body = slice.payload();
+    let mut info = NdpInfo{message_type, target: None, redirect_destination: None, source_link_address: None, target_link_address: None,
+        router_lifetime: None, managed: false, other_config: false, router: false, solicited: false, override_flag: false,
+        prefixes: Vec::new(), mtu: None, dns_servers: Vec::new()};
+    let options = match message_type {
+        NdpMessageType::RouterSolicitation => body,
+        NdpMessageType::RouterAdvertisement => {
+            info.managed = header[1] & 0x80 != 0;
+            info.other_config = header[1] & 0x40 != 0;
+            info.router_lifetime = Some(u16::from_be_bytes([header[2], header[3]]));
+            // Reachable time and retransmission timer
+            body.get(8..)?
+        },
+        NdpMessageType::NeighborSolicitation => {
+            info.target = Some(ipv6(body)?.to_string());
+            &body[16..]
+        },
+        NdpMessageType::NeighborAdvertisement => {
+            info.router = header[0] & 0x80 != 0;
+            info.solicited = header[0] & 0x40 != 0;
+            info.override_flag = header[0] & 0x20 != 0;
+            info.target = Some(ipv6(body)?.to_string());
+            &body[16..]
+        },
+        NdpMessageType::Redirect => {
+            info.target = Some(ipv6(body)?.to_string());
+            info.redirect_destination = Some(ipv6(body.get(16..)?)?.to_string());
+            &body[32..]
+        }
+    };
+    parse_ndp_options(options, &mut info);
+    Some(info)
+}
+
+/// The function takes as parameter an `NdpInfo` struct and generate a String containing its formatted informations.
+pub fn ndp_info_to_string(info: &NdpInfo) -> String {
+    let mut ndp_string = "".to_owned();
+    if let Some(target) = &info.target {
+        ndp_string.push_str("Target: ");
+        ndp_string.push_str(target);
+    }
+    if let Some(destination) = &info.redirect_destination {
+        ndp_string.push_str("; Destination: ");
+        ndp_string.push_str(destination);
+    }
+    if info.message_type == NdpMessageType::NeighborAdvertisement {
+        ndp_string.push_str(&format!("; Flags: {}{}{}",
+                                     if info.router { "R" } else { "-" },
+                                     if info.solicited { "S" } else { "-" },
+                                     if info.override_flag { "O" } else { "-" }));
+    }
+    if let Some(lifetime) = info.router_lifetime {
+        ndp_string.push_str(&format!("; Router lifetime: {} s; Flags: {}{}", lifetime, if info.managed { "M" } else { "-" }, if info.other_config { "O" } else { "-" }));
+    }
+    if let Some(mac) = &info.source_link_address {
+        ndp_string.push_str("; Source link-layer address: ");
+        ndp_string.push_str(mac);
+    }
+    if let Some(mac) = &info.target_link_address {
+        ndp_string.push_str("; Target link-layer address: ");
+        ndp_string.push_str(mac);
+    }
+    for prefix in &info.prefixes {
+        ndp_string.push_str(&format!("; Prefix: {} ({}{}, valid {} s, preferred {} s)",
+                                     prefix.prefix,
+                                     if prefix.on_link { "L" } else { "-" },
+                                     if prefix.autonomous { "A" } else { "-" },
+                                     prefix.valid_lifetime,
+                                     prefix.preferred_lifetime));
+    }
+    if let Some(mtu) = info.mtu {
+        ndp_string.push_str(&format!("; MTU: {}", mtu));
+    }
+    if !info.dns_servers.is_empty() {
+        ndp_string.push_str("; RDNSS: ");
+        ndp_string.push_str(info.dns_servers.join(", ").as_str());
+    }
+    ndp_string.trim_start_matches("; ").to_string()
+}
+
+impl NeighborTable {
+    /// Create a new empty NeighborTable struct.
+    pub fn new() -> NeighborTable {
+        NeighborTable{neighbors: HashMap::new(), routers: HashMap::new()}
+    }
+
+    /// Function that records that `address` uses the link-layer address `mac` at timestamp `ts`.
+    fn learn(&mut self, address: &str, mac: &str, is_router: bool, ts: u64) {
+        let entry = self.neighbors.entry(address.to_string()).or_insert_with(|| NeighborEntry{
+            address: address.to_string(), mac: mac.to_string(), is_router, mac_changes: 0, first_seen: ts, last_seen: ts});
+        if entry.mac != mac {
+            entry.mac = mac.to_string();
+            entry.mac_changes += 1;
+        }
+        entry.is_router |= is_router;
+        entry.last_seen = ts;
+    }
+
+    /// Registers a Neighbor Discovery message sent by `source` with link-layer address `link_mac`, observed at timestamp
+    /// `ts` (in seconds).
+    pub fn register(&mut self, info: &NdpInfo, source: &str, link_mac: [u8; 6], ts: u64) {
+        let link_mac = mac_address_to_string(MacAddress::new(link_mac));
+        // Duplicate address detection uses the unspecified source address
+        let has_source = source != "::";
+        match info.message_type {
+            NdpMessageType::RouterSolicitation | NdpMessageType::NeighborSolicitation => {
+                if let (true, Some(mac)) = (has_source, &info.source_link_address) {
+                    self.learn(source, mac, false, ts);
+                }
+            },
+            NdpMessageType::NeighborAdvertisement => {
+                if let Some(target) = &info.target {
+                    let mac = info.target_link_address.as_deref().unwrap_or(&link_mac).to_string();
+                    self.learn(target, &mac, info.router, ts);
+                }
+            },
+            NdpMessageType::Redirect => {
+                if let (Some(target), Some(mac)) = (&info.target, &info.target_link_address) {
+                    self.learn(target, mac, false, ts);
+                }
+            },
+            NdpMessageType::RouterAdvertisement => {
+                let mac = info.source_link_address.clone();
+                self.learn(source, mac.as_deref().unwrap_or(&link_mac), true, ts);
+                self.routers.insert(source.to_string(), RouterInfo{
+                    address: source.to_string(),
+                    mac,
+                    lifetime: info.router_lifetime.unwrap_or(0),
+                    managed: info.managed,
+                    other_config: info.other_config,
+                    prefixes: info.prefixes.clone(),
+                    mtu: info.mtu,
+                    dns_servers: info.dns_servers.clone(),
+                    last_seen: ts,
+                });
+            }
+        }
+    }
+
+    /// Removes, at timestamp `now` (in seconds), the routers whose lifetime elapsed since their last advertisement and
+    /// the neighbors not seen for longer than the timeout.
+    pub fn purge_expired(&mut self, now: u64) {
+        let deadline = now.saturating_sub(NEIGHBOR_TIMEOUT_S);
+        self.neighbors.retain(|_, neighbor| neighbor.last_seen >= deadline);
+        self.routers.retain(|_, router| {
+            let lifetime = if router.lifetime == 0 { NEIGHBOR_TIMEOUT_S } else { router.lifetime as u64 };
+            router.last_seen + lifetime >= now
+        });
+    }
+
+    /// Returns the known IPv6 neighbors, sorted by address.
+    pub fn neighbors(&self) -> Vec<NeighborEntry> {
+        let mut neighbors: Vec<NeighborEntry> = self.neighbors.values().cloned().collect();
+        neighbors.sort_by(|a, b| a.address.cmp(&b.address));
+        neighbors
+    }
+
+    /// Returns the routers that sent a Router Advertisement, sorted by address.
+    pub fn routers(&self) -> Vec<RouterInfo> {
+        let mut routers: Vec<RouterInfo> = self.routers.values().cloned().collect();
+        routers.sort_by(|a, b| a.address.cmp(&b.address));
+        routers
+    }
+
+    /// Generates the IPv6 neighbors section of a report, listing the neighbors and then the routers with their prefixes.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----IPv6 neighbors-----\n");
+        for neighbor in self.neighbors() {
+            s.push_str(&format!("{} -> {}{}; MAC changes: {}; First seen: {}; Last seen: {}\n",
+                                neighbor.address,
+                                neighbor.mac,
+                                if neighbor.is_router { " (router)" } else { "" },
+                                neighbor.mac_changes,
+                                neighbor.first_seen,
+                                neighbor.last_seen));
+        }
+        for router in self.routers() {
+            let prefixes: Vec<&str> = router.prefixes.iter().map(|p| p.prefix.as_str()).collect();
+            s.push_str(&format!("Router {}; Lifetime: {} s; Flags: {}{}; Prefixes: {}; MTU: {}; RDNSS: {}; Last seen: {}\n",
+                                router.address,
+                                router.lifetime,
+                                if router.managed { "M" } else { "-" },
+                                if router.other_config { "O" } else { "-" },
+                                prefixes.join(", "),
+                                router.mtu.map(|m| m.to_string()).unwrap_or_else(|| "Unknown".to_string()),
+                                router.dns_servers.join(", "),
+                                router.last_seen));
+        }
+        s.push('\n');
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::Icmpv6Slice;
+
+    /// Returns the octets of an IPv6 address.
+    fn address(address: &str) -> [u8; 16] {
+        address.parse::<Ipv6Addr>().unwrap().octets()
+    }
+
+    /// Parses an ICMPv6 message made of the given type, the rest of the header and the body.
+    fn parse(type_u8: u8, rest: [u8; 4], body: &[u8]) -> Option<NdpInfo> {
+        let mut message = vec![type_u8, 0, 0, 0];
+        message.extend_from_slice(&rest);
+        message.extend_from_slice(body);
+        parse_ndp(&Some(TransportSlice::Icmpv6(Icmpv6Slice::from_slice(&message).unwrap())))
+    }
+
+    #[test]
+    fn parses_router_advertisement_options() {
+        // M and O flags, router lifetime of 1800 seconds, then reachable time and retransmission timer
+        let mut body = vec![0; 8];
+        body.extend_from_slice(&[1, 1, 0x02, 0, 0, 0, 0, 0x01]);
+        body.extend_from_slice(&[5, 1, 0, 0, 0, 0, 0x05, 0xdc]);
+        body.extend_from_slice(&[3, 4, 64, 0xc0, 0x00, 0x01, 0x51, 0x80, 0x00, 0x00, 0x38, 0x40, 0, 0, 0, 0]);
+        body.extend_from_slice(&address("2001:db8:1::"));
+        body.extend_from_slice(&[25, 5, 0, 0, 0, 0, 0x0e, 0x10]);
+        body.extend_from_slice(&address("2001:db8::53"));
+        body.extend_from_slice(&address("2001:db8::54"));
+        let info = parse(134, [64, 0xc0, 0x07, 0x08], &body).unwrap();
+        assert_eq!(info.message_type, NdpMessageType::RouterAdvertisement);
+        assert_eq!((info.router_lifetime, info.managed, info.other_config), (Some(1800), true, true));
+        assert_eq!(info.source_link_address.as_deref(), Some("02:00:00:00:00:01"));
+        assert_eq!(info.mtu, Some(1500));
+        assert_eq!(info.prefixes, vec![NdpPrefix{prefix: "2001:db8:1::/64".to_string(), on_link: true, autonomous: true, valid_lifetime: 86400, preferred_lifetime: 14400}]);
+        assert_eq!(info.dns_servers, vec!["2001:db8::53", "2001:db8::54"]);
+    }
+
+    #[test]
+    fn parses_neighbor_advertisement() {
+        let mut body = address("2001:db8::1").to_vec();
+        body.extend_from_slice(&[2, 1, 0x02, 0, 0, 0, 0, 0x02]);
+        let info = parse(136, [0xe0, 0, 0, 0], &body).unwrap();
+        assert_eq!(info.target.as_deref(), Some("2001:db8::1"));
+        assert_eq!((info.router, info.solicited, info.override_flag), (true, true, true));
+        assert_eq!(info.target_link_address.as_deref(), Some("02:00:00:00:00:02"));
+        assert_eq!(ndp_info_to_string(&info), "Target: 2001:db8::1; Flags: RSO; Target link-layer address: 02:00:00:00:00:02");
+        // The target is mandatory
+        assert!(parse(136, [0x60, 0, 0, 0], &body[..15]).is_none());
+        // Other ICMPv6 messages are not Neighbor Discovery
+        assert!(parse(128, [0, 1, 0, 1], &[]).is_none());
+    }
+
+    #[test]
+    fn zero_length_option_stops_the_options() {
+        let mut body = address("2001:db8::1").to_vec();
+        body.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        body.extend_from_slice(&[1, 1, 0x02, 0, 0, 0, 0, 0x01]);
+        let info = parse(135, [0; 4], &body).unwrap();
+        assert_eq!(info.target.as_deref(), Some("2001:db8::1"));
+        assert_eq!(info.source_link_address, None);
+    }
+
+    #[test]
+    fn truncated_or_too_many_options_are_not_read() {
+        // An option longer than the message
+        let mut body = address("2001:db8::1").to_vec();
+        body.extend_from_slice(&[1, 2, 0x02, 0, 0, 0, 0, 0x01]);
+        assert_eq!(parse(135, [0; 4], &body).unwrap().source_link_address, None);
+        // Options after the limit are ignored
+        let mut body = address("2001:db8::1").to_vec();
+        for _ in 0..MAX_NDP_OPTIONS {
+            body.extend_from_slice(&[99, 1, 0, 0, 0, 0, 0, 0]);
+        }
+        body.extend_from_slice(&[1, 1, 0x02, 0, 0, 0, 0, 0x01]);
+        assert_eq!(parse(135, [0; 4], &body).unwrap().source_link_address, None);
+    }
+
+    fn advertisement(message_type: NdpMessageType, router_lifetime: Option<u16>) -> NdpInfo {
+        NdpInfo{message_type, target: None, redirect_destination: None, source_link_address: None, target_link_address: None, router_lifetime,
+            managed: false, other_config: false, router: false, solicited: false, override_flag: false,
+            prefixes: Vec::new(), mtu: None, dns_servers: Vec::new()}
+    }
+
+    #[test]
+    fn routers_expire_with_their_lifetime() {
+        let mut table = NeighborTable::new();
+        table.register(&advertisement(NdpMessageType::RouterAdvertisement, Some(300)), "fe80::1", [2, 0, 0, 0, 0, 1], 0);
+        table.register(&advertisement(NdpMessageType::RouterAdvertisement, Some(0)), "fe80::2", [2, 0, 0, 0, 0, 2], 0);
+        table.purge_expired(300);
+        assert_eq!(table.routers().len(), 2);
+        table.purge_expired(301);
+        let routers = table.routers();
+        assert_eq!(routers.len(), 1);
+        assert_eq!(routers[0].address, "fe80::2");
+        assert_eq!(table.neighbors().len(), 2);
+        table.purge_expired(NEIGHBOR_TIMEOUT_S + 1);
+        assert!(table.routers().is_empty());
+        assert!(table.neighbors().is_empty());
+    }
+}
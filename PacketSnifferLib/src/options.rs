@@ -0,0 +1,103 @@
+use crate::classify::AppSignature;
+use crate::rollup::NamedSubnet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///Enum representing which flows are written in each report.
+pub enum ReportMode {
+    /// The flows of the last interval (or exported by the flow cache since the previous report)
+    Delta,
+    /// The flows since the start of the capture, with their running totals
+    Cumulative,
+    /// Both the flows of the last interval and the ones since the start of the capture, in two separate parts
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///Enum representing the order of the flows listed in a report.
+pub enum FlowOrder {
+    /// By address and port of the endpoints
+    Address,
+    /// The flows with the most bytes first
+    Bytes,
+    /// The flows with the most packets first
+    Packets,
+    /// The oldest flows first
+    FirstSeen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///Enum representing which flows are evicted when the report map reaches its maximum size.
+pub enum EvictionPolicy {
+    /// The flows whose last packet is the oldest
+    LeastRecentlyUsed,
+    /// The flows with the fewest bytes
+    SmallestFirst,
+}
+
+#[derive(Debug, Clone, Default)]
+///Struct containing the granularity of the traffic rollups written besides the flows.
+pub struct AggregationOptions {
+    /// True if the traffic is aggregated per host
+    pub hosts: bool,
+    /// Length of the prefix of the IPv4 subnets the traffic is aggregated per, e.g. 24
+    pub ipv4_prefix: Option<u8>,
+    /// Length of the prefix of the IPv6 subnets the traffic is aggregated per, e.g. 64
+    pub ipv6_prefix: Option<u8>,
+    /// User-defined subnets the traffic is aggregated per
+    pub subnets: Vec<NamedSubnet>,
+}
+
+impl AggregationOptions {
+    /// Checks if the traffic is aggregated per subnet, either by prefix length or by user-defined subnets.
+    pub fn subnet_view(&self) -> bool {
+        !self.subnets.is_empty() || self.ipv4_prefix.is_some() || self.ipv6_prefix.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+///Struct containing the timeouts of the flow cache, which exports each flow when it ends instead of at every interval.
+pub struct FlowTimeouts {
+    /// Seconds without packets after which a flow is exported
+    pub idle_s: u64,
+    /// Seconds from the first packet after which a flow is exported, even if it is still active
+    pub active_s: u64,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the settings that tune how the captured packets are parsed and reported.
+pub struct CaptureOptions {
+    /// Ports, besides the standard ones (53, 5353 and 5355), on which the traffic is decoded as DNS
+    pub extra_dns_ports: Vec<u16>,
+    /// Time in milliseconds after which a DNS query without response is counted as unanswered
+    pub dns_timeout_ms: u64,
+    /// Time in milliseconds after which an echo request without reply is counted as lost
+    pub ping_timeout_ms: u64,
+    /// Ports on which WireGuard transport data is recognized also when the handshake of its session was not captured
+    pub wireguard_ports: Vec<u16>,
+    /// Number of entries listed in the ranked sections of a report
+    pub top_n: usize,
+    /// Number of payload bytes inspected in each direction of a flow to recognize its application protocol
+    pub inspected_bytes: usize,
+    /// User-defined signatures, checked before the built-in ones
+    pub signatures: Vec<AppSignature>,
+    /// True if the packets carried by tunnels are extracted and reported in place of the tunnels
+    pub decapsulate_tunnels: bool,
+    /// Timeouts of the flow cache; if None, each report contains the flows of the last interval
+    pub flow_timeouts: Option<FlowTimeouts>,
+    /// Flows written in each report
+    pub report_mode: ReportMode,
+    /// Maximum number of flows kept in the report map; if None, the map is not bounded
+    pub max_flows: Option<usize>,
+    /// Flows evicted when the report map is full
+    pub eviction_policy: EvictionPolicy,
+    /// Order of the flows listed in a report; flows that are equal for the order are listed by address
+    pub flow_order: FlowOrder,
+    /// Views of the traffic aggregated per host and per subnet
+    pub aggregation: AggregationOptions,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions{extra_dns_ports: Vec::new(), dns_timeout_ms: 5000, ping_timeout_ms: 2000, wireguard_ports: Vec::new(), top_n: 10, inspected_bytes: 512, signatures: Vec::new(), decapsulate_tunnels: true, flow_timeouts: None, report_mode: ReportMode::Delta, max_flows: None, eviction_policy: EvictionPolicy::LeastRecentlyUsed, flow_order: FlowOrder::Address, aggregation: AggregationOptions::default()}
+    }
+}
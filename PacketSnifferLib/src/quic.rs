@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+use aes::Aes128;
+use aes::cipher::BlockEncrypt;
+use aes_gcm::{Aes128Gcm, Nonce};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use crate::tls::{parse_handshake_message, TlsClientHello, TlsHandshake};
+
+/// QUIC version 1 (RFC 9000).
+pub const QUIC_V1: u32 = 0x0000_0001;
+/// QUIC version 2 (RFC 9369).
+pub const QUIC_V2: u32 = 0x6b33_43cf;
+/// Last draft version of QUIC deployed on a large scale.
+pub const QUIC_DRAFT_29: u32 = 0xff00_001d;
+
+/// Salt used to derive the Initial secrets of QUIC version 1.
+const SALT_V1: [u8; 20] = [0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb, 0x7f, 0x0a];
+/// Salt used to derive the Initial secrets of QUIC version 2.
+const SALT_V2: [u8; 20] = [0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb, 0xf9, 0xbd, 0x2e, 0xd9];
+/// Salt used to derive the Initial secrets of QUIC draft 29.
+const SALT_DRAFT_29: [u8; 20] = [0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11, 0xe0, 0x43, 0x90, 0xa8, 0x99];
+/// Maximum number of connections whose ClientHello is being reassembled.
+const MAX_PENDING_CONNECTIONS: usize = 1024;
+/// Maximum number of CRYPTO bytes buffered for a single connection.
+const MAX_CRYPTO_BYTES: usize = 16384;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///Enum representing the types of QUIC long header packets.
+pub enum QuicPacketType {
+    /// Packet carrying the first handshake messages
+    Initial,
+    /// Packet carrying early data
+    ZeroRtt,
+    /// Packet carrying the rest of the handshake
+    Handshake,
+    /// Packet asking the client to retry with a token
+    Retry,
+    /// Packet listing the versions supported by the server
+    VersionNegotiation,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the fields of a QUIC long header.
+pub struct QuicLongHeader {
+    /// Version of the packet
+    pub version: u32,
+    /// Type of the packet
+    pub packet_type: QuicPacketType,
+    /// Destination connection ID
+    pub dcid: Vec<u8>,
+    /// Source connection ID
+    pub scid: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+///Struct which reports info relative to the QUIC packets of a flow.
+pub struct QuicInfo {
+    /// Version of the connection
+    pub version: u32,
+    /// Number of long header packets observed
+    pub long_header_packets: u32,
+    /// True if the ClientHello was decrypted from the Initial packets
+    pub client_hello_decrypted: bool,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing what was learned from a UDP datagram carrying QUIC packets.
+pub struct QuicDatagram {
+    /// Header of the first packet of the datagram
+    pub header: QuicLongHeader,
+    /// Number of long header packets coalesced in the datagram
+    pub long_header_packets: u32,
+    /// ClientHello, when the datagram completes it
+    pub client_hello: Option<TlsClientHello>,
+}
+
+#[derive(Debug, Default)]
+///Struct containing the CRYPTO bytes received for a connection.
+struct CryptoBuffer {
+    /// Bytes received from offset 0 without holes
+    stream: Vec<u8>,
+    /// Frames (offset, data) received after a hole, sorted by offset
+    pending: Vec<(usize, Vec<u8>)>,
+}
+
+impl CryptoBuffer {
+    /// Number of bytes buffered.
+    fn len(&self) -> usize {
+        self.stream.len() + self.pending.iter().map(|(_, data)| data.len()).sum::<usize>()
+    }
+
+    /// Adds a CRYPTO frame, dropping the bytes already received. It returns false if the frame ends after the maximum
+    /// number of CRYPTO bytes of a connection, or if too many bytes are buffered.
+    fn add(&mut self, offset: u64, data: Vec<u8>) -> bool {
+        let end = offset.saturating_add(data.len() as u64);
+        if end > MAX_CRYPTO_BYTES as u64 {
+            return false;
+        }
+        let (offset, end) = (offset as usize, end as usize);
+        // Retransmitted frames carry bytes that were already received
+        if end <= self.stream.len() {
+            return true;
+        }
+        if offset > self.stream.len() {
+            if self.pending.iter().any(|(o, d)| *o <= offset && o + d.len() >= end) {
+                return true;
+            }
+            if self.len() + data.len() > MAX_CRYPTO_BYTES {
+                return false;
+            }
+            self.pending.push((offset, data));
+            self.pending.sort_by_key(|(o, _)| *o);
+            return true;
+        }
+        self.stream.extend_from_slice(&data[self.stream.len() - offset..]);
+        // The frames received after the hole can follow the new bytes
+        while let Some(i) = self.pending.iter().position(|(o, _)| *o <= self.stream.len()) {
+            let (o, d) = self.pending.remove(i);
+            if o + d.len() > self.stream.len() {
+                self.stream.extend_from_slice(&d[self.stream.len() - o..]);
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+///Struct that reassembles the ClientHello of QUIC connections, which can be split across several CRYPTO frames and Initial packets.
+pub struct QuicTracker {
+    /// CRYPTO bytes received for each original destination connection ID
+    buffers: HashMap<Vec<u8>, CryptoBuffer>,
+}
+
+impl QuicTracker {
+    /// Create a new QuicTracker struct.
+    pub fn new() -> QuicTracker {
+        QuicTracker{buffers: HashMap::new()}
+    }
+
+    /// Adds the CRYPTO frames of a client Initial packet, returning the ClientHello when its bytes are all available.
+    /// The bytes already received are dropped, and the connections that send more than `MAX_CRYPTO_BYTES` are forgotten.
+    fn add_crypto_frames(&mut self, dcid: &[u8], frames: Vec<(u64, Vec<u8>)>) -> Option<TlsClientHello> {
+        if self.buffers.len() >= MAX_PENDING_CONNECTIONS && !self.buffers.contains_key(dcid) {
+            self.buffers.clear();
+        }
+        let buffer = self.buffers.entry(dcid.to_vec()).or_default();
+        for (offset, data) in frames {
+            if !buffer.add(offset, data) {
+                self.buffers.remove(dcid);
+                return None;
+            }
+        }
+        let stream = &buffer.stream;
+        if stream.len() < 4 {
+            return None;
+        }
+        let message_len = ((stream[1] as usize) << 16) | ((stream[2] as usize) << 8) | stream[3] as usize;
+        if 4 + message_len > MAX_CRYPTO_BYTES {
+            self.buffers.remove(dcid);
+            return None;
+        }
+        if stream.len() < 4 + message_len {
+            return None;
+        }
+        let hello = match parse_handshake_message(stream) {
+            Some(TlsHandshake::ClientHello(hello)) => Some(hello),
+            _ => None
+        };
+        self.buffers.remove(dcid);
+        hello
+    }
+}
+
+/// Function that returns the name of a QUIC version.
+pub fn quic_version_name(version: u32) -> String {
+    match version {
+        QUIC_V1 => "QUIC v1".to_string(),
+        QUIC_V2 => "QUIC v2".to_string(),
+        QUIC_DRAFT_29 => "QUIC draft-29".to_string(),
+        0 => "Version Negotiation".to_string(),
+        _ => format!("0x{:08x}", version)
+    }
+}
+
+/// Function that reads a QUIC variable-length integer, advancing `pos`.
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let first = *data.get(*pos)?;
+    let len = 1 << (first >> 6);
+    if *pos + len > data.len() {
+        return None;
+    }
+    let mut value = (first & 0x3f) as u64;
+    for b in &data[*pos + 1..*pos + len] {
+        value = (value << 8) | *b as u64;
+    }
+    *pos += len;
+    Some(value)
+}
+
+/// Function that parses the long header at the beginning of `data`, returning the header and the offset after the connection IDs.
+pub fn parse_long_header(data: &[u8]) -> Option<(QuicLongHeader, usize)> {
+    if data.len() < 7 || data[0] & 0x80 == 0 {
+        return None;
+    }
+    let version = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+    let packet_type = if version == 0 {
+        QuicPacketType::VersionNegotiation
+    } else {
+        if data[0] & 0x40 == 0 {
+            return None;
+        }
+        let bits = (data[0] >> 4) & 0x03;
+        let bits = if version == QUIC_V2 { (bits + 3) & 0x03 } else { bits };
+        match bits {
+            0 => QuicPacketType::Initial,
+            1 => QuicPacketType::ZeroRtt,
+            2 => QuicPacketType::Handshake,
+            _ => QuicPacketType::Retry
+        }
+    };
+    let mut pos = 5;
+    let dcid_len = data[pos] as usize;
+    pos += 1;
+    if dcid_len > 20 || pos + dcid_len >= data.len() {
+        return None;
+    }
+    let dcid = data[pos..pos + dcid_len].to_vec();
+    pos += dcid_len;
+    let scid_len = data[pos] as usize;
+    pos += 1;
+    if scid_len > 20 || pos + scid_len > data.len() {
+        return None;
+    }
+    let scid = data[pos..pos + scid_len].to_vec();
+    pos += scid_len;
+    Some((QuicLongHeader{version, packet_type, dcid, scid}, pos))
+}
+
+/// Function that checks if a UDP payload starts with a QUIC long header of a known version.
+/// Version Negotiation packets are not accepted: their version is 0, so any UDP payload whose first byte has the high bit
+/// set and whose next four bytes are zero would match. They are recognized with `is_version_negotiation` only on the flows
+/// already identified as QUIC.
+pub fn is_quic(payload: &[u8]) -> bool {
+    match parse_long_header(payload) {
+        Some((header, _)) => matches!(header.version, QUIC_V1 | QUIC_V2 | QUIC_DRAFT_29),
+        None => false
+    }
+}
+
+/// Function that checks if a UDP payload is a QUIC Version Negotiation packet.
+pub fn is_version_negotiation(payload: &[u8]) -> bool {
+    matches!(parse_long_header(payload), Some((header, _)) if header.packet_type == QuicPacketType::VersionNegotiation)
+}
+
+/// Function that implements HKDF-Expand-Label of TLS 1.3 with an empty context.
+fn hkdf_expand_label(hk: &Hkdf<Sha256>, label: &str, len: usize) -> Option<Vec<u8>> {
+    let full_label = format!("tls13 {}", label);
+    let mut info = Vec::new();
+    info.extend_from_slice(&(len as u16).to_be_bytes());
+    info.push(full_label.len() as u8);
+    info.extend_from_slice(full_label.as_bytes());
+    info.push(0);
+    let mut out = vec![0u8; len];
+    hk.expand(&info, &mut out).ok()?;
+    Some(out)
+}
+
+/// Function that derives key, iv and header protection key that the client uses for the Initial packets of a connection.
+fn client_initial_keys(version: u32, dcid: &[u8]) -> Option<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let (salt, prefix): (&[u8], &str) = match version {
+        QUIC_V1 => (&SALT_V1, "quic"),
+        QUIC_V2 => (&SALT_V2, "quicv2"),
+        QUIC_DRAFT_29 => (&SALT_DRAFT_29, "quic"),
+        _ => return None
+    };
+    let (_, initial) = Hkdf::<Sha256>::extract(Some(salt), dcid);
+    let client_secret = hkdf_expand_label(&initial, "client in", 32)?;
+    let client = Hkdf::<Sha256>::from_prk(&client_secret).ok()?;
+    let key = hkdf_expand_label(&client, &format!("{} key", prefix), 16)?;
+    let iv = hkdf_expand_label(&client, &format!("{} iv", prefix), 12)?;
+    let hp = hkdf_expand_label(&client, &format!("{} hp", prefix), 16)?;
+    Some((key, iv, hp))
+}
+
+/// Function that removes header protection and decrypts a client Initial packet.
+/// `packet` contains the whole packet and `pn_offset` is the offset of the packet number. Packets sent by the server fail the authentication and return `None`.
+fn decrypt_initial(packet: &[u8], pn_offset: usize, version: u32, dcid: &[u8]) -> Option<Vec<u8>> {
+    let (key, iv, hp) = client_initial_keys(version, dcid)?;
+    let sample = packet.get(pn_offset + 4..pn_offset + 20)?;
+    let hp_cipher = Aes128::new_from_slice(&hp).ok()?;
+    let mut mask = aes::Block::clone_from_slice(sample);
+    hp_cipher.encrypt_block(&mut mask);
+
+    let mut header = packet[..pn_offset].to_vec();
+    header[0] ^= mask[0] & 0x0f;
+    let pn_len = (header[0] & 0x03) as usize + 1;
+    let mut packet_number: u64 = 0;
+    for i in 0..pn_len {
+        let b = *packet.get(pn_offset + i)? ^ mask[1 + i];
+        header.push(b);
+        packet_number = (packet_number << 8) | b as u64;
+    }
+
+    let mut nonce = iv;
+    for (i, b) in packet_number.to_be_bytes().iter().enumerate() {
+        nonce[4 + i] ^= b;
+    }
+    let cipher = Aes128Gcm::new_from_slice(&key).ok()?;
+    cipher.decrypt(Nonce::from_slice(&nonce), Payload{msg: &packet[pn_offset + pn_len..], aad: &header}).ok()
+}
+
+/// Function that returns the CRYPTO frames (offset, data) of a decrypted Initial packet, skipping the other frames allowed in it.
+fn crypto_frames(plain: &[u8]) -> Vec<(u64, Vec<u8>)> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while pos < plain.len() {
+        let frame_type = match read_varint(plain, &mut pos) {
+            Some(t) => t,
+            None => break
+        };
+        let complete = match frame_type {
+            // PADDING and PING
+            0x00 | 0x01 => Some(()),
+            // ACK, with or without ECN counts
+            0x02 | 0x03 => (|| {
+                read_varint(plain, &mut pos)?;
+                read_varint(plain, &mut pos)?;
+                let ranges = read_varint(plain, &mut pos)?;
+                read_varint(plain, &mut pos)?;
+                for _ in 0..ranges {
+                    read_varint(plain, &mut pos)?;
+                    read_varint(plain, &mut pos)?;
+                }
+                if frame_type == 0x03 {
+                    for _ in 0..3 {
+                        read_varint(plain, &mut pos)?;
+                    }
+                }
+                Some(())
+            })(),
+            // CRYPTO
+            0x06 => (|| {
+                let offset = read_varint(plain, &mut pos)?;
+                let len = read_varint(plain, &mut pos)? as usize;
+                let data = plain.get(pos..pos + len)?;
+                pos += len;
+                frames.push((offset, data.to_vec()));
+                Some(())
+            })(),
+            // CONNECTION_CLOSE
+            0x1c | 0x1d => (|| {
+                read_varint(plain, &mut pos)?;
+                if frame_type == 0x1c {
+                    read_varint(plain, &mut pos)?;
+                }
+                let len = read_varint(plain, &mut pos)? as usize;
+                pos += len;
+                Some(())
+            })(),
+            _ => None
+        };
+        if complete.is_none() {
+            break;
+        }
+    }
+    frames
+}
+
+/// Function that parses a UDP datagram carrying QUIC packets. The client Initial packets are decrypted with the keys derived from
+/// their destination connection ID, and their CRYPTO frames are passed to `tracker` to reassemble the ClientHello.
+pub fn parse_quic_datagram(payload: &[u8], tracker: &mut QuicTracker) -> Option<QuicDatagram> {
+    let (first_header, _) = parse_long_header(payload)?;
+    let mut result = QuicDatagram{header: first_header, long_header_packets: 0, client_hello: None};
+    let mut offset = 0;
+    // Several long header packets can be coalesced in the same datagram
+    while offset < payload.len() {
+        let packet = &payload[offset..];
+        let (header, mut pos) = match parse_long_header(packet) {
+            Some(h) => h,
+            None => break
+        };
+        result.long_header_packets += 1;
+        match header.packet_type {
+            QuicPacketType::VersionNegotiation | QuicPacketType::Retry => break,
+            QuicPacketType::Initial => {
+                let token_len = match read_varint(packet, &mut pos) {
+                    Some(len) => len as usize,
+                    None => break
+                };
+                pos += token_len;
+            },
+            _ => {}
+        }
+        let len = match read_varint(packet, &mut pos) {
+            Some(len) => len as usize,
+            None => break
+        };
+        let end = (pos + len).min(packet.len());
+        if header.packet_type == QuicPacketType::Initial {
+            if let Some(plain) = decrypt_initial(&packet[..end], pos, header.version, &header.dcid) {
+                let frames = crypto_frames(&plain);
+                if !frames.is_empty() {
+                    if let Some(hello) = tracker.add_crypto_frames(&header.dcid, frames) {
+                        result.client_hello = Some(hello);
+                    }
+                }
+            }
+        }
+        offset += end;
+    }
+    Some(result)
+}
+
+/// The function takes as parameter a `QuicInfo` struct and generate a String containing its formatted informations.
+pub fn quic_info_to_string(info: &QuicInfo) -> String {
+    format!("Version: {}; Long header packets: {}; ClientHello decrypted: {}\n",
+            quic_version_name(info.version), info.long_header_packets, info.client_hello_decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Destination connection ID of the client Initial packet of RFC 9001, Appendix A.
+    const RFC9001_DCID: &str = "8394c8f03e515708";
+    /// Protected client Initial packet of RFC 9001, Appendix A.2.
+    const RFC9001_CLIENT_INITIAL: &str = concat!(
+        "c000000001088394c8f03e5157080000449e7b9aec34d1b1c98dd7689fb8ec11d242b123dc9bd8bab936b47d92ec356c",
+        "0bab7df5976d27cd449f63300099f3991c260ec4c60d17b31f8429157bb35a1282a643a8d2262cad67500cadb8e7378c",
+        "8eb7539ec4d4905fed1bee1fc8aafba17c750e2c7ace01e6005f80fcb7df621230c83711b39343fa028cea7f7fb5ff89",
+        "eac2308249a02252155e2347b63d58c5457afd84d05dfffdb20392844ae812154682e9cf012f9021a6f0be17ddd0c208",
+        "4dce25ff9b06cde535d0f920a2db1bf362c23e596d11a4f5a6cf3948838a3aec4e15daf8500a6ef69ec4e3feb6b1d98e",
+        "610ac8b7ec3faf6ad760b7bad1db4ba3485e8a94dc250ae3fdb41ed15fb6a8e5eba0fc3dd60bc8e30c5c4287e53805db",
+        "059ae0648db2f64264ed5e39be2e20d82df566da8dd5998ccabdae053060ae6c7b4378e846d29f37ed7b4ea9ec5d82e7",
+        "961b7f25a9323851f681d582363aa5f89937f5a67258bf63ad6f1a0b1d96dbd4faddfcefc5266ba6611722395c906556",
+        "be52afe3f565636ad1b17d508b73d8743eeb524be22b3dcbc2c7468d54119c7468449a13d8e3b95811a198f3491de3e7",
+        "fe942b330407abf82a4ed7c1b311663ac69890f4157015853d91e923037c227a33cdd5ec281ca3f79c44546b9d90ca00",
+        "f064c99e3dd97911d39fe9c5d0b23a229a234cb36186c4819e8b9c5927726632291d6a418211cc2962e20fe47feb3edf",
+        "330f2c603a9d48c0fcb5699dbfe5896425c5bac4aee82e57a85aaf4e2513e4f05796b07ba2ee47d80506f8d2c25e50fd",
+        "14de71e6c418559302f939b0e1abd576f279c4b2e0feb85c1f28ff18f58891ffef132eef2fa09346aee33c28eb130ff2",
+        "8f5b766953334113211996d20011a198e3fc433f9f2541010ae17c1bf202580f6047472fb36857fe843b19f5984009dd",
+        "c324044e847a4f4a0ab34f719595de37252d6235365e9b84392b061085349d73203a4a13e96f5432ec0fd4a1ee65accd",
+        "d5e3904df54c1da510b0ff20dcc0c77fcb2c0e0eb605cb0504db87632cf3d8b4dae6e705769d1de354270123cb11450e",
+        "fc60ac47683d7b8d0f811365565fd98c4c8eb936bcab8d069fc33bd801b03adea2e1fbc5aa463d08ca19896d2bf59a07",
+        "1b851e6c239052172f296bfb5e72404790a2181014f3b94a4e97d117b438130368cc39dbb2d198065ae3986547926cd2",
+        "162f40a29f0c3c8745c0f50fba3852e566d44575c29d39a03f0cda721984b6f440591f355e12d439ff150aab7613499d",
+        "bd49adabc8676eef023b15b65bfc5ca06948109f23f350db82123535eb8a7433bdabcb909271a6ecbcb58b936a88cd4e",
+        "8f2e6ff5800175f113253d8fa9ca8885c2f552e657dc603f252e1a8e308f76f0be79e2fb8f5d5fbbe2e30ecadd220723",
+        "c8c0aea8078cdfcb3868263ff8f0940054da48781893a7e49ad5aff4af300cd804a6b6279ab3ff3afb64491c85194aab",
+        "760d58a606654f9f4400e8b38591356fbf6425aca26dc85244259ff2b19c41b9f96f3ca9ec1dde434da7d2d392b905dd",
+        "f3d1f9af93d1af5950bd493f5aa731b4056df31bd267b6b90a079831aaf579be0a39013137aac6d404f518cfd4684064",
+        "7e78bfe706ca4cf5e9c5453e9f7cfd2b8b4c8d169a44e55c88d4a9a7f9474241e221af44860018ab0856972e194cd934"
+    );
+    /// Unprotected payload of the same packet: a CRYPTO frame with the ClientHello, followed by PADDING frames.
+    const RFC9001_CLIENT_PAYLOAD: &str = concat!(
+        "060040f1010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e86804fe3a47f06a2b69484c000004130113",
+        "02010000c000000010000e00000b6578616d706c652e636f6dff01000100000a00080006001d00170018001000070005",
+        "04616c706e000500050100000000003300260024001d00209370b2c9caa47fbabaf4559fedba753de171fa71f50f1ce1",
+        "5d43e994ec74d748002b0003020304000d0010000e0403050306030203080408050806002d00020101001c0002400100",
+        "3900320408ffffffffffffffff05048000ffff07048000ffff0801100104800075300901100f088394c8f03e51570806",
+        "048000ffff"
+    );
+
+    fn decode(data: &str) -> Vec<u8> {
+        hex::decode(data).unwrap()
+    }
+
+    #[test]
+    fn derives_client_initial_keys() {
+        // RFC 9001, Appendix A.1
+        let (key, iv, hp) = client_initial_keys(QUIC_V1, &decode(RFC9001_DCID)).unwrap();
+        assert_eq!(hex::encode(key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(hex::encode(iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(hex::encode(hp), "9f50449e04a0e810283a1e9933adedd2");
+    }
+
+    #[test]
+    fn decrypts_client_initial() {
+        // The packet number follows the long header, the empty token and the 2-byte length
+        let packet = decode(RFC9001_CLIENT_INITIAL);
+        let plain = decrypt_initial(&packet, 18, QUIC_V1, &decode(RFC9001_DCID)).unwrap();
+        let payload = decode(RFC9001_CLIENT_PAYLOAD);
+        assert_eq!(&plain[..payload.len()], &payload[..]);
+        assert_eq!(plain.len(), 1200 - 22 - 16);
+        assert!(plain[payload.len()..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn header_protection_is_removed() {
+        // The protected first byte and packet number are c0 and 7b9aec34, the unprotected ones c3 and 00000002
+        let mut packet = decode(RFC9001_CLIENT_INITIAL);
+        assert_eq!(packet[0], 0xc0);
+        assert_eq!(&packet[18..22], &[0x7b, 0x9a, 0xec, 0x34]);
+        assert!(decrypt_initial(&packet, 18, QUIC_V1, &decode(RFC9001_DCID)).is_some());
+        // A packet whose protected packet number was altered fails the authentication
+        packet[19] ^= 0x01;
+        assert!(decrypt_initial(&packet, 18, QUIC_V1, &decode(RFC9001_DCID)).is_none());
+    }
+
+    #[test]
+    fn parses_client_hello_of_client_initial() {
+        let mut tracker = QuicTracker::new();
+        let datagram = parse_quic_datagram(&decode(RFC9001_CLIENT_INITIAL), &mut tracker).unwrap();
+        assert_eq!(datagram.header.version, QUIC_V1);
+        assert_eq!(datagram.header.packet_type, QuicPacketType::Initial);
+        assert_eq!(datagram.long_header_packets, 1);
+        let hello = datagram.client_hello.unwrap();
+        assert_eq!(hello.sni.as_deref(), Some("example.com"));
+        assert_eq!(hello.alpn, vec!["alpn".to_string()]);
+        assert_eq!(hello.cipher_suites, vec![0x1301, 0x1302]);
+        assert!(tracker.buffers.is_empty());
+    }
+
+    #[test]
+    fn version_negotiation_is_not_quic_by_itself() {
+        // Version 0, empty connection IDs, supported version 1
+        let packet = decode("80000000000000000000000001");
+        assert!(!is_quic(&packet));
+        assert!(is_version_negotiation(&packet));
+        assert!(is_quic(&decode(RFC9001_CLIENT_INITIAL)));
+        assert!(!is_version_negotiation(&decode(RFC9001_CLIENT_INITIAL)));
+        // A long header of a reserved version, used to exercise version negotiation
+        assert!(!is_quic(&decode("c01a2a3a4a0000")));
+    }
+
+    #[test]
+    fn reassembles_crypto_frames_out_of_order() {
+        let message = decode(RFC9001_CLIENT_PAYLOAD)[4..].to_vec();
+        let mut tracker = QuicTracker::new();
+        assert!(tracker.add_crypto_frames(b"dcid", vec![(100, message[100..].to_vec())]).is_none());
+        assert!(tracker.add_crypto_frames(b"dcid", vec![(0, message[..60].to_vec())]).is_none());
+        let hello = tracker.add_crypto_frames(b"dcid", vec![(40, message[40..120].to_vec())]).unwrap();
+        assert_eq!(hello.sni.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn retransmitted_frames_are_not_buffered_again() {
+        let message = decode(RFC9001_CLIENT_PAYLOAD)[4..].to_vec();
+        let mut tracker = QuicTracker::new();
+        for _ in 0..1000 {
+            assert!(tracker.add_crypto_frames(b"dcid", vec![(0, message[..100].to_vec()), (150, message[150..200].to_vec())]).is_none());
+        }
+        assert_eq!(tracker.buffers[&b"dcid".to_vec()].len(), 150);
+    }
+
+    #[test]
+    fn frames_over_the_limit_drop_the_connection() {
+        let mut tracker = QuicTracker::new();
+        assert!(tracker.add_crypto_frames(b"dcid", vec![(0, vec![1, 0, 0])]).is_none());
+        assert!(tracker.add_crypto_frames(b"dcid", vec![(MAX_CRYPTO_BYTES as u64 - 10, vec![0; 20])]).is_none());
+        assert!(tracker.buffers.is_empty());
+        // Overlapping frames after a hole cannot buffer more than the limit either
+        for i in 0..64 {
+            tracker.add_crypto_frames(b"dcid", vec![(0, vec![1]), (10 + i, vec![0; 1000])]);
+        }
+        assert!(tracker.buffers.get(&b"dcid".to_vec()).map_or(0, |b| b.len()) <= MAX_CRYPTO_BYTES);
+    }
+}
@@ -12,10 +12,26 @@ use std::fs::{OpenOptions};
 use std::io::{BufWriter, Write};
 use chrono::{DateTime, Local};
 use etherparse::icmpv4::ParameterProblemHeader::{PointerIndicatesError, MissingRequiredOption, BadLength};
+use std::net::{Ipv4Addr, Ipv6Addr};
 use simple_dns::{CLASS, QCLASS, QTYPE};
+use simple_dns::rdata::RData;
 use crate::PacketSnifferError;
+use crate::options::{CaptureOptions, EvictionPolicy, FlowTimeouts};
+use crate::trackers::Trackers;
+use crate::tls::{tls_info_to_string, TlsHandshake, TlsInfo};
+use crate::http::{http_info_to_string, http_summary_to_string, HttpInfo, HttpMessage};
+use crate::quic::{quic_info_to_string, QuicDatagram, QuicInfo};
+use crate::dhcp::DhcpLease;
+use crate::classify::FlowClassifier;
+use crate::tunnel::{tunnel_info_to_string, TunnelInfo};
+use crate::multicast::IGMP_PROTOCOL;
+use crate::vpn::{AH_PROTOCOL, ESP_PROTOCOL};
+use crate::summary::{sort_flows, summary_to_string};
+
+/// Maximum number of distinct application protocol summaries remembered for a single flow in a report.
+const MAX_APPLICATION_INFO_PER_FLOW: usize = 50;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 ///Struct useful to contains info relative to each single packet.
 pub struct Report {
     /// First timestamp of the packet
@@ -23,17 +39,41 @@ pub struct Report {
     /// Last timestamp of the packet
     last_ts: u64,
     /// Total packet bytes
-    total_bytes: u32,
+    total_bytes: u64,
+    /// Total number of packets
+    total_packets: u64,
     /// Informations about transport layer protocol
     transport_layer_protocols: HashSet<String>,
     /// Informations about network layer protocol
     network_layer_protocols: String,
+    /// Informations about the transport layer headers, e.g. the SCTP verification tags and chunk types
+    transport_info: HashSet<String>,
     /// Informations about link layer protocol
     link_layer_info: HashSet<LinkInfo>,
     /// Informations about icmp messages
     icmp_info: HashSet<String>,
     /// Informations about dns packet
-    dns_info: HashSet<String>
+    dns_info: HashSet<String>,
+    /// Informations about the TLS handshake
+    tls_info: Option<TlsInfo>,
+    /// Informations about the HTTP/1.x messages
+    http_info: Option<HttpInfo>,
+    /// Informations about the QUIC packets
+    quic_info: Option<QuicInfo>,
+    /// Informations about other application protocols, e.g. DHCP, NTP, SNMP, syslog, IGMP, MLD, IKE and WireGuard
+    application_info: HashSet<String>,
+    /// Application protocol summaries not stored because the flow reached the limit
+    dropped_application_info: u32,
+    /// Classification of the application protocol, made on the first bytes of payload
+    classifier: FlowClassifier,
+    /// Tunnels that carried the packets of the flow
+    tunnels: HashSet<TunnelInfo>,
+    /// Endpoints (address and port) that sent a TCP FIN
+    fin_senders: HashSet<String>,
+    /// True if a TCP RST was observed
+    reset: bool,
+    /// Reason why the flow cache exported the flow, e.g. "Idle timeout"
+    export_reason: Option<String>
 }
 
 /// Struct representing a Mac address.
@@ -62,13 +102,14 @@ impl Report {
         icmp_set.insert(icmp_string);
         dns_set.insert(dns_string);
 
-        Report{first_ts: ts, last_ts: ts, total_bytes: bytes, transport_layer_protocols: t_set, network_layer_protocols: nlp, link_layer_info: l_set, icmp_info: icmp_set, dns_info: dns_set}
+        Report{first_ts: ts, last_ts: ts, total_bytes: bytes as u64, total_packets: 1, transport_layer_protocols: t_set, transport_info: HashSet::new(), network_layer_protocols: nlp, link_layer_info: l_set, icmp_info: icmp_set, dns_info: dns_set, tls_info: None, http_info: None, quic_info: None, application_info: HashSet::new(), dropped_application_info: 0, classifier: FlowClassifier::default(), tunnels: HashSet::new(), fin_senders: HashSet::new(), reset: false, export_reason: None}
     }
 
     /// Update an existing Report struct, replacing each field of the struct with the fields passed through parameters.
     pub fn update_report(&mut self, ts: u64, bytes: u32, tlp: String, nlp: String, llp: LinkInfo, icmp_inf: String, dns_inf: String) {
         self.last_ts = ts;
-        self.total_bytes += bytes;
+        self.total_bytes += bytes as u64;
+        self.total_packets += 1;
         self.transport_layer_protocols.insert(tlp);
         self.network_layer_protocols = nlp;
         self.link_layer_info.insert(llp);
@@ -76,10 +117,278 @@ impl Report {
         self.dns_info.insert(dns_inf);
     }
 
+    /// Update the TLS informations of the Report struct with a handshake message observed in the flow.
+    /// `transport` is 't' for handshakes carried by TCP and 'q' for handshakes carried by QUIC.
+    pub fn add_tls_handshake(&mut self, handshake: &TlsHandshake, transport: char) {
+        self.tls_info.get_or_insert_with(TlsInfo::default).update(handshake, transport);
+    }
+
+    /// Marks the TLS handshake of the flow as incomplete, when the segments of a hello message were lost.
+    pub fn mark_tls_incomplete(&mut self) {
+        self.tls_info.get_or_insert_with(TlsInfo::default).incomplete_handshake = true;
+    }
+
+    /// Informations about the TLS handshake of the flow, if it was observed.
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.tls_info.as_ref()
+    }
+
+    /// Update the HTTP informations of the Report struct with a request or response sent by `sender` ("address:port").
+    /// The sender of a response is recorded as the server of the flow.
+    pub fn add_http_message(&mut self, message: HttpMessage, sender: &str) {
+        let info = self.http_info.get_or_insert_with(HttpInfo::default);
+        if let HttpMessage::Response(_) = message {
+            info.server = Some(sender.to_string());
+        }
+        info.add(message);
+    }
+
+    /// Informations about the HTTP/1.x messages of the flow, if any was observed.
+    pub fn http_info(&self) -> Option<&HttpInfo> {
+        self.http_info.as_ref()
+    }
+
+    /// Update the QUIC informations of the Report struct with a datagram observed in the flow, labelling the flow as QUIC.
+    pub fn add_quic_datagram(&mut self, datagram: &QuicDatagram) {
+        self.transport_layer_protocols.insert("QUIC".to_string());
+        let info = self.quic_info.get_or_insert_with(QuicInfo::default);
+        if datagram.header.version != 0 {
+            info.version = datagram.header.version;
+        }
+        info.long_header_packets += datagram.long_header_packets;
+        if let Some(hello) = &datagram.client_hello {
+            info.client_hello_decrypted = true;
+            self.add_tls_handshake(&TlsHandshake::ClientHello(hello.clone()), 'q');
+        }
+    }
+
+    /// Informations about the QUIC packets of the flow, if any was observed.
+    pub fn quic_info(&self) -> Option<&QuicInfo> {
+        self.quic_info.as_ref()
+    }
+
+    /// Update the Report struct with the summary of a message of an application protocol, e.g. DHCP.
+    /// A new summary is counted as dropped when the flow has already too many of them.
+    pub fn add_application_info(&mut self, info: String) {
+        if self.application_info.len() >= MAX_APPLICATION_INFO_PER_FLOW && !self.application_info.contains(&info) {
+            self.dropped_application_info += 1;
+            return;
+        }
+        self.application_info.insert(info);
+    }
+
+    /// Summaries of the application protocol messages observed in the flow.
+    pub fn application_info(&self) -> &HashSet<String> {
+        &self.application_info
+    }
+
+    /// Update the classification of the flow with the payload of a packet sent by `sender` (address and port).
+    pub fn classify_payload(&mut self, sender: String, transport: &str, source_port: &str, destination_port: &str, payload: &[u8], options: &CaptureOptions) {
+        self.classifier.inspect(sender, transport, source_port, destination_port, payload, options);
+    }
+
+    /// Update the Report struct with the summary of a transport layer header, e.g. of SCTP.
+    pub fn add_transport_info(&mut self, info: String) {
+        self.transport_info.insert(info);
+    }
+
+    /// Update the ICMP informations of the Report struct with an error triggered by a packet of the flow.
+    pub fn add_icmp_error(&mut self, error: String) {
+        self.icmp_info.insert(error);
+    }
+
+    /// Update the Report struct with the tunnels that carried a packet of the flow.
+    pub fn add_tunnels(&mut self, tunnels: &[TunnelInfo]) {
+        for tunnel in tunnels {
+            if !self.tunnels.contains(tunnel) {
+                self.tunnels.insert(tunnel.clone());
+            }
+        }
+    }
+
+    /// Tunnels that carried the packets of the flow, empty if it was not encapsulated.
+    pub fn tunnels(&self) -> &HashSet<TunnelInfo> {
+        &self.tunnels
+    }
+
+    /// Application protocol detected from the payload of the flow, independently of the ports.
+    pub fn application_protocol(&self) -> Option<&str> {
+        self.classifier.protocol()
+    }
+
+    /// Timestamp (in seconds) of the first packet of the flow.
+    pub fn first_ts(&self) -> u64 {
+        self.first_ts
+    }
+
+    /// Timestamp (in seconds) of the last packet of the flow.
+    pub fn last_ts(&self) -> u64 {
+        self.last_ts
+    }
+
+    /// Bytes of the packets of the flow.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Number of packets of the flow.
+    pub fn total_packets(&self) -> u64 {
+        self.total_packets
+    }
+
+    /// Transport layer protocols of the flow, e.g. "TCP", together with the protocols recognized over them, e.g. "QUIC".
+    pub fn transport_layer_protocols(&self) -> &HashSet<String> {
+        &self.transport_layer_protocols
+    }
+
+    /// Update the Report struct with the flags of a TCP segment sent by `sender` (address and port).
+    pub fn add_tcp_flags(&mut self, sender: String, fin: bool, rst: bool) {
+        if fin {
+            self.fin_senders.insert(sender);
+        }
+        self.reset |= rst;
+    }
+
+    /// Checks if the flow cache has to export the flow at time `now` (in seconds): when the TCP connection was reset or closed
+    /// by both endpoints, when the flow is idle for longer than the idle timeout or active for longer than the active timeout.
+    /// The reason is recorded in the Report struct.
+    pub fn check_export(&mut self, now: u64, timeouts: &FlowTimeouts) -> bool {
+        let reason = if self.reset {
+            Some("TCP RST")
+        } else if self.fin_senders.len() >= 2 {
+            Some("TCP FIN")
+        } else if now.saturating_sub(self.last_ts) >= timeouts.idle_s {
+            Some("Idle timeout")
+        } else if now.saturating_sub(self.first_ts) >= timeouts.active_s {
+            Some("Active timeout")
+        } else {
+            None
+        };
+        self.export_reason = reason.map(|r| r.to_string());
+        self.export_reason.is_some()
+    }
+
+    /// Merges into the Report struct the Report of a later part of the same flow, e.g. of the following interval.
+    pub fn merge(&mut self, other: Report) {
+        self.first_ts = self.first_ts.min(other.first_ts);
+        self.last_ts = self.last_ts.max(other.last_ts);
+        self.total_bytes += other.total_bytes;
+        self.total_packets += other.total_packets;
+        self.transport_layer_protocols.extend(other.transport_layer_protocols);
+        self.network_layer_protocols = other.network_layer_protocols;
+        self.transport_info.extend(other.transport_info);
+        self.link_layer_info.extend(other.link_layer_info);
+        self.icmp_info.extend(other.icmp_info);
+        self.dns_info.extend(other.dns_info);
+        if let Some(tls) = other.tls_info {
+            self.tls_info.get_or_insert_with(TlsInfo::default).merge(tls);
+        }
+        if let Some(http) = other.http_info {
+            self.http_info.get_or_insert_with(HttpInfo::default).merge(http);
+        }
+        if let Some(quic) = other.quic_info {
+            let info = self.quic_info.get_or_insert_with(QuicInfo::default);
+            if quic.version != 0 {
+                info.version = quic.version;
+            }
+            info.long_header_packets += quic.long_header_packets;
+            info.client_hello_decrypted |= quic.client_hello_decrypted;
+        }
+        self.dropped_application_info += other.dropped_application_info;
+        for info in other.application_info {
+            self.add_application_info(info);
+        }
+        self.classifier.merge(other.classifier);
+        self.tunnels.extend(other.tunnels);
+        self.fin_senders.extend(other.fin_senders);
+        self.reset |= other.reset;
+        self.export_reason = other.export_reason;
+    }
+
+    /// Reason why the flow cache exported the flow, None if the flow was reported at the end of an interval.
+    pub fn export_reason(&self) -> Option<&str> {
+        self.export_reason.as_deref()
+    }
+
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
+///Struct that aggregates the traffic of the flows evicted from a bounded report map.
+pub struct OverflowBucket {
+    /// Flows evicted
+    pub evictions: u64,
+    /// Bytes of the evicted flows
+    pub bytes: u64,
+    /// Packets of the evicted flows
+    pub packets: u64,
+    /// Timestamp of the first packet of the evicted flows
+    pub first_ts: Option<u64>,
+    /// Timestamp of the last packet of the evicted flows
+    pub last_ts: Option<u64>,
+}
+
+impl OverflowBucket {
+    /// Adds the traffic of an evicted flow.
+    fn add(&mut self, report: &Report) {
+        self.evictions += 1;
+        self.bytes += report.total_bytes;
+        self.packets += report.total_packets;
+        self.first_ts = Some(self.first_ts.map_or(report.first_ts, |ts| ts.min(report.first_ts)));
+        self.last_ts = Some(self.last_ts.map_or(report.last_ts, |ts| ts.max(report.last_ts)));
+    }
+
+    /// Adds the flows aggregated by another bucket.
+    pub fn merge(&mut self, other: &OverflowBucket) {
+        self.evictions += other.evictions;
+        self.bytes += other.bytes;
+        self.packets += other.packets;
+        self.first_ts = match (self.first_ts, other.first_ts) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        };
+        self.last_ts = self.last_ts.max(other.last_ts);
+    }
+
+    /// Generates the section of a report with the traffic of the evicted flows.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----Other (evicted flows)-----\n");
+        s.push_str(&format!("Flows: {}; Total bytes: {}; Total packets: {}; First timestamp: {}; Last timestamp: {}\n",
+                            self.evictions,
+                            self.bytes,
+                            self.packets,
+                            self.first_ts.map(|ts| ts.to_string()).unwrap_or_else(|| "None".to_string()),
+                            self.last_ts.map(|ts| ts.to_string()).unwrap_or_else(|| "None".to_string())));
+        s.push('\n');
+        s
+    }
+}
+
+/// Function that makes room for a new flow in `map`, which can hold at most `max_flows` flows, removing the flows chosen by
+/// `policy` and adding their traffic to `bucket`. When the map is full a tenth of its flows are removed at once, so that the
+/// cost of choosing them is shared by the following flows.
+pub fn evict_flows(map: &mut HashMap<AddressPortPair, Report>, max_flows: usize, policy: EvictionPolicy, bucket: &mut OverflowBucket) {
+    if map.len() < max_flows {
+        return;
+    }
+    let count = (map.len() + 1 - max_flows).max(max_flows / 10);
+    let mut candidates: Vec<(u64, AddressPortPair)> = map.iter().map(|(pair, report)| {
+        let key = match policy {
+            EvictionPolicy::LeastRecentlyUsed => report.last_ts,
+            EvictionPolicy::SmallestFirst => report.total_bytes
+        };
+        (key, pair.clone())
+    }).collect();
+    candidates.sort_unstable_by_key(|(key, _)| *key);
+    for (_, pair) in candidates.into_iter().take(count) {
+        if let Some(report) = map.remove(&pair) {
+            bucket.add(&report);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 /// Struct representing a pair of tuple (String, String). It is used in order to identify address/port pairs uniquely.
 /// pair is composed of (address, port)
 pub struct AddressPortPair {
@@ -155,14 +464,26 @@ pub fn icmpv6_type_parser(icmp_type: Option<Icmpv6Type>) -> Option<String>{
         // You can still further decode the packet on your own by using the raw data in this enum
         // together with `headers.payload` (contains the packet data after the 8th byte)
         Icmpv6Type::Unknown{ type_u8, code_u8, bytes5to8: _ } => {
-            return Some(format!("Unknown, type: {}, code: {}", type_u8, code_u8).to_string())
+            let name = match type_u8 {
+                130 => "Multicast Listener Query",
+                131 => "Multicast Listener Report",
+                132 => "Multicast Listener Done",
+                143 => "Multicast Listener Report v2",
+                133 => "Router Solicitation",
+                134 => "Router Advertisement",
+                135 => "Neighbor Solicitation",
+                136 => "Neighbor Advertisement",
+                137 => "Redirect",
+                _ => return Some(format!("Unknown, type: {}, code: {}", type_u8, code_u8).to_string())
+            };
+            Some(format!("{} - code: {}", name, code_u8))
         },
         Icmpv6Type::DestinationUnreachable(header) => return Some(format!("Destination Unreachable - code: {}",header.code_u8()).to_string()),
         Icmpv6Type::PacketTooBig { mtu } => return Some(format!("Packet too big mtu = {} - code: 0", mtu).to_string()),
         Icmpv6Type::TimeExceeded(code) => return Some(format!("Time exceeded - code: {}",code.code_u8()).to_string()),
         Icmpv6Type::ParameterProblem(header) => return Some(format!("Parameter problem - code: {}",header.code.code_u8()).to_string()),
-        Icmpv6Type::EchoRequest(_) => return Some("Echo request - code: 0".to_string()),
-        Icmpv6Type::EchoReply(_) => return Some("Echo reply - code: 0".to_string()),
+        Icmpv6Type::EchoRequest(header) => return Some(format!("Echo request - code: 0; id: {}", header.id)),
+        Icmpv6Type::EchoReply(header) => return Some(format!("Echo reply - code: 0; id: {}", header.id)),
     }
 }
 
@@ -178,13 +499,13 @@ pub fn icmpv4_type_parser(icmp_type: Option<Icmpv4Type>) -> Option<String> {
             code_u8,
             bytes5to8: _,
         } => return Some(format!("Unknown, type: {}, code: {}", type_u8, code_u8).to_string()),
-        Icmpv4Type::EchoReply(_) => {return Some("Echo Reply - code: 0".to_string())},
+        Icmpv4Type::EchoReply(header) => {return Some(format!("Echo Reply - code: 0; id: {}", header.id))},
         Icmpv4Type::DestinationUnreachable(header) => {return Some(format!("Destination Unreachable - code: {}", header.code_u8()).to_string())},
         Icmpv4Type::Redirect(header) => {
             let ip_addr = header.gateway_internet_address;
             let s = format!("{}.{}.{}.{}", ip_addr[0], ip_addr[1], ip_addr[2], ip_addr[3]);
             return Some(format!("Redirect {} - code: {}", header.code.code_u8(), s).to_string())},
-        Icmpv4Type::EchoRequest(_) => {return Some("Echo Request - code: 0".to_string())},
+        Icmpv4Type::EchoRequest(header) => {return Some(format!("Echo Request - code: 0; id: {}", header.id))},
         Icmpv4Type::TimeExceeded(code)=> {return Some(format!("Time Exceeded - code: {}",code.code_u8()).to_string())},
         Icmpv4Type::ParameterProblem(header) => {
             let code;
@@ -218,6 +539,9 @@ pub fn parse_transport(transport_value: Option<TransportSlice>) -> Option<Transp
             Tcp(header) => {
                 return Some(TransportInfo{protocol: "TCP".to_string(), source_port: Some(header.source_port().to_string()), destination_port: Some(header.destination_port().to_string()), icmp_type: None});
             },
+            TransportSlice::Unknown(ESP_PROTOCOL) => {return Some(TransportInfo{protocol: "ESP".to_string(), source_port: None, destination_port: None, icmp_type: None});},
+            TransportSlice::Unknown(AH_PROTOCOL) => {return Some(TransportInfo{protocol: "AH".to_string(), source_port: None, destination_port: None, icmp_type: None});},
+            TransportSlice::Unknown(IGMP_PROTOCOL) => {return Some(TransportInfo{protocol: "IGMP".to_string(), source_port: None, destination_port: None, icmp_type: None});},
             TransportSlice::Unknown(ip_protocol_number) => {return Some(TransportInfo{protocol: format!("Unknown | IP protocol number: {}", ip_protocol_number).to_string(), source_port: None, destination_port: None, icmp_type: None});}
 
         }
@@ -344,6 +668,9 @@ pub struct DnsInfo {
     ///  Dns packet header Id
     pub id: u16,
 
+    ///  True if the message is a response, false if it is a query
+    pub is_response: bool,
+
     ///  Opcode for this query
     pub opcode: simple_dns::OPCODE,
 
@@ -363,8 +690,35 @@ pub struct DnsInfo {
     pub responses : Vec<String>,
 
     ///  Answers classes (according to his own rfc)
-    pub response_class : Vec<CLASS>
+    pub response_class : Vec<CLASS>,
+
+    ///  Answers records, with their data when it is an address or an alias
+    pub answers : Vec<DnsAnswer>
+
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Enum containing the data of a DNS answer record.
+pub enum DnsAnswerData {
+    /// IPv4 address of an A record
+    A(Ipv4Addr),
+    /// IPv6 address of an AAAA record
+    AAAA(Ipv6Addr),
+    /// Canonical name of a CNAME record
+    CNAME(String),
+    /// Any other record type
+    Other
+}
 
+#[derive(Debug, Clone)]
+///Struct containing a DNS answer record.
+pub struct DnsAnswer {
+    /// Domain name the record refers to
+    pub name: String,
+    /// Time to live of the record, in seconds
+    pub ttl: u32,
+    /// Data of the record
+    pub data: DnsAnswerData
 }
 
 /// The function parse dns packet gaining main informations.
@@ -374,6 +728,7 @@ pub fn parse_dns(dns_packet: Option< simple_dns::Packet>) -> Option<DnsInfo> {
                 let dns = dns_packet.unwrap();
                 return Some(DnsInfo{
                     id: dns.header.id,
+                    is_response: !dns.header.query,
                     opcode: dns.header.opcode,
                     response_code: dns.header.response_code,
                     queries : dns.questions.iter().map(|q| q.qname.to_string()).collect(),
@@ -381,11 +736,37 @@ pub fn parse_dns(dns_packet: Option< simple_dns::Packet>) -> Option<DnsInfo> {
                     query_class : dns.questions.iter().map(|q| q.qclass).collect(),
                     responses:dns.answers.iter().map(|q| q.name.to_string()).collect(),
                     response_class :dns.answers.iter().map(|q| q.class).collect(),
+                    answers: dns.answers.iter().map(|q| DnsAnswer{
+                        name: q.name.to_string(),
+                        ttl: q.ttl,
+                        data: match &q.rdata {
+                            RData::A(a) => DnsAnswerData::A(Ipv4Addr::from(a.address)),
+                            RData::AAAA(aaaa) => DnsAnswerData::AAAA(Ipv6Addr::from(aaaa.address)),
+                            RData::CNAME(cname) => DnsAnswerData::CNAME(cname.0.to_string()),
+                            _ => DnsAnswerData::Other
+                        }
+                    }).collect(),
                 });
     }
     None
 }
 
+/// Standard port used by DNS, both over UDP and TCP.
+pub const DNS_PORT: u16 = 53;
+/// Port used by multicast DNS (mDNS).
+pub const MDNS_PORT: u16 = 5353;
+/// Port used by Link-Local Multicast Name Resolution (LLMNR).
+pub const LLMNR_PORT: u16 = 5355;
+
+/// Function that checks if the given port carries DNS messages. Besides the standard DNS, mDNS and LLMNR ports,
+/// also the ports contained in `extra_ports` are considered.
+pub fn is_dns_port(port: &str, extra_ports: &[u16]) -> bool {
+    match port.parse::<u16>() {
+        Ok(p) => p == DNS_PORT || p == MDNS_PORT || p == LLMNR_PORT || extra_ports.contains(&p),
+        Err(_) => false
+    }
+}
+
 /// The function takes as parameter an `OptionDns<Info>` struct and generate a String containing dns formatted informations.
 pub fn dns_info_to_string ( application_level: Option<DnsInfo>) -> String {
     if application_level.is_some() {
@@ -433,8 +814,11 @@ pub fn dns_info_to_string ( application_level: Option<DnsInfo>) -> String {
     }
 }
 
-/// Function that write on a specified txt file an `HashMap<AddressPortPair,Report>` struct.
-pub fn write_file(filename: &str, report : &HashMap<AddressPortPair,Report>) -> Result<(), PacketSnifferError>{
+/// Function that write on a specified txt file an `HashMap<AddressPortPair,Report>` struct, followed by the sections built from `trackers`.
+/// When `totals` is given, the sections of the trackers that are reset at every report are written under a heading that
+/// marks them as statistics of the last interval. The flows still `active` in the flow cache are written together with the
+/// totals, merged with the total of the same flow, without being added to them.
+pub fn write_file(filename: &str, report : Option<&HashMap<AddressPortPair,Report>>, totals: Option<&HashMap<AddressPortPair,Report>>, active: Option<&HashMap<AddressPortPair,Report>>, trackers: &Trackers, options: &CaptureOptions) -> Result<(), PacketSnifferError>{
 
     let  file = match OpenOptions::new()
         .write(true)
@@ -447,22 +831,97 @@ pub fn write_file(filename: &str, report : &HashMap<AddressPortPair,Report>) ->
 
     let mut file = BufWriter::new(file);
 
-    let vec = Vec::from_iter(report.iter());
     let local: DateTime<Local> = Local::now();
     write!(file, "================================================\n").expect("unable to write to file");
     write!(file, "NEW REPORT: {}\n",local).expect("unable to write to file");
+    if options.max_flows.is_some() {
+        // The evictions are counted for the flows of the interval and for the totals separately
+        match (report, totals) {
+            (Some(_), Some(_)) => writeln!(file, "Evicted flows: {}; Since the start of the capture: {}", trackers.overflow.evictions, trackers.overflow_totals.evictions),
+            (None, Some(_)) => writeln!(file, "Evicted flows: {}", trackers.overflow_totals.evictions),
+            _ => writeln!(file, "Evicted flows: {}", trackers.overflow.evictions)
+        }.expect("unable to write to file");
+    }
     write!(file, "================================================\n\n").expect("unable to write to file");
-    for x in vec {
-        let string_to_print = parse_report(x);
-        write!(file, "{}", string_to_print).expect("unable to write to file");
+    // Only the active flows that are also in the totals are copied, to be merged with their total
+    let merged: HashMap<AddressPortPair, Report> = match (totals, active) {
+        (Some(totals), Some(active)) => active.iter().filter_map(|(pair, flow)| {
+            let mut total = totals.get(pair)?.clone();
+            total.merge(flow.clone());
+            Some((pair.clone(), total))
+        }).collect(),
+        _ => HashMap::new()
+    };
+    let totals: Option<Vec<(&AddressPortPair, &Report)>> = totals.map(|totals| totals.iter()
+        .filter(|(pair, _)| !merged.contains_key(*pair))
+        .chain(merged.iter())
+        .chain(active.into_iter().flatten().filter(|(pair, _)| !totals.contains_key(*pair)))
+        .collect());
+    if let Some(report) = report {
+        write!(file, "{}", summary_to_string(report, options.max_flows.map(|_| &trackers.overflow), options.top_n)).expect("unable to write to file");
+    } else if let Some(totals) = &totals {
+        write!(file, "{}", summary_to_string(totals.iter().cloned(), options.max_flows.map(|_| &trackers.overflow_totals), options.top_n)).expect("unable to write to file");
+    }
+    if let Some(report) = report {
+        let vec = sort_flows(report, options.flow_order);
+        for x in vec {
+            let string_to_print = parse_report(x, trackers);
+            write!(file, "{}", string_to_print).expect("unable to write to file");
+        }
+        if options.max_flows.is_some() {
+            write!(file, "{}", trackers.overflow.to_report_string()).expect("unable to write to file");
+        }
+        write!(file, "{}", http_summary_to_string(report, options.top_n)).expect("unable to write to file");
     }
+    if let Some(totals) = totals {
+        write!(file, "-----Totals since the start of the capture-----\n\n").expect("unable to write to file");
+        let vec = sort_flows(totals.iter().cloned(), options.flow_order);
+        for x in vec {
+            let string_to_print = parse_report(x, trackers);
+            write!(file, "{}", string_to_print).expect("unable to write to file");
+        }
+        if options.max_flows.is_some() {
+            write!(file, "{}", trackers.overflow_totals.to_report_string()).expect("unable to write to file");
+        }
+        write!(file, "{}", http_summary_to_string(totals.iter().cloned(), options.top_n)).expect("unable to write to file");
+        // The statistics of the trackers are reset at every report, also when the flows are cumulative
+        write!(file, "-----Statistics of the last interval-----\n\n").expect("unable to write to file");
+    }
+    write!(file, "{}", trackers.dns.to_report_string(options.top_n)).expect("unable to write to file");
+    write!(file, "{}", trackers.rollups.to_report_string(&options.aggregation)).expect("unable to write to file");
+    write!(file, "{}", trackers.ping.to_report_string()).expect("unable to write to file");
+    write!(file, "{}", trackers.multicast.to_report_string()).expect("unable to write to file");
+    write!(file, "{}", trackers.dhcp.to_report_string()).expect("unable to write to file");
+    write!(file, "{}", trackers.neighbors.to_report_string()).expect("unable to write to file");
+    write!(file, "{}", trackers.vpn.to_report_string()).expect("unable to write to file");
+    write!(file, "{}", trackers.discovery.to_report_string()).expect("unable to write to file");
+    write!(file, "{}", trackers.wifi.to_report_string()).expect("unable to write to file");
 
     Ok(())
 }
 
+/// Function that formats a list of hostnames, returning "Unknown" if the list is empty.
+fn hostnames_to_string(hostnames: &[String]) -> String {
+    if hostnames.is_empty() {
+        return "Unknown".to_string();
+    }
+    hostnames.join(", ")
+}
+
+/// Function that formats the device holding a DHCP lease, returning "Unknown" if there is none.
+fn device_to_string(lease: Option<DhcpLease>) -> String {
+    match lease {
+        Some(lease) => match lease.hostname {
+            Some(hostname) => format!("{} ({})", lease.mac, hostname),
+            None => lease.mac
+        },
+        None => "Unknown".to_string()
+    }
+}
+
 /// Functions that converts and formats a tuple (&AddressPortPair, &Report) into a String, following a specific user-friendly schema.
-pub fn parse_report(report : (&AddressPortPair,&Report)) -> String {
-    let mut i;
+/// The addresses are annotated with the information known by `trackers`, such as the hostnames learned from DNS.
+pub fn parse_report(report : (&AddressPortPair,&Report), trackers: &Trackers) -> String {
     let mut string_report = "".to_owned();
 
     string_report.push_str("-----Packets info-----");
@@ -480,6 +939,22 @@ pub fn parse_report(report : (&AddressPortPair,&Report)) -> String {
     string_report.push_str(report.0.second_pair.1.to_string().as_str());
     string_report.push( '\n');
 
+    string_report.push_str("First pair hostnames: ");
+    string_report.push_str(hostnames_to_string(&trackers.passive_dns.lookup(&report.0.first_pair.0, report.1.last_ts)).as_str());
+    string_report.push( '\n');
+
+    string_report.push_str("Second pair hostnames: ");
+    string_report.push_str(hostnames_to_string(&trackers.passive_dns.lookup(&report.0.second_pair.0, report.1.last_ts)).as_str());
+    string_report.push( '\n');
+
+    string_report.push_str("First pair device: ");
+    string_report.push_str(device_to_string(trackers.dhcp.device_for_ip(&report.0.first_pair.0)).as_str());
+    string_report.push( '\n');
+
+    string_report.push_str("Second pair device: ");
+    string_report.push_str(device_to_string(trackers.dhcp.device_for_ip(&report.0.second_pair.0)).as_str());
+    string_report.push( '\n');
+
     string_report.push_str("First timestamp: ");
     string_report.push_str(report.1.first_ts.to_string().as_str());
     string_report.push( '\n');
@@ -488,21 +963,37 @@ pub fn parse_report(report : (&AddressPortPair,&Report)) -> String {
     string_report.push_str(report.1.last_ts.to_string().as_str());
     string_report.push( '\n');
 
+    if let Some(reason) = report.1.export_reason() {
+        string_report.push_str("Export reason: ");
+        string_report.push_str(reason);
+        string_report.push('\n');
+    }
+
     string_report.push_str("Total bytes: ");
     string_report.push_str(report.1.total_bytes.to_string().as_str());
     string_report.push( '\n');
 
+    string_report.push_str("Total packets: ");
+    string_report.push_str(report.1.total_packets.to_string().as_str());
+    string_report.push('\n');
+
     string_report.push_str("Transport layer protocol: ");
-    i = 0;
-    let tl_info_len = report.1.transport_layer_protocols.len();
-    for info in &report.1.transport_layer_protocols {
+    let mut transport_protocols: Vec<&str> = report.1.transport_layer_protocols.iter().map(|p| p.as_str()).collect();
+    transport_protocols.sort();
+    string_report.push_str(transport_protocols.join(", ").as_str());
+    string_report.push_str("\n");
+
+    let mut transport_info: Vec<&String> = report.1.transport_info.iter().collect();
+    transport_info.sort();
+    for info in transport_info {
+        string_report.push_str("Transport info: ");
         string_report.push_str(info.as_str());
-        if i != tl_info_len-1 {
-            string_report.push_str(", ")
-        }
-        i+=1;
+        string_report.push('\n');
     }
-    string_report.push_str("\n");
+
+    string_report.push_str("Application protocol: ");
+    string_report.push_str(report.1.application_protocol().unwrap_or("Unknown"));
+    string_report.push('\n');
 
     string_report.push_str("Network layer protocol: ");
     string_report.push_str((report.1.network_layer_protocols.to_string()).as_str());
@@ -510,38 +1001,60 @@ pub fn parse_report(report : (&AddressPortPair,&Report)) -> String {
 
     string_report.push_str("Link layer info:");
     string_report.push_str("\n");
-    i = 0;
-    let link_info_len = report.1.link_layer_info.len();
-    for info in &report.1.link_layer_info {
-        string_report.push_str(linkinfo_tostring(info).as_str());
-        if i != link_info_len-1 {
-            string_report.push_str(", ")
-        }
-        i+=1;
-    }
+    let mut link_info: Vec<String> = report.1.link_layer_info.iter().map(linkinfo_tostring).collect();
+    link_info.sort();
+    string_report.push_str(link_info.join(", ").as_str());
     string_report.push_str(" \n");
 
 
+    string_report.push_str("Tunnel info:");
+    string_report.push('\n');
+    let mut tunnels: Vec<String> = report.1.tunnels.iter().map(tunnel_info_to_string).collect();
+    tunnels.sort();
+    for tunnel in tunnels {
+        string_report.push_str(tunnel.as_str());
+        string_report.push('\n');
+    }
+
     string_report.push_str("Icmp info:");
     string_report.push_str("\n");
-    i = 0;
-    let icmp_info_len = report.1.icmp_info.len();
-    for info in &report.1.icmp_info {
-        string_report.push_str(info.as_str());
-        if i != icmp_info_len-1 {
-            string_report.push_str(", ")
-        }
-        i+=1;
-    }
+    let mut icmp_info: Vec<&str> = report.1.icmp_info.iter().map(|info| info.as_str()).collect();
+    icmp_info.sort();
+    string_report.push_str(icmp_info.join(", ").as_str());
     //string_report.push_str((report.1.icmp_info.to_string()).as_str());
     string_report.push_str(" \n");
     string_report.push_str("Dns info:");
     string_report.push_str("\n");
-    i = 0;
-    let dns_info_len = report.1.dns_info.len();
-    for info in &report.1.dns_info {
+    let mut dns_info: Vec<&String> = report.1.dns_info.iter().collect();
+    dns_info.sort();
+    for info in dns_info {
+        string_report.push_str(info.as_str());
+    }
+    string_report.push_str("Tls info:");
+    string_report.push('\n');
+    if let Some(tls) = &report.1.tls_info {
+        string_report.push_str(tls_info_to_string(tls).as_str());
+    }
+    string_report.push_str("Quic info:");
+    string_report.push('\n');
+    if let Some(quic) = &report.1.quic_info {
+        string_report.push_str(quic_info_to_string(quic).as_str());
+    }
+    string_report.push_str("Application info:");
+    string_report.push('\n');
+    let mut application_info: Vec<&String> = report.1.application_info.iter().collect();
+    application_info.sort();
+    for info in application_info {
         string_report.push_str(info.as_str());
-        i+=1;
+        string_report.push('\n');
+    }
+    if report.1.dropped_application_info > 0 {
+        string_report.push_str(&format!("Messages not shown: {}\n", report.1.dropped_application_info));
+    }
+    string_report.push_str("Http info:");
+    string_report.push('\n');
+    if let Some(http) = &report.1.http_info {
+        string_report.push_str(http_info_to_string(http).as_str());
     }
 
 
@@ -553,3 +1066,129 @@ pub fn parse_report(report : (&AddressPortPair,&Report)) -> String {
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(ts: u64, bytes: u32) -> Report {
+        let link = LinkInfo{source_mac: [0; 6], destination_mac: [0; 6], ether_type: "Ipv4".to_string()};
+        Report::new(ts, bytes, "TCP".to_string(), "IPv4".to_string(), link, String::new(), String::new())
+    }
+
+    fn pair(client_port: u16) -> AddressPortPair {
+        AddressPortPair::new("10.0.0.1".to_string(), client_port.to_string(), "10.0.0.2".to_string(), "80".to_string())
+    }
+
+    /// Returns a map of `n` flows, the flow of client port 40000 + i having its last packet at timestamp 100 + i and
+    /// n - i bytes, so that the least recently used flows are the largest.
+    fn flows(n: u16) -> HashMap<AddressPortPair, Report> {
+        (0..n).map(|i| {
+            let mut report = flow(0, (n - i) as u32);
+            report.last_ts = 100 + i as u64;
+            (pair(40000 + i), report)
+        }).collect()
+    }
+
+    #[test]
+    fn eviction_policy_chooses_the_victims() {
+        let mut map = flows(10);
+        let mut bucket = OverflowBucket::default();
+        evict_flows(&mut map, 10, EvictionPolicy::LeastRecentlyUsed, &mut bucket);
+        assert_eq!(map.len(), 9);
+        assert!(!map.contains_key(&pair(40000)));
+        let mut map = flows(10);
+        evict_flows(&mut map, 10, EvictionPolicy::SmallestFirst, &mut bucket);
+        assert_eq!(map.len(), 9);
+        assert!(!map.contains_key(&pair(40009)));
+        // A map with room for the new flow is left as it is
+        let mut map = flows(10);
+        evict_flows(&mut map, 11, EvictionPolicy::LeastRecentlyUsed, &mut bucket);
+        assert_eq!(map.len(), 10);
+        assert_eq!(bucket.evictions, 2);
+    }
+
+    #[test]
+    fn eviction_removes_a_tenth_of_the_flows() {
+        let mut map = flows(50);
+        let mut bucket = OverflowBucket::default();
+        evict_flows(&mut map, 50, EvictionPolicy::LeastRecentlyUsed, &mut bucket);
+        assert_eq!(map.len(), 45);
+        assert!((40000..40005).all(|port| !map.contains_key(&pair(port))));
+        // A map above its bound, e.g. after the bound was lowered, is brought back under it at once
+        let mut map = flows(50);
+        evict_flows(&mut map, 20, EvictionPolicy::LeastRecentlyUsed, &mut bucket);
+        assert_eq!(map.len(), 19);
+    }
+
+    #[test]
+    fn overflow_bucket_aggregates_the_evicted_flows() {
+        let mut map = flows(20);
+        let mut bucket = OverflowBucket::default();
+        evict_flows(&mut map, 20, EvictionPolicy::LeastRecentlyUsed, &mut bucket);
+        // The flows of client ports 40000 and 40001, of 20 and 19 bytes
+        assert_eq!((bucket.evictions, bucket.bytes, bucket.packets), (2, 39, 2));
+        assert_eq!((bucket.first_ts, bucket.last_ts), (Some(0), Some(101)));
+        let mut totals = OverflowBucket::default();
+        totals.merge(&bucket);
+        totals.merge(&OverflowBucket{evictions: 1, bytes: 10, packets: 3, first_ts: Some(50), last_ts: Some(300)});
+        assert_eq!(totals.to_report_string(), "-----Other (evicted flows)-----\nFlows: 3; Total bytes: 49; Total packets: 5; First timestamp: 0; Last timestamp: 300\n\n");
+    }
+
+    const TIMEOUTS: FlowTimeouts = FlowTimeouts{idle_s: 30, active_s: 120};
+
+    #[test]
+    fn tcp_reset_exports_the_flow() {
+        let mut report = flow(100, 60);
+        report.add_tcp_flags("10.0.0.1:40000".to_string(), false, true);
+        assert!(report.check_export(100, &TIMEOUTS));
+        assert_eq!(report.export_reason(), Some("TCP RST"));
+    }
+
+    #[test]
+    fn tcp_fin_exports_the_flow_once_both_sides_closed() {
+        let mut report = flow(100, 60);
+        report.add_tcp_flags("10.0.0.1:40000".to_string(), true, false);
+        // A FIN retransmitted by the same side does not close the connection
+        report.add_tcp_flags("10.0.0.1:40000".to_string(), true, false);
+        assert!(!report.check_export(100, &TIMEOUTS));
+        assert_eq!(report.export_reason(), None);
+        report.add_tcp_flags("10.0.0.2:80".to_string(), true, false);
+        assert!(report.check_export(100, &TIMEOUTS));
+        assert_eq!(report.export_reason(), Some("TCP FIN"));
+    }
+
+    #[test]
+    fn idle_and_active_timeouts_export_the_flow() {
+        let mut report = flow(100, 60);
+        assert!(!report.check_export(100 + TIMEOUTS.idle_s - 1, &TIMEOUTS));
+        assert!(report.check_export(100 + TIMEOUTS.idle_s, &TIMEOUTS));
+        assert_eq!(report.export_reason(), Some("Idle timeout"));
+        // A flow with a packet every 10 seconds is never idle
+        let mut report = flow(100, 60);
+        report.last_ts = 100 + TIMEOUTS.active_s - 10;
+        assert!(!report.check_export(100 + TIMEOUTS.active_s - 1, &TIMEOUTS));
+        report.last_ts = 100 + TIMEOUTS.active_s;
+        assert!(report.check_export(100 + TIMEOUTS.active_s, &TIMEOUTS));
+        assert_eq!(report.export_reason(), Some("Active timeout"));
+        // A packet timestamp later than the clock of the flow cache does not export the flow
+        assert!(!flow(200, 60).check_export(100, &TIMEOUTS));
+    }
+
+    #[test]
+    fn application_info_is_capped() {
+        let mut report = flow(0, 100);
+        for i in 0..MAX_APPLICATION_INFO_PER_FLOW + 2 {
+            report.add_application_info(format!("Syslog {}", i));
+        }
+        // A summary already stored is not counted again
+        report.add_application_info("Syslog 0".to_string());
+        assert_eq!(report.application_info().len(), MAX_APPLICATION_INFO_PER_FLOW);
+        assert_eq!(report.dropped_application_info, 2);
+        let mut later = flow(1, 100);
+        later.add_application_info("Syslog later".to_string());
+        report.merge(later);
+        assert_eq!(report.application_info().len(), MAX_APPLICATION_INFO_PER_FLOW);
+        assert_eq!(report.dropped_application_info, 3);
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use crate::errors::PacketSnifferError;
+use crate::options::AggregationOptions;
+
+/// Maximum number of hosts, and of subnets, aggregated in an interval; the traffic of the others is aggregated together.
+const MAX_ROLLUPS: usize = 10000;
+/// Maximum number of distinct peers, and of distinct ports, kept for a host or a subnet.
+const MAX_DISTINCT: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///Struct representing a subnet in CIDR notation, with the name it is reported with.
+pub struct NamedSubnet {
+    /// Name of the subnet, e.g. "office"
+    pub name: String,
+    /// Network address of the subnet
+    pub network: IpAddr,
+    /// Length of the prefix of the subnet
+    pub prefix_len: u8,
+    /// Name and CIDR notation the subnet is reported with, e.g. "office (10.1.0.0/16)"
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Default)]
+///Struct containing the traffic of a host or of a subnet.
+pub struct Rollup {
+    /// Address of the host, or name and CIDR of the subnet
+    pub name: String,
+    /// Bytes received
+    pub bytes_in: u64,
+    /// Bytes sent
+    pub bytes_out: u64,
+    /// Packets received
+    pub packets_in: u64,
+    /// Packets sent
+    pub packets_out: u64,
+    /// Distinct addresses the host or the subnet exchanged packets with, at most `MAX_DISTINCT`
+    pub peers: HashSet<String>,
+    /// Distinct ports used by the host or the subnet on its side of the flows, at most `MAX_DISTINCT`
+    pub ports: HashSet<String>,
+    /// True if the host or the subnet had more distinct peers than the ones kept
+    pub more_peers: bool,
+    /// True if the host or the subnet used more distinct ports than the ones kept
+    pub more_ports: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///Enum identifying a subnet of the rollups.
+enum SubnetKey {
+    /// Named subnet, by index in the aggregation options
+    Named(usize),
+    /// Subnet of the configured prefix length, by network address and prefix length
+    Prefix(IpAddr, u8),
+}
+
+#[derive(Debug, Default)]
+///Struct that aggregates the traffic of the current interval per host and per subnet. At most `MAX_ROLLUPS` hosts and
+///subnets are kept, the traffic of the later ones is aggregated in an "Other hosts" and an "Other subnets" rollup.
+pub struct RollupTable {
+    /// Traffic by host address
+    hosts: HashMap<String, Rollup>,
+    /// Traffic by subnet
+    subnets: HashMap<SubnetKey, Rollup>,
+    /// Traffic of the hosts over the limit
+    other_hosts: Rollup,
+    /// Traffic of the subnets over the limit
+    other_subnets: Rollup,
+}
+
+/// Function that returns the network address of the `prefix_len` bits long prefix of `address`.
+fn network(address: IpAddr, prefix_len: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len.min(32) as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        },
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len.min(128) as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+impl NamedSubnet {
+    /// Create a new NamedSubnet struct, clearing the host bits of `network_address`.
+    pub fn new(name: String, network_address: IpAddr, prefix_len: u8) -> NamedSubnet {
+        let network = network(network_address, prefix_len);
+        let label = format!("{} ({}/{})", name, network, prefix_len);
+        NamedSubnet{name, network, prefix_len, label}
+    }
+
+    /// Checks if `address` belongs to the subnet.
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        address.is_ipv4() == self.network.is_ipv4() && network(*address, self.prefix_len) == self.network
+    }
+}
+
+impl FromStr for NamedSubnet {
+    type Err = PacketSnifferError;
+
+    /// Parses a subnet with format NAME=ADDRESS/PREFIX, e.g. "office=10.1.0.0/16". Without a name, the subnet is named
+    /// after its CIDR notation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PacketSnifferError::InvalidSubnet(s.to_string());
+        let (name, cidr) = match s.split_once('=') {
+            Some((name, cidr)) if !name.is_empty() => (name.to_string(), cidr),
+            Some(_) => return Err(invalid()),
+            None => (s.to_string(), s)
+        };
+        let (address, prefix_len) = cidr.split_once('/').ok_or_else(invalid)?;
+        let address = address.parse::<IpAddr>().map_err(|_| invalid())?;
+        let prefix_len = prefix_len.parse::<u8>().map_err(|_| invalid())?;
+        if prefix_len > if address.is_ipv4() { 32 } else { 128 } {
+            return Err(invalid());
+        }
+        Ok(NamedSubnet::new(name, address, prefix_len))
+    }
+}
+
+impl Rollup {
+    /// Create a new empty Rollup struct named `name`.
+    fn named(name: String) -> Rollup {
+        Rollup{name, ..Default::default()}
+    }
+
+    /// Counts a packet of `bytes` bytes exchanged with `peer`, using `port` on the side of the rollup.
+    fn add(&mut self, outgoing: bool, bytes: u32, peer: &str, port: &str) {
+        if outgoing {
+            self.bytes_out += bytes as u64;
+            self.packets_out += 1;
+        } else {
+            self.bytes_in += bytes as u64;
+            self.packets_in += 1;
+        }
+        insert_capped(&mut self.peers, &mut self.more_peers, peer);
+        if port != "No port" {
+            insert_capped(&mut self.ports, &mut self.more_ports, port);
+        }
+    }
+}
+
+/// Function that inserts `value` in `set`, unless the set is full, in which case `more` is set.
+fn insert_capped(set: &mut HashSet<String>, more: &mut bool, value: &str) {
+    if set.contains(value) {
+        return;
+    }
+    if set.len() < MAX_DISTINCT {
+        set.insert(value.to_string());
+    } else {
+        *more = true;
+    }
+}
+
+/// Function that formats the number of distinct values of a set, followed by "+" if some were not kept.
+fn distinct_to_string(set: &HashSet<String>, more: bool) -> String {
+    format!("{}{}", set.len(), if more { "+" } else { "" })
+}
+
+/// Function that returns the subnets `address` belongs to: the named subnets containing it and the subnet of the
+/// configured prefix length for its family.
+fn subnets_of(address: &IpAddr, options: &AggregationOptions) -> Vec<SubnetKey> {
+    let mut keys: Vec<SubnetKey> = options.subnets.iter()
+        .enumerate()
+        .filter(|(_, s)| s.contains(address))
+        .map(|(i, _)| SubnetKey::Named(i))
+        .collect();
+    let prefix_len = if address.is_ipv4() { options.ipv4_prefix } else { options.ipv6_prefix };
+    if let Some(prefix_len) = prefix_len {
+        keys.push(SubnetKey::Prefix(network(*address, prefix_len), prefix_len));
+    }
+    keys
+}
+
+/// Function that returns the name a subnet is reported with.
+fn subnet_name(key: &SubnetKey, options: &AggregationOptions) -> String {
+    match key {
+        SubnetKey::Named(i) => options.subnets[*i].label.clone(),
+        SubnetKey::Prefix(network, prefix_len) => format!("{}/{}", network, prefix_len)
+    }
+}
+
+/// Function that returns the rollup of `key` in `rollups`, creating it unless `rollups` is full, in which case `other`
+/// is returned.
+fn rollup_entry<'a, K: Eq + std::hash::Hash>(rollups: &'a mut HashMap<K, Rollup>, other: &'a mut Rollup, key: K, name: impl FnOnce() -> String) -> &'a mut Rollup {
+    if !rollups.contains_key(&key) && rollups.len() >= MAX_ROLLUPS {
+        return other;
+    }
+    rollups.entry(key).or_insert_with(|| Rollup::named(name()))
+}
+
+/// Function that formats the rollups of a section, the ones with the most bytes first.
+fn rollups_to_string(mut rollups: Vec<Rollup>) -> String {
+    let mut s = "".to_owned();
+    rollups.sort_by(|a, b| (b.bytes_in + b.bytes_out).cmp(&(a.bytes_in + a.bytes_out)).then(a.name.cmp(&b.name)));
+    for rollup in rollups {
+        s.push_str(&rollup_to_string(&rollup));
+    }
+    s
+}
+
+/// Function that formats the traffic of a rollup.
+fn rollup_to_string(rollup: &Rollup) -> String {
+    format!("{}: bytes in: {}; bytes out: {}; packets in: {}; packets out: {}; peers: {}; ports: {}\n",
+            rollup.name,
+            rollup.bytes_in,
+            rollup.bytes_out,
+            rollup.packets_in,
+            rollup.packets_out,
+            distinct_to_string(&rollup.peers, rollup.more_peers),
+            distinct_to_string(&rollup.ports, rollup.more_ports))
+}
+
+impl RollupTable {
+    /// Create a new empty RollupTable struct.
+    pub fn new() -> RollupTable {
+        RollupTable{hosts: HashMap::new(), subnets: HashMap::new(), other_hosts: Rollup::named("Other hosts".to_string()), other_subnets: Rollup::named("Other subnets".to_string())}
+    }
+
+    /// Registers a packet of `bytes` bytes sent from `source` (address and port) to `destination`, in the views enabled by `options`.
+    pub fn register(&mut self, source: (&str, &str), destination: (&str, &str), bytes: u32, options: &AggregationOptions) {
+        if options.hosts {
+            rollup_entry(&mut self.hosts, &mut self.other_hosts, source.0.to_string(), || source.0.to_string())
+                .add(true, bytes, destination.0, source.1);
+            rollup_entry(&mut self.hosts, &mut self.other_hosts, destination.0.to_string(), || destination.0.to_string())
+                .add(false, bytes, source.0, destination.1);
+        }
+        if !options.subnet_view() {
+            return;
+        }
+        for (endpoint, peer, outgoing) in [(source, destination, true), (destination, source, false)] {
+            let address = match endpoint.0.parse::<IpAddr>() {
+                Ok(address) => address,
+                Err(_) => continue
+            };
+            for key in subnets_of(&address, options) {
+                let name = || subnet_name(&key, options);
+                rollup_entry(&mut self.subnets, &mut self.other_subnets, key.clone(), name)
+                    .add(outgoing, bytes, peer.0, endpoint.1);
+            }
+        }
+    }
+
+    /// Clears the rollups at the end of an interval.
+    pub fn reset_stats(&mut self) {
+        *self = RollupTable::new();
+    }
+
+    /// Returns the traffic of the current interval per host, sorted by address, without the hosts over the limit.
+    pub fn hosts(&self) -> Vec<Rollup> {
+        let mut hosts: Vec<Rollup> = self.hosts.values().cloned().collect();
+        hosts.sort_by(|a, b| a.name.cmp(&b.name));
+        hosts
+    }
+
+    /// Returns the traffic of the current interval per subnet, sorted by name, without the subnets over the limit.
+    pub fn subnets(&self) -> Vec<Rollup> {
+        let mut subnets: Vec<Rollup> = self.subnets.values().cloned().collect();
+        subnets.sort_by(|a, b| a.name.cmp(&b.name));
+        subnets
+    }
+
+    /// Generates the sections of a report with the traffic per host and per subnet, for the views enabled by `options`.
+    pub fn to_report_string(&self, options: &AggregationOptions) -> String {
+        let mut s = "".to_owned();
+        if options.hosts {
+            s.push_str("-----Traffic per host-----\n");
+            s.push_str(&rollups_to_string(self.hosts.values().cloned().collect()));
+            if self.other_hosts.packets_in + self.other_hosts.packets_out > 0 {
+                s.push_str(&rollup_to_string(&self.other_hosts));
+            }
+            s.push('\n');
+        }
+        if options.subnet_view() {
+            s.push_str("-----Traffic per subnet-----\n");
+            s.push_str(&rollups_to_string(self.subnets.values().cloned().collect()));
+            if self.other_subnets.packets_in + self.other_subnets.packets_out > 0 {
+                s.push_str(&rollup_to_string(&self.other_subnets));
+            }
+            s.push('\n');
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> AggregationOptions {
+        AggregationOptions{hosts: true, ipv4_prefix: Some(24), ipv6_prefix: None, subnets: vec!["office=10.1.0.7/16".parse().unwrap()]}
+    }
+
+    #[test]
+    fn subnets_are_named_after_their_cidr() {
+        let options = options();
+        assert_eq!(options.subnets[0].label, "office (10.1.0.0/16)");
+        let mut table = RollupTable::new();
+        table.register(("10.1.2.3", "40000"), ("192.168.0.1", "443"), 100, &options);
+        let names: Vec<String> = table.subnets().into_iter().map(|r| r.name).collect();
+        assert_eq!(names, vec!["10.1.2.0/24", "192.168.0.0/24", "office (10.1.0.0/16)"]);
+    }
+
+    #[test]
+    fn distinct_peers_and_ports_are_capped() {
+        let options = AggregationOptions{hosts: true, ..Default::default()};
+        let mut table = RollupTable::new();
+        for i in 0..MAX_DISTINCT + 10 {
+            table.register(("10.0.0.1", &i.to_string()), (&format!("10.1.{}.{}", i / 256, i % 256), "80"), 1, &options);
+        }
+        let host = table.hosts().into_iter().find(|r| r.name == "10.0.0.1").unwrap();
+        assert_eq!(host.packets_out, MAX_DISTINCT as u64 + 10);
+        assert_eq!(host.peers.len(), MAX_DISTINCT);
+        assert!(host.more_peers && host.more_ports);
+        assert!(table.to_report_string(&options).contains(&format!("peers: {}+; ports: {}+", MAX_DISTINCT, MAX_DISTINCT)));
+    }
+
+    #[test]
+    fn hosts_over_the_limit_are_aggregated() {
+        let options = AggregationOptions{hosts: true, ..Default::default()};
+        let mut table = RollupTable::new();
+        for i in 0..MAX_ROLLUPS {
+            table.register((&format!("10.0.{}.{}", i / 256, i % 256), "1"), ("10.255.0.1", "2"), 1, &options);
+        }
+        assert_eq!(table.hosts().len(), MAX_ROLLUPS);
+        let report = table.to_report_string(&options);
+        assert!(report.contains("Other hosts: bytes in: 0; bytes out: 1; packets in: 0; packets out: 1; peers: 1; ports: 1\n"));
+        table.reset_stats();
+        assert!(table.hosts().is_empty());
+        assert!(!table.to_report_string(&options).contains("Other hosts"));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+/// Time (in seconds) after which a stream without new segments is removed.
+const STREAM_IDLE_TIMEOUT: u64 = 120;
+
+#[derive(Debug, Clone, Copy)]
+///Struct containing the fields of a TCP header used to reassemble the byte streams.
+pub struct TcpSegment {
+    /// Sequence number of the segment
+    pub sequence: u32,
+    /// True if the segment opens the connection
+    pub syn: bool,
+    /// True if the segment closes its direction of the connection
+    pub fin: bool,
+    /// True if the segment resets the connection
+    pub rst: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///Struct identifying a direction of a TCP connection.
+pub struct StreamKey {
+    /// Address and port of the sender, e.g. "10.0.0.1 53"
+    pub source: String,
+    /// Address and port of the receiver
+    pub destination: String,
+}
+
+#[derive(Debug)]
+///Struct containing the bytes of a direction of a TCP connection received in order and not consumed yet.
+pub struct TcpStream {
+    /// Buffered bytes, waiting for the rest of their message
+    data: Vec<u8>,
+    /// Sequence number of the next byte expected
+    next_sequence: u32,
+    /// True if the first buffered byte starts a message: the stream was seen from its SYN, or the buffered messages were
+    /// consumed up to the end of a segment
+    synchronized: bool,
+    /// True if some bytes were lost or dropped since the last call of `take_discarded`
+    discarded: bool,
+    /// True if the buffered bytes were dropped and no message was consumed since then
+    resynchronizing: bool,
+    /// Timestamp (in seconds) of the last segment
+    last_seen: u64,
+}
+
+#[derive(Debug)]
+///Struct that reassembles the byte streams of TCP connections, keeping for each direction the bytes of the messages that
+///span more than one segment.
+pub struct StreamTable {
+    /// Streams by direction of the connection
+    streams: HashMap<StreamKey, TcpStream>,
+    /// Maximum number of bytes kept waiting for the rest of a message
+    max_buffered: usize,
+}
+
+impl TcpStream {
+    /// Returns the buffered bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Checks if the first buffered byte is known to start a message.
+    pub fn is_synchronized(&self) -> bool {
+        self.synchronized
+    }
+
+    /// Removes the first `len` buffered bytes, which contained complete messages. The bytes that follow start a message.
+    pub fn consume(&mut self, len: usize) {
+        self.data.drain(..len.min(self.data.len()));
+        self.synchronized = true;
+        self.resynchronizing = false;
+    }
+
+    /// Drops the buffered bytes, which cannot be decoded. The stream is resynchronized at the start of a later segment.
+    pub fn skip(&mut self) {
+        self.data.clear();
+        self.synchronized = false;
+        self.resynchronizing = true;
+    }
+
+    /// Checks if the buffered bytes were dropped and the stream is still waiting for the start of a message.
+    pub fn is_resynchronizing(&self) -> bool {
+        self.resynchronizing
+    }
+
+    /// Returns true if some bytes were lost or dropped since the last call, and clears the indication.
+    pub fn take_discarded(&mut self) -> bool {
+        std::mem::take(&mut self.discarded)
+    }
+}
+
+impl StreamTable {
+    /// Create a new empty StreamTable struct, which keeps at most `max_buffered` bytes waiting for the rest of a message.
+    pub fn new(max_buffered: usize) -> StreamTable {
+        StreamTable{streams: HashMap::new(), max_buffered}
+    }
+
+    /// Registers the `payload` of a segment sent at timestamp `ts` (in seconds) in the direction `key`, and returns the stream
+    /// with the new bytes appended, or None if the segment carries no new bytes. The bytes already received are dropped
+    /// from the retransmissions. When some bytes are lost, or the buffer is full, the buffered bytes are dropped and the
+    /// stream is no longer synchronized.
+    pub fn register(&mut self, key: StreamKey, segment: TcpSegment, payload: &[u8], ts: u64) -> Option<&mut TcpStream> {
+        // The SYN takes a sequence number: the payload, if any, starts after it
+        let start = if segment.syn { segment.sequence.wrapping_add(1) } else { segment.sequence };
+        let stream = self.streams.entry(key).or_insert_with(|| TcpStream{data: Vec::new(), next_sequence: start, synchronized: false, discarded: false, resynchronizing: false, last_seen: ts});
+        if segment.syn {
+            *stream = TcpStream{data: Vec::new(), next_sequence: start, synchronized: true, discarded: false, resynchronizing: false, last_seen: ts};
+        }
+        stream.last_seen = ts;
+        let mut payload = payload;
+        let offset = start.wrapping_sub(stream.next_sequence) as i32;
+        if offset < 0 {
+            let received = offset.unsigned_abs() as usize;
+            if received >= payload.len() {
+                return None;
+            }
+            payload = &payload[received..];
+        } else if offset > 0 {
+            stream.skip();
+            stream.discarded = true;
+            stream.next_sequence = start;
+        }
+        if payload.is_empty() {
+            return None;
+        }
+        stream.next_sequence = stream.next_sequence.wrapping_add(payload.len() as u32);
+        if stream.data.len() >= self.max_buffered {
+            stream.skip();
+            stream.discarded = true;
+        }
+        stream.data.extend_from_slice(payload);
+        Some(stream)
+    }
+
+    /// Checks if the direction `key` has a stream.
+    pub fn contains(&self, key: &StreamKey) -> bool {
+        self.streams.contains_key(key)
+    }
+
+    /// Removes the stream `key`, when its direction of the connection is closed or reset.
+    pub fn close(&mut self, key: &StreamKey) {
+        self.streams.remove(key);
+    }
+
+    /// Removes, at timestamp `now` (in seconds), the streams without segments for longer than the idle timeout.
+    pub fn purge_expired(&mut self, now: u64) {
+        let deadline = now.saturating_sub(STREAM_IDLE_TIMEOUT);
+        self.streams.retain(|_, stream| stream.last_seen >= deadline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> StreamKey {
+        StreamKey{source: "10.0.0.1 40000".to_string(), destination: "10.0.0.2 53".to_string()}
+    }
+
+    fn segment(sequence: u32, syn: bool) -> TcpSegment {
+        TcpSegment{sequence, syn, fin: false, rst: false}
+    }
+
+    #[test]
+    fn appends_in_order_segments() {
+        let mut table = StreamTable::new(1024);
+        assert!(table.register(key(), segment(99, true), &[], 0).is_none());
+        table.register(key(), segment(100, false), b"abc", 0).unwrap();
+        let stream = table.register(key(), segment(103, false), b"def", 0).unwrap();
+        assert!(stream.is_synchronized());
+        assert_eq!(stream.data(), b"abcdef");
+        assert!(!stream.take_discarded());
+    }
+
+    #[test]
+    fn drops_retransmitted_bytes() {
+        let mut table = StreamTable::new(1024);
+        table.register(key(), segment(99, true), &[], 0);
+        table.register(key(), segment(100, false), b"abc", 0).unwrap();
+        assert!(table.register(key(), segment(100, false), b"abc", 0).is_none());
+        let stream = table.register(key(), segment(101, false), b"bcde", 0).unwrap();
+        assert_eq!(stream.data(), b"abcde");
+    }
+
+    #[test]
+    fn gap_drops_buffered_bytes() {
+        let mut table = StreamTable::new(1024);
+        table.register(key(), segment(99, true), &[], 0);
+        table.register(key(), segment(100, false), b"abc", 0).unwrap();
+        let stream = table.register(key(), segment(110, false), b"xyz", 0).unwrap();
+        assert_eq!(stream.data(), b"xyz");
+        assert!(!stream.is_synchronized());
+        assert!(stream.take_discarded());
+    }
+
+    #[test]
+    fn stream_without_syn_is_not_synchronized() {
+        let mut table = StreamTable::new(1024);
+        let stream = table.register(key(), segment(5000, false), b"abc", 0).unwrap();
+        assert!(!stream.is_synchronized());
+        stream.consume(3);
+        assert!(stream.is_synchronized());
+        assert!(stream.data().is_empty());
+    }
+
+    #[test]
+    fn full_buffer_is_dropped() {
+        let mut table = StreamTable::new(4);
+        table.register(key(), segment(99, true), &[], 0);
+        table.register(key(), segment(100, false), b"abcd", 0).unwrap();
+        let stream = table.register(key(), segment(104, false), b"ef", 0).unwrap();
+        assert_eq!(stream.data(), b"ef");
+        assert!(stream.take_discarded());
+    }
+
+    #[test]
+    fn idle_streams_expire() {
+        let mut table = StreamTable::new(1024);
+        table.register(key(), segment(100, false), b"abc", 0);
+        let other = StreamKey{source: "10.0.0.3 40000".to_string(), destination: "10.0.0.2 53".to_string()};
+        table.register(other, segment(100, false), b"abc", STREAM_IDLE_TIMEOUT + 1);
+        table.purge_expired(STREAM_IDLE_TIMEOUT);
+        assert_eq!(table.streams.len(), 2);
+        table.purge_expired(STREAM_IDLE_TIMEOUT + 1);
+        assert_eq!(table.streams.len(), 1);
+        assert!(!table.streams.contains_key(&key()));
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use crate::options::FlowOrder;
+use crate::report::{AddressPortPair, OverflowBucket, Report};
+
+/// Transport protocols that identify the service of a flow by its port.
+const PORT_PROTOCOLS: [&str; 4] = ["TCP", "UDP", "SCTP", "DCCP"];
+
+#[derive(Debug, Clone, Default)]
+///Struct containing the traffic aggregated under a key of the summary, e.g. a host or a port.
+struct Traffic {
+    /// Bytes of the flows
+    bytes: u64,
+    /// Packets of the flows
+    packets: u64,
+}
+
+impl Traffic {
+    /// Adds the traffic of a flow.
+    fn add(&mut self, report: &Report) {
+        self.bytes += report.total_bytes();
+        self.packets += report.total_packets();
+    }
+}
+
+/// Key that orders an endpoint by address and port, followed by the endpoint as text to break the ties.
+type EndpointKey<'a> = (Option<IpAddr>, Option<u16>, &'a (String, String));
+
+/// Key that orders the flows by address: the lower endpoint, then the higher one.
+type AddressKey<'a> = (EndpointKey<'a>, EndpointKey<'a>);
+
+/// Function that returns the key that orders an endpoint: the address is compared as IP address and the port as number,
+/// the endpoints that are not addresses or ports (e.g. "No port") come first.
+fn endpoint_key(endpoint: &(String, String)) -> EndpointKey<'_> {
+    (endpoint.0.parse().ok(), endpoint.1.parse().ok(), endpoint)
+}
+
+/// Function that returns the key that orders a flow by address. The endpoints are sorted, because a flow is the same
+/// whichever endpoint sent its first packet, so that the order does not depend on the direction of the first packet.
+fn address_key(pair: &AddressPortPair) -> AddressKey<'_> {
+    let first = endpoint_key(&pair.first_pair);
+    let second = endpoint_key(&pair.second_pair);
+    if first <= second { (first, second) } else { (second, first) }
+}
+
+/// Function that returns the flows of `report` sorted following `order`, the largest first for bytes and packets and
+/// the oldest first for the first timestamp. The flows that are equal for `order` are sorted by address, so that the
+/// order does not depend on the map and two reports of the same traffic are identical.
+pub fn sort_flows<'a>(report: impl IntoIterator<Item = (&'a AddressPortPair, &'a Report)>, order: FlowOrder) -> Vec<(&'a AddressPortPair, &'a Report)> {
+    let mut flows: Vec<(&AddressPortPair, &Report)> = report.into_iter().collect();
+    match order {
+        FlowOrder::Address => flows.sort_by(|a, b| address_key(a.0).cmp(&address_key(b.0))),
+        FlowOrder::Bytes => flows.sort_by(|a, b| b.1.total_bytes().cmp(&a.1.total_bytes()).then_with(|| address_key(a.0).cmp(&address_key(b.0)))),
+        FlowOrder::Packets => flows.sort_by(|a, b| b.1.total_packets().cmp(&a.1.total_packets()).then_with(|| address_key(a.0).cmp(&address_key(b.0)))),
+        FlowOrder::FirstSeen => flows.sort_by(|a, b| a.1.first_ts().cmp(&b.1.first_ts()).then_with(|| address_key(a.0).cmp(&address_key(b.0))))
+    }
+    flows
+}
+
+/// Function that returns the service of a flow, as transport protocol and port: the port of the side that does not use an
+/// ephemeral port, which is the lower one.
+fn service(pair: &AddressPortPair, report: &Report) -> Option<String> {
+    let protocol = PORT_PROTOCOLS.iter().find(|p| report.transport_layer_protocols().contains(**p))?;
+    let first = pair.first_pair.1.parse::<u16>().ok()?;
+    let second = pair.second_pair.1.parse::<u16>().ok()?;
+    Some(format!("{}/{}", protocol, first.min(second)))
+}
+
+/// Function that returns the protocol mix key of a flow, e.g. "TCP" or "QUIC, UDP".
+fn protocol_mix_key(report: &Report) -> String {
+    let mut protocols: Vec<&str> = report.transport_layer_protocols().iter().map(|p| p.as_str()).collect();
+    protocols.sort();
+    protocols.join(", ")
+}
+
+/// Function that returns the `top_n` keys of `traffic` with the most bytes, or packets if `by_packets` is true.
+fn top(traffic: &HashMap<String, Traffic>, top_n: usize, by_packets: bool) -> Vec<(&String, &Traffic)> {
+    let mut entries: Vec<(&String, &Traffic)> = traffic.iter().collect();
+    if by_packets {
+        entries.sort_by(|a, b| b.1.packets.cmp(&a.1.packets).then(a.0.cmp(b.0)));
+    } else {
+        entries.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+    }
+    entries.truncate(top_n);
+    entries
+}
+
+/// Function that returns the conversation of a flow: the pair of hosts, whatever their ports and the direction of the flow.
+fn conversation(pair: &AddressPortPair) -> String {
+    let (first, second) = (&pair.first_pair.0, &pair.second_pair.0);
+    if first <= second {
+        format!("{} <-> {}", first, second)
+    } else {
+        format!("{} <-> {}", second, first)
+    }
+}
+
+/// Function that generates the summary section at the top of a report: the `top_n` hosts by bytes and by packets, the
+/// `top_n` conversations between pairs of hosts and services by bytes, and the share of traffic of each protocol. The
+/// traffic of the flows evicted in `overflow`, if any, is part of the totals and of the protocol mix.
+pub fn summary_to_string<'a>(report: impl IntoIterator<Item = (&'a AddressPortPair, &'a Report)>, overflow: Option<&OverflowBucket>, top_n: usize) -> String {
+    let mut hosts: HashMap<String, Traffic> = HashMap::new();
+    let mut conversations: HashMap<String, Traffic> = HashMap::new();
+    let mut services: HashMap<String, Traffic> = HashMap::new();
+    let mut protocols: HashMap<String, Traffic> = HashMap::new();
+    let mut total = Traffic::default();
+    let mut flows = 0;
+    for (pair, flow) in report {
+        flows += 1;
+        total.add(flow);
+        hosts.entry(pair.first_pair.0.clone()).or_default().add(flow);
+        if pair.second_pair.0 != pair.first_pair.0 {
+            hosts.entry(pair.second_pair.0.clone()).or_default().add(flow);
+        }
+        conversations.entry(conversation(pair)).or_default().add(flow);
+        if let Some(service) = service(pair, flow) {
+            services.entry(service).or_default().add(flow);
+        }
+        protocols.entry(protocol_mix_key(flow)).or_default().add(flow);
+    }
+    // The protocols of the evicted flows are not kept
+    let evicted = overflow.filter(|o| o.evictions > 0).map(|o| Traffic{bytes: o.bytes, packets: o.packets});
+    if let Some(evicted) = &evicted {
+        total.bytes += evicted.bytes;
+        total.packets += evicted.packets;
+        protocols.insert("Evicted flows".to_string(), evicted.clone());
+    }
+
+    let mut s = "".to_owned();
+    s.push_str("-----Summary-----\n");
+    s.push_str(&format!("Flows: {}; Total bytes: {}; Total packets: {}", flows, total.bytes, total.packets));
+    if let Some(overflow) = overflow.filter(|o| o.evictions > 0) {
+        s.push_str(&format!(" (including the traffic of {} evicted flows)", overflow.evictions));
+    }
+    s.push('\n');
+    s.push_str("Top hosts by bytes:\n");
+    for (host, traffic) in top(&hosts, top_n, false) {
+        s.push_str(&format!("{}: {} bytes\n", host, traffic.bytes));
+    }
+    s.push_str("Top hosts by packets:\n");
+    for (host, traffic) in top(&hosts, top_n, true) {
+        s.push_str(&format!("{}: {} packets\n", host, traffic.packets));
+    }
+    s.push_str("Top conversations:\n");
+    for (conversation, traffic) in top(&conversations, top_n, false) {
+        s.push_str(&format!("{}: {} bytes, {} packets\n", conversation, traffic.bytes, traffic.packets));
+    }
+    s.push_str("Top services:\n");
+    for (service, traffic) in top(&services, top_n, false) {
+        s.push_str(&format!("{}: {} bytes, {} packets\n", service, traffic.bytes, traffic.packets));
+    }
+    s.push_str("Protocol mix:\n");
+    for (protocol, traffic) in top(&protocols, protocols.len(), false) {
+        let share = if total.bytes == 0 { 0.0 } else { traffic.bytes as f64 * 100.0 / total.bytes as f64 };
+        s.push_str(&format!("{}: {} bytes ({:.1}%), {} packets\n", protocol, traffic.bytes, share, traffic.packets));
+    }
+    s.push('\n');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::LinkInfo;
+
+    fn flow(bytes: u32) -> Report {
+        let link = LinkInfo{source_mac: [0; 6], destination_mac: [0; 6], ether_type: "Ipv4".to_string()};
+        Report::new(0, bytes, "TCP".to_string(), "IPv4".to_string(), link, String::new(), String::new())
+    }
+
+    fn pair(first: (&str, &str), second: (&str, &str)) -> AddressPortPair {
+        AddressPortPair::new(first.0.to_string(), first.1.to_string(), second.0.to_string(), second.1.to_string())
+    }
+
+    #[test]
+    fn conversations_are_pairs_of_hosts() {
+        let mut report = HashMap::new();
+        report.insert(pair(("10.0.0.1", "40000"), ("10.0.0.2", "443")), flow(100));
+        report.insert(pair(("10.0.0.2", "443"), ("10.0.0.1", "40001")), flow(200));
+        report.insert(pair(("10.0.0.1", "40002"), ("10.0.0.3", "53")), flow(50));
+        let summary = summary_to_string(&report, None, 10);
+        assert!(summary.contains("Top conversations:\n10.0.0.1 <-> 10.0.0.2: 300 bytes, 2 packets\n10.0.0.1 <-> 10.0.0.3: 50 bytes, 1 packets\n"));
+    }
+
+    #[test]
+    fn address_order_ignores_the_direction() {
+        let forward = pair(("10.0.0.2", "443"), ("10.0.0.1", "40000"));
+        let backward = pair(("10.0.0.1", "40000"), ("10.0.0.2", "443"));
+        assert_eq!(forward, backward);
+        assert_eq!(address_key(&forward), address_key(&backward));
+        let mut report = HashMap::new();
+        report.insert(forward, flow(100));
+        report.insert(pair(("10.0.0.1", "9000"), ("10.0.0.10", "80")), flow(100));
+        report.insert(pair(("No address", "No port"), ("10.0.0.1", "5000")), flow(100));
+        let order: Vec<String> = sort_flows(&report, FlowOrder::Address).into_iter().map(|(pair, _)| pair.first_pair.1.clone()).collect();
+        assert_eq!(order, vec!["No port", "9000", "443"]);
+    }
+
+    #[test]
+    fn evicted_traffic_is_part_of_the_totals() {
+        let mut report = HashMap::new();
+        report.insert(pair(("10.0.0.1", "40000"), ("10.0.0.2", "443")), flow(100));
+        let overflow = OverflowBucket{evictions: 3, bytes: 300, packets: 3, first_ts: Some(0), last_ts: Some(0)};
+        let summary = summary_to_string(&report, Some(&overflow), 10);
+        assert!(summary.contains("Flows: 1; Total bytes: 400; Total packets: 4 (including the traffic of 3 evicted flows)\n"));
+        assert!(summary.contains("TCP: 100 bytes (25.0%), 1 packets\n"));
+        assert!(summary.contains("Evicted flows: 300 bytes (75.0%), 3 packets\n"));
+        // Without evictions the bucket is not shown
+        let summary = summary_to_string(&report, Some(&OverflowBucket::default()), 10);
+        assert!(summary.contains("Flows: 1; Total bytes: 100; Total packets: 1\n"));
+    }
+}
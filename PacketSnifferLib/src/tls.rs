@@ -0,0 +1,733 @@
+use sha2::{Digest, Sha256};
+use crate::stream::{StreamKey, StreamTable, TcpSegment};
+
+/// Content type of a TLS handshake record.
+const HANDSHAKE_RECORD: u8 = 22;
+/// Handshake type of a ClientHello message.
+const CLIENT_HELLO: u8 = 1;
+/// Handshake type of a ServerHello message.
+const SERVER_HELLO: u8 = 2;
+/// Extension carrying the server name (SNI).
+const EXT_SERVER_NAME: u16 = 0;
+/// Extension carrying the application protocols (ALPN).
+const EXT_ALPN: u16 = 16;
+/// Extension carrying the supported TLS versions.
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+/// Extension carrying the supported elliptic curves (groups).
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+/// Extension carrying the supported elliptic curve point formats.
+const EXT_EC_POINT_FORMATS: u16 = 11;
+/// Extension carrying the supported signature algorithms.
+const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+/// Maximum number of bytes of a hello message buffered while waiting for the rest of its segments.
+pub const MAX_TLS_HANDSHAKE_BUFFER: usize = 65536;
+
+#[derive(Debug, Clone, Default)]
+///Struct containing the fields of a TLS ClientHello message.
+pub struct TlsClientHello {
+    /// Version field of the handshake message
+    pub legacy_version: u16,
+    /// Offered cipher suites, in the order sent by the client
+    pub cipher_suites: Vec<u16>,
+    /// Extension types, in the order sent by the client
+    pub extensions: Vec<u16>,
+    /// Server name indication
+    pub sni: Option<String>,
+    /// Offered application protocols
+    pub alpn: Vec<String>,
+    /// Versions listed in the supported_versions extension
+    pub supported_versions: Vec<u16>,
+    /// Elliptic curves listed in the supported_groups extension
+    pub supported_groups: Vec<u16>,
+    /// Point formats listed in the ec_point_formats extension
+    pub ec_point_formats: Vec<u8>,
+    /// Signature algorithms, in the order sent by the client
+    pub signature_algorithms: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Default)]
+///Struct containing the fields of a TLS ServerHello message.
+pub struct TlsServerHello {
+    /// Version field of the handshake message
+    pub legacy_version: u16,
+    /// Cipher suite chosen by the server
+    pub cipher_suite: u16,
+    /// Extension types, in the order sent by the server
+    pub extensions: Vec<u16>,
+    /// Version chosen through the supported_versions extension (TLS 1.3)
+    pub selected_version: Option<u16>,
+    /// Application protocol chosen by the server
+    pub alpn: Option<String>,
+}
+
+impl TlsServerHello {
+    /// Version negotiated by the handshake.
+    pub fn negotiated_version(&self) -> u16 {
+        self.selected_version.unwrap_or(self.legacy_version)
+    }
+}
+
+#[derive(Debug, Clone)]
+///Enum representing the handshake messages that are decoded.
+pub enum TlsHandshake {
+    /// First message sent by the client
+    ClientHello(TlsClientHello),
+    /// Answer of the server to the ClientHello
+    ServerHello(TlsServerHello),
+}
+
+#[derive(Debug, Clone, Default)]
+///Struct which reports info relative to the TLS handshake of a flow.
+pub struct TlsInfo {
+    /// Server name requested by the client
+    pub sni: Option<String>,
+    /// Application protocols offered by the client
+    pub client_alpn: Vec<String>,
+    /// Versions offered by the client
+    pub client_versions: Vec<u16>,
+    /// Version negotiated with the server
+    pub negotiated_version: Option<u16>,
+    /// Cipher suite chosen by the server
+    pub cipher_suite: Option<u16>,
+    /// Application protocol chosen by the server
+    pub server_alpn: Option<String>,
+    /// JA3 string of the ClientHello
+    pub ja3_string: Option<String>,
+    /// JA3 client fingerprint (MD5 of the JA3 string)
+    pub ja3: Option<String>,
+    /// JA4 client fingerprint
+    pub ja4: Option<String>,
+    /// JA3S string of the ServerHello
+    pub ja3s_string: Option<String>,
+    /// JA3S server fingerprint (MD5 of the JA3S string)
+    pub ja3s: Option<String>,
+    /// True if the segments of a hello message were lost, so its fingerprint is missing
+    pub incomplete_handshake: bool,
+}
+
+#[derive(Debug, Clone)]
+///Enum representing the result of the reassembly of a hello message from the segments of a TCP stream.
+pub enum TlsReassembly {
+    /// The hello message is complete
+    Complete(TlsHandshake),
+    /// The hello message continues in the next segments
+    Incomplete,
+    /// Some segments of the hello message were lost
+    Lost,
+    /// The stream does not start with a hello message
+    NotHandshake,
+}
+
+impl TlsInfo {
+    /// Updates the struct with the fields of a handshake message.
+    /// `transport` is 't' if the message was carried by TCP and 'q' if it was carried by QUIC, as used by the JA4 fingerprint.
+    pub fn update(&mut self, handshake: &TlsHandshake, transport: char) {
+        match handshake {
+            TlsHandshake::ClientHello(hello) => {
+                if hello.sni.is_some() {
+                    self.sni = hello.sni.clone();
+                }
+                self.client_alpn = hello.alpn.clone();
+                self.client_versions = if hello.supported_versions.is_empty() {
+                    vec![hello.legacy_version]
+                } else {
+                    hello.supported_versions.clone()
+                };
+                let ja3_string = ja3_string(hello);
+                self.ja3 = Some(format!("{:x}", md5::compute(ja3_string.as_bytes())));
+                self.ja3_string = Some(ja3_string);
+                self.ja4 = Some(ja4(hello, transport));
+            },
+            TlsHandshake::ServerHello(hello) => {
+                self.negotiated_version = Some(hello.negotiated_version());
+                self.cipher_suite = Some(hello.cipher_suite);
+                self.server_alpn = hello.alpn.clone();
+                let ja3s_string = ja3s_string(hello);
+                self.ja3s = Some(format!("{:x}", md5::compute(ja3s_string.as_bytes())));
+                self.ja3s_string = Some(ja3s_string);
+            }
+        }
+    }
+
+    /// Merges the informations of a later part of the same flow, which replace the ones of the same message.
+    pub fn merge(&mut self, other: TlsInfo) {
+        self.incomplete_handshake |= other.incomplete_handshake;
+        if other.ja3.is_some() {
+            if other.sni.is_some() {
+                self.sni = other.sni;
+            }
+            self.client_alpn = other.client_alpn;
+            self.client_versions = other.client_versions;
+            self.ja3_string = other.ja3_string;
+            self.ja3 = other.ja3;
+            self.ja4 = other.ja4;
+        }
+        if other.ja3s.is_some() {
+            self.negotiated_version = other.negotiated_version;
+            self.cipher_suite = other.cipher_suite;
+            self.server_alpn = other.server_alpn;
+            self.ja3s_string = other.ja3s_string;
+            self.ja3s = other.ja3s;
+        }
+    }
+}
+
+/// Function that checks if a value is a GREASE value (RFC 8701), used by clients to keep the extension points open.
+pub fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+/// Function that returns the name of a TLS version.
+pub fn tls_version_name(version: u16) -> String {
+    match version {
+        0x0300 => "SSL 3.0".to_string(),
+        0x0301 => "TLS 1.0".to_string(),
+        0x0302 => "TLS 1.1".to_string(),
+        0x0303 => "TLS 1.2".to_string(),
+        0x0304 => "TLS 1.3".to_string(),
+        _ => format!("0x{:04x}", version)
+    }
+}
+
+/// Function that returns the name of the most common cipher suites, or their hexadecimal code.
+pub fn cipher_suite_name(suite: u16) -> String {
+    let name = match suite {
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0xc02b => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02c => "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xc02f => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        0xc030 => "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        0xcca8 => "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xcca9 => "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xc009 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
+        0xc00a => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA",
+        0xc013 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        0xc014 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        0x009c => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0x009d => "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        0x002f => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        0x000a => "TLS_RSA_WITH_3DES_EDE_CBC_SHA",
+        _ => return format!("0x{:04x}", suite)
+    };
+    format!("{} (0x{:04x})", name, suite)
+}
+
+/// Simple cursor used to read the big endian fields of a handshake message.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader{data, pos: 0}
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.remaining() < len {
+            return None;
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Some(slice)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.bytes(3).map(|b| ((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+
+    /// Reads a vector preceded by a length field of `len_size` bytes.
+    fn vector(&mut self, len_size: usize) -> Option<&'a [u8]> {
+        let len = match len_size {
+            1 => self.u8()? as usize,
+            2 => self.u16()? as usize,
+            _ => self.u24()?
+        };
+        self.bytes(len)
+    }
+}
+
+/// Function that reads a list of 16 bits values.
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect()
+}
+
+/// Function that reads the protocol names of an ALPN extension.
+fn alpn_list(data: &[u8]) -> Vec<String> {
+    let mut reader = Reader::new(data);
+    let mut protocols = Vec::new();
+    if let Some(list) = reader.vector(2) {
+        let mut list = Reader::new(list);
+        while let Some(name) = list.vector(1) {
+            protocols.push(String::from_utf8_lossy(name).to_string());
+        }
+    }
+    protocols
+}
+
+/// Function that reads the host name of a server_name extension.
+fn server_name(data: &[u8]) -> Option<String> {
+    let mut reader = Reader::new(data);
+    let mut list = Reader::new(reader.vector(2)?);
+    while list.remaining() > 0 {
+        let name_type = list.u8()?;
+        let name = list.vector(2)?;
+        if name_type == 0 {
+            return Some(String::from_utf8_lossy(name).to_string());
+        }
+    }
+    None
+}
+
+/// Function that reads the extensions block of a hello message, returning the type and the data of each extension.
+/// A block truncated by the end of the segment returns the extensions that are complete.
+fn extensions(reader: &mut Reader) -> Vec<(u16, Vec<u8>)> {
+    let mut result = Vec::new();
+    let len = match reader.u16() {
+        Some(len) => len as usize,
+        None => return result
+    };
+    let available = len.min(reader.remaining());
+    let mut block = match reader.bytes(available) {
+        Some(block) => Reader::new(block),
+        None => return result
+    };
+    while block.remaining() >= 4 {
+        let ext_type = block.u16().unwrap_or(0);
+        match block.vector(2) {
+            Some(data) => result.push((ext_type, data.to_vec())),
+            None => break
+        }
+    }
+    result
+}
+
+/// Function that parses the body of a ClientHello message.
+pub fn parse_client_hello(body: &[u8]) -> Option<TlsClientHello> {
+    let mut reader = Reader::new(body);
+    let mut hello = TlsClientHello{legacy_version: reader.u16()?, ..Default::default()};
+    reader.bytes(32)?;
+    reader.vector(1)?;
+    hello.cipher_suites = u16_list(reader.vector(2)?);
+    reader.vector(1)?;
+    for (ext_type, data) in extensions(&mut reader) {
+        hello.extensions.push(ext_type);
+        match ext_type {
+            EXT_SERVER_NAME => hello.sni = server_name(&data),
+            EXT_ALPN => hello.alpn = alpn_list(&data),
+            EXT_SUPPORTED_VERSIONS => {
+                if let Some(list) = Reader::new(&data).vector(1) {
+                    hello.supported_versions = u16_list(list).into_iter().filter(|v| !is_grease(*v)).collect();
+                }
+            },
+            EXT_SUPPORTED_GROUPS => {
+                if let Some(list) = Reader::new(&data).vector(2) {
+                    hello.supported_groups = u16_list(list);
+                }
+            },
+            EXT_EC_POINT_FORMATS => {
+                if let Some(list) = Reader::new(&data).vector(1) {
+                    hello.ec_point_formats = list.to_vec();
+                }
+            },
+            EXT_SIGNATURE_ALGORITHMS => {
+                if let Some(list) = Reader::new(&data).vector(2) {
+                    hello.signature_algorithms = u16_list(list);
+                }
+            },
+            _ => {}
+        }
+    }
+    Some(hello)
+}
+
+/// Function that parses the body of a ServerHello message.
+pub fn parse_server_hello(body: &[u8]) -> Option<TlsServerHello> {
+    let mut reader = Reader::new(body);
+    let mut hello = TlsServerHello{legacy_version: reader.u16()?, ..Default::default()};
+    reader.bytes(32)?;
+    reader.vector(1)?;
+    hello.cipher_suite = reader.u16()?;
+    reader.u8()?;
+    for (ext_type, data) in extensions(&mut reader) {
+        hello.extensions.push(ext_type);
+        match ext_type {
+            EXT_ALPN => hello.alpn = alpn_list(&data).into_iter().next(),
+            EXT_SUPPORTED_VERSIONS => hello.selected_version = Reader::new(&data).u16(),
+            _ => {}
+        }
+    }
+    Some(hello)
+}
+
+/// Function that parses a handshake message (type and 3 bytes length followed by the body).
+/// A body truncated by the end of the segment is parsed as far as possible.
+pub fn parse_handshake_message(data: &[u8]) -> Option<TlsHandshake> {
+    let mut reader = Reader::new(data);
+    let handshake_type = reader.u8()?;
+    let len = reader.u24()?;
+    let body = reader.bytes(len.min(reader.remaining()))?;
+    match handshake_type {
+        CLIENT_HELLO => parse_client_hello(body).map(TlsHandshake::ClientHello),
+        SERVER_HELLO => parse_server_hello(body).map(TlsHandshake::ServerHello),
+        _ => None
+    }
+}
+
+/// Function that parses the hello message at the start of the bytes of a TCP stream. The message can span several TLS
+/// records, and the records several segments: when the bytes end before the end of the message, it returns Incomplete.
+pub fn parse_tls_handshake(data: &[u8]) -> TlsReassembly {
+    if data.is_empty() {
+        return TlsReassembly::NotHandshake;
+    }
+    let mut reader = Reader::new(data);
+    let mut message: Vec<u8> = Vec::new();
+    while reader.remaining() > 0 {
+        let header = reader.bytes(reader.remaining().min(5)).unwrap_or_default();
+        if header[0] != HANDSHAKE_RECORD || matches!(header.get(1), Some(major) if *major != 0x03) {
+            return TlsReassembly::NotHandshake;
+        }
+        if header.len() < 5 {
+            return TlsReassembly::Incomplete;
+        }
+        let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        let fragment = reader.bytes(len.min(reader.remaining())).unwrap_or_default();
+        message.extend_from_slice(fragment);
+        // The type, the length and the version of the message tell a hello from an encrypted record
+        if matches!(message.first(), Some(t) if *t != CLIENT_HELLO && *t != SERVER_HELLO) || matches!(message.get(4), Some(major) if *major != 0x03) {
+            return TlsReassembly::NotHandshake;
+        }
+        if message.len() >= 4 {
+            let needed = 4 + (((message[1] as usize) << 16) | ((message[2] as usize) << 8) | message[3] as usize);
+            if needed > MAX_TLS_HANDSHAKE_BUFFER {
+                return TlsReassembly::NotHandshake;
+            }
+            if message.len() >= needed {
+                return match parse_handshake_message(&message[..needed]) {
+                    Some(handshake) => TlsReassembly::Complete(handshake),
+                    None => TlsReassembly::NotHandshake
+                };
+            }
+        }
+        if fragment.len() < len {
+            break;
+        }
+    }
+    TlsReassembly::Incomplete
+}
+
+/// Function that registers the `payload` of a TCP segment sent at timestamp `ts` (in seconds) in the direction `key`, and
+/// returns the hello message it completes. A hello message that does not fit in its first segment is buffered in
+/// `streams` until its last segment arrives, or until some of its segments are lost or the connection is closed.
+pub fn reassemble_tls_handshake(streams: &mut StreamTable, key: StreamKey, segment: TcpSegment, payload: &[u8], ts: u64) -> TlsReassembly {
+    let status = if streams.contains(&key) {
+        match streams.register(key.clone(), segment, payload, ts) {
+            Some(stream) => {
+                if stream.take_discarded() {
+                    TlsReassembly::Lost
+                } else {
+                    parse_tls_handshake(stream.data())
+                }
+            },
+            None => TlsReassembly::Incomplete
+        }
+    } else {
+        let status = parse_tls_handshake(payload);
+        if matches!(status, TlsReassembly::Incomplete) && !segment.fin && !segment.rst {
+            streams.register(key, segment, payload, ts);
+            return status;
+        }
+        status
+    };
+    match status {
+        TlsReassembly::Incomplete if !segment.fin && !segment.rst => TlsReassembly::Incomplete,
+        TlsReassembly::Incomplete => {
+            streams.close(&key);
+            TlsReassembly::Lost
+        },
+        status => {
+            streams.close(&key);
+            status
+        }
+    }
+}
+
+/// The function takes as parameter a `TlsInfo` struct and generate a String containing its formatted informations.
+pub fn tls_info_to_string(info: &TlsInfo) -> String {
+    let mut tls_string = "".to_owned();
+
+    tls_string.push_str("SNI: ");
+    tls_string.push_str(info.sni.as_deref().unwrap_or("None"));
+
+    tls_string.push_str("; Client ALPN: ");
+    tls_string.push_str(info.client_alpn.join(", ").as_str());
+
+    tls_string.push_str("; Client versions: ");
+    let versions: Vec<String> = info.client_versions.iter().map(|v| tls_version_name(*v)).collect();
+    tls_string.push_str(versions.join(", ").as_str());
+
+    tls_string.push_str("; Negotiated version: ");
+    match info.negotiated_version {
+        Some(version) => tls_string.push_str(tls_version_name(version).as_str()),
+        None => tls_string.push_str("None")
+    }
+
+    tls_string.push_str("; Cipher suite: ");
+    match info.cipher_suite {
+        Some(suite) => tls_string.push_str(cipher_suite_name(suite).as_str()),
+        None => tls_string.push_str("None")
+    }
+
+    tls_string.push_str("; Server ALPN: ");
+    tls_string.push_str(info.server_alpn.as_deref().unwrap_or("None"));
+
+    tls_string.push_str("; JA3: ");
+    tls_string.push_str(info.ja3.as_deref().unwrap_or("None"));
+
+    tls_string.push_str("; JA4: ");
+    tls_string.push_str(info.ja4.as_deref().unwrap_or("None"));
+
+    tls_string.push_str("; JA3S: ");
+    tls_string.push_str(info.ja3s.as_deref().unwrap_or("None"));
+    if info.incomplete_handshake {
+        tls_string.push_str("; Incomplete handshake");
+    }
+    tls_string.push('\n');
+
+    tls_string
+}
+
+/// Function that joins the values of a list with '-', skipping the GREASE values, as required by JA3.
+fn ja3_list<T: Copy + Into<u16>>(values: &[T]) -> String {
+    let list: Vec<String> = values.iter()
+        .map(|v| (*v).into())
+        .filter(|v| !is_grease(*v))
+        .map(|v| v.to_string())
+        .collect();
+    list.join("-")
+}
+
+/// Function that builds the JA3 string of a ClientHello: "version,ciphers,extensions,curves,point formats".
+pub fn ja3_string(hello: &TlsClientHello) -> String {
+    format!("{},{},{},{},{}",
+            hello.legacy_version,
+            ja3_list(&hello.cipher_suites),
+            ja3_list(&hello.extensions),
+            ja3_list(&hello.supported_groups),
+            ja3_list(&hello.ec_point_formats))
+}
+
+/// Function that builds the JA3S string of a ServerHello: "version,cipher,extensions".
+pub fn ja3s_string(hello: &TlsServerHello) -> String {
+    format!("{},{},{}", hello.legacy_version, hello.cipher_suite, ja3_list(&hello.extensions))
+}
+
+/// Function that returns the first 12 hexadecimal characters of the SHA256 of a JA4 list, or zeros for an empty list.
+fn ja4_hash(list: &str) -> String {
+    if list.is_empty() {
+        return "000000000000".to_string();
+    }
+    let digest = Sha256::digest(list.as_bytes());
+    hex::encode(digest)[..12].to_string()
+}
+
+/// Function that computes the JA4 fingerprint of a ClientHello. `transport` is 't' for TCP and 'q' for QUIC.
+pub fn ja4(hello: &TlsClientHello, transport: char) -> String {
+    let version = hello.supported_versions.iter().copied().max().unwrap_or(hello.legacy_version);
+    let version = match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00"
+    };
+    let sni = if hello.sni.is_some() { 'd' } else { 'i' };
+    let mut ciphers: Vec<u16> = hello.cipher_suites.iter().copied().filter(|c| !is_grease(*c)).collect();
+    let all_extensions: Vec<u16> = hello.extensions.iter().copied().filter(|e| !is_grease(*e)).collect();
+    let alpn = match hello.alpn.first() {
+        Some(protocol) if !protocol.is_empty() => {
+            let first = protocol.chars().next().unwrap_or('0');
+            let last = protocol.chars().last().unwrap_or('0');
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                format!("{}{}", first, last)
+            } else {
+                let bytes = protocol.as_bytes();
+                let first = format!("{:02x}", bytes[0]);
+                let last = format!("{:02x}", bytes[bytes.len() - 1]);
+                format!("{}{}", &first[..1], &last[1..])
+            }
+        },
+        _ => "00".to_string()
+    };
+    let ja4_a = format!("{}{}{}{:02}{:02}{}", transport, version, sni, ciphers.len().min(99), all_extensions.len().min(99), alpn);
+
+    ciphers.sort_unstable();
+    let ciphers: Vec<String> = ciphers.iter().map(|c| format!("{:04x}", c)).collect();
+    let ja4_b = ja4_hash(&ciphers.join(","));
+
+    let mut extensions: Vec<u16> = all_extensions.into_iter().filter(|e| *e != EXT_SERVER_NAME && *e != EXT_ALPN).collect();
+    extensions.sort_unstable();
+    let extensions: Vec<String> = extensions.iter().map(|e| format!("{:04x}", e)).collect();
+    let mut ja4_c = extensions.join(",");
+    if !ja4_c.is_empty() && !hello.signature_algorithms.is_empty() {
+        let algorithms: Vec<String> = hello.signature_algorithms.iter().map(|a| format!("{:04x}", a)).collect();
+        ja4_c.push('_');
+        ja4_c.push_str(&algorithms.join(","));
+    }
+    let ja4_c = ja4_hash(&ja4_c);
+
+    format!("{}_{}_{}", ja4_a, ja4_b, ja4_c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Appends an extension of type `ext_type` with content `data` to `extensions`.
+    fn push_extension(extensions: &mut Vec<u8>, ext_type: u16, data: &[u8]) {
+        extensions.extend(ext_type.to_be_bytes());
+        extensions.extend((data.len() as u16).to_be_bytes());
+        extensions.extend(data);
+    }
+
+    /// Returns a ClientHello message for `sni`, with a key share of `key_share_len` bytes.
+    fn client_hello_message(sni: &str, key_share_len: usize) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        let mut server_name = ((sni.len() + 3) as u16).to_be_bytes().to_vec();
+        server_name.push(0);
+        server_name.extend((sni.len() as u16).to_be_bytes());
+        server_name.extend(sni.as_bytes());
+        push_extension(&mut extensions, EXT_SERVER_NAME, &server_name);
+        push_extension(&mut extensions, EXT_SUPPORTED_GROUPS, &[0x00, 0x02, 0x00, 0x1d]);
+        push_extension(&mut extensions, 51, &vec![0x42; key_share_len]);
+        let mut body = vec![0x03, 0x03];
+        body.extend([0x11; 32]);
+        body.push(0);
+        body.extend([0x00, 0x04, 0x13, 0x01, 0x13, 0x02]);
+        body.extend([0x01, 0x00]);
+        body.extend((extensions.len() as u16).to_be_bytes());
+        body.extend(extensions);
+        let mut message = vec![CLIENT_HELLO];
+        message.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        message.extend(body);
+        message
+    }
+
+    /// Splits a handshake message into TLS records of at most `record_len` bytes of fragment.
+    fn records(message: &[u8], record_len: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for fragment in message.chunks(record_len) {
+            data.extend([HANDSHAKE_RECORD, 0x03, 0x01]);
+            data.extend((fragment.len() as u16).to_be_bytes());
+            data.extend(fragment);
+        }
+        data
+    }
+
+    fn key() -> StreamKey {
+        StreamKey{source: "10.0.0.1 40000".to_string(), destination: "10.0.0.2 443".to_string()}
+    }
+
+    fn segment(sequence: u32) -> TcpSegment {
+        TcpSegment{sequence, syn: false, fin: false, rst: false}
+    }
+
+    fn ja3_of(status: TlsReassembly) -> String {
+        match status {
+            TlsReassembly::Complete(TlsHandshake::ClientHello(hello)) => ja3_string(&hello),
+            other => panic!("unexpected reassembly result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn ja3_known_answer() {
+        // Example of the JA3 reference implementation, with GREASE values that have to be ignored
+        let hello = TlsClientHello{
+            legacy_version: 769,
+            cipher_suites: vec![0x0a0a, 47, 53, 5, 10, 49161, 49162, 49171, 49172, 50, 56, 19, 4],
+            extensions: vec![0, 10, 0x1a1a, 11],
+            supported_groups: vec![23, 24, 25],
+            ec_point_formats: vec![0],
+            ..Default::default()
+        };
+        let ja3 = ja3_string(&hello);
+        assert_eq!(ja3, "769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0");
+        assert_eq!(format!("{:x}", md5::compute(ja3.as_bytes())), "ada70206e40642a3e4461f35503241d5");
+    }
+
+    #[test]
+    fn ja4_known_answer() {
+        // Example of the JA4 specification: 15 cipher suites and 16 extensions, SNI and ALPN "h2"
+        let hello = TlsClientHello{
+            legacy_version: 0x0303,
+            cipher_suites: vec![0x2a2a, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc013, 0xc014, 0x009c, 0x009d, 0x002f, 0x0035],
+            extensions: vec![0x3a3a, 0x0000, 0x0017, 0xff01, 0x000a, 0x000b, 0x0023, 0x0010, 0x0005, 0x000d, 0x0012, 0x0033, 0x002d, 0x002b, 0x001b, 0x0015, 0x4469],
+            sni: Some("example.com".to_string()),
+            alpn: vec!["h2".to_string(), "http/1.1".to_string()],
+            supported_versions: vec![0x0304, 0x0303],
+            signature_algorithms: vec![0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601],
+            ..Default::default()
+        };
+        assert_eq!(ja4(&hello, 't'), "t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert!(ja4(&hello, 'q').starts_with("q13d1516h2_"));
+    }
+
+    #[test]
+    fn parses_hello_in_one_segment() {
+        let data = records(&client_hello_message("example.com", 32), 16384);
+        assert_eq!(ja3_of(parse_tls_handshake(&data)), "771,4865-4866,0-10-51,29,");
+    }
+
+    #[test]
+    fn parses_hello_spanning_records() {
+        let data = records(&client_hello_message("example.com", 32), 40);
+        assert_eq!(ja3_of(parse_tls_handshake(&data)), "771,4865-4866,0-10-51,29,");
+    }
+
+    #[test]
+    fn reassembles_hello_spanning_segments() {
+        // A post-quantum key share makes the ClientHello longer than a segment
+        let data = records(&client_hello_message("example.com", 1216), 16384);
+        let mut streams = StreamTable::new(MAX_TLS_HANDSHAKE_BUFFER);
+        assert!(matches!(reassemble_tls_handshake(&mut streams, key(), segment(1000), &data[..600], 0), TlsReassembly::Incomplete));
+        assert!(matches!(reassemble_tls_handshake(&mut streams, key(), segment(1600), &data[600..1200], 0), TlsReassembly::Incomplete));
+        let status = reassemble_tls_handshake(&mut streams, key(), segment(2200), &data[1200..], 0);
+        assert_eq!(ja3_of(status), "771,4865-4866,0-10-51,29,");
+        assert!(!streams.contains(&key()));
+    }
+
+    #[test]
+    fn lost_segment_marks_hello_lost() {
+        let data = records(&client_hello_message("example.com", 1216), 16384);
+        let mut streams = StreamTable::new(MAX_TLS_HANDSHAKE_BUFFER);
+        assert!(matches!(reassemble_tls_handshake(&mut streams, key(), segment(1000), &data[..600], 0), TlsReassembly::Incomplete));
+        assert!(matches!(reassemble_tls_handshake(&mut streams, key(), segment(2200), &data[1200..], 0), TlsReassembly::Lost));
+        assert!(!streams.contains(&key()));
+    }
+
+    #[test]
+    fn other_payloads_are_not_handshakes() {
+        let mut streams = StreamTable::new(MAX_TLS_HANDSHAKE_BUFFER);
+        assert!(matches!(reassemble_tls_handshake(&mut streams, key(), segment(1), b"GET / HTTP/1.1\r\n", 0), TlsReassembly::NotHandshake));
+        // Encrypted handshake record, e.g. the Finished message of TLS 1.2
+        let encrypted = [HANDSHAKE_RECORD, 0x03, 0x03, 0x00, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert!(matches!(reassemble_tls_handshake(&mut streams, key(), segment(1), &encrypted, 0), TlsReassembly::NotHandshake));
+        assert!(!streams.contains(&key()));
+    }
+}
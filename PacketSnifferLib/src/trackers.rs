@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use crate::report::{AddressPortPair, OverflowBucket, Report};
+use crate::dns::{DnsTracker, PassiveDnsCache, MAX_TCP_DNS_BUFFER};
+use crate::options::CaptureOptions;
+use crate::quic::QuicTracker;
+use crate::dhcp::LeaseTable;
+use crate::ndp::NeighborTable;
+use crate::icmp::PingTracker;
+use crate::multicast::MulticastTable;
+use crate::vpn::SaTable;
+use crate::discovery::DiscoveryTable;
+use crate::wifi::WifiTable;
+use crate::rollup::RollupTable;
+use crate::stream::StreamTable;
+use crate::tls::MAX_TLS_HANDSHAKE_BUFFER;
+
+#[derive(Debug)]
+///Struct grouping the stateful tables that are built while capturing, besides the per-flow reports.
+pub struct Trackers {
+    /// Pairing of DNS queries and responses
+    pub dns: DnsTracker,
+    /// Reassembly of the DNS messages carried over TCP
+    pub dns_streams: StreamTable,
+    /// Hostnames learned from the DNS answers, kept for the whole capture
+    pub passive_dns: PassiveDnsCache,
+    /// Reassembly of the TLS hello messages that span more than one segment
+    pub tls_streams: StreamTable,
+    /// Reassembly of the ClientHello of QUIC connections
+    pub quic: QuicTracker,
+    /// Addresses leased through DHCP to each device
+    pub dhcp: LeaseTable,
+    /// IPv6 neighbors and routers learned from Neighbor Discovery
+    pub neighbors: NeighborTable,
+    /// Pairing of ICMP echo requests and replies
+    pub ping: PingTracker,
+    /// Members and traffic of the multicast groups
+    pub multicast: MulticastTable,
+    /// Traffic of the IPsec security associations and of the WireGuard sessions
+    pub vpn: SaTable,
+    /// Neighbors that advertised themselves through LLDP or CDP
+    pub discovery: DiscoveryTable,
+    /// Traffic of the wireless stations and networks, from the 802.11 frames
+    pub wifi: WifiTable,
+    /// Flows since the start of the capture, written by the cumulative reports
+    pub totals: HashMap<AddressPortPair, Report>,
+    /// Traffic of the flows evicted from the report map in the current interval
+    pub overflow: OverflowBucket,
+    /// Traffic of the flows evicted since the start of the capture, from the report map or from the totals
+    pub overflow_totals: OverflowBucket,
+    /// Traffic of the current interval per host and per subnet
+    pub rollups: RollupTable,
+    /// Timestamp (in microseconds) of the newest captured packet, used as the clock of the timeouts
+    pub now_us: u64,
+}
+
+impl Trackers {
+    /// Create a new Trackers struct, configured with the given options.
+    pub fn new(options: &CaptureOptions) -> Trackers {
+        Trackers{dns: DnsTracker::new(options.dns_timeout_ms), dns_streams: StreamTable::new(MAX_TCP_DNS_BUFFER), passive_dns: PassiveDnsCache::new(), tls_streams: StreamTable::new(MAX_TLS_HANDSHAKE_BUFFER), quic: QuicTracker::new(), dhcp: LeaseTable::new(), neighbors: NeighborTable::new(), ping: PingTracker::new(options.ping_timeout_ms), multicast: MulticastTable::new(), vpn: SaTable::new(), discovery: DiscoveryTable::new(), wifi: WifiTable::new(), totals: HashMap::new(), overflow: OverflowBucket::default(), overflow_totals: OverflowBucket::default(), rollups: RollupTable::new(), now_us: 0}
+    }
+
+    /// Advances the clock of the timeouts to the timestamp `ts_us` (in microseconds) of a captured packet.
+    pub fn advance_clock(&mut self, ts_us: u64) {
+        if ts_us > self.now_us {
+            self.now_us = ts_us;
+        }
+    }
+}
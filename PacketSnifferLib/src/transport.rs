@@ -0,0 +1,254 @@
+use etherparse::TransportSlice;
+use crate::report::TransportInfo;
+
+/// IP protocol number of DCCP.
+pub const DCCP_PROTOCOL: u8 = 33;
+/// IP protocol number of SCTP.
+pub const SCTP_PROTOCOL: u8 = 132;
+/// Maximum number of chunks read from a single SCTP packet.
+const MAX_SCTP_CHUNKS: usize = 16;
+
+#[derive(Debug, Clone)]
+///Struct containing the common header and the chunk types of an SCTP packet.
+pub struct SctpHeader {
+    /// Source port
+    pub source_port: u16,
+    /// Destination port
+    pub destination_port: u16,
+    /// Verification tag, identifying the association in the direction of the packet
+    pub verification_tag: u32,
+    /// Types of the chunks, e.g. "DATA"
+    pub chunk_types: Vec<String>,
+    /// Payload protocols of the DATA chunks, e.g. "Diameter"
+    pub payload_protocols: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the generic header of a DCCP packet.
+pub struct DccpHeader {
+    /// Source port
+    pub source_port: u16,
+    /// Destination port
+    pub destination_port: u16,
+    /// Type of the packet, e.g. "Request"
+    pub packet_type: String,
+}
+
+/// Function that returns the name of an SCTP chunk type.
+fn sctp_chunk_type(t: u8) -> String {
+    match t {
+        0 => "DATA".to_string(),
+        1 => "INIT".to_string(),
+        2 => "INIT ACK".to_string(),
+        3 => "SACK".to_string(),
+        4 => "HEARTBEAT".to_string(),
+        5 => "HEARTBEAT ACK".to_string(),
+        6 => "ABORT".to_string(),
+        7 => "SHUTDOWN".to_string(),
+        8 => "SHUTDOWN ACK".to_string(),
+        9 => "ERROR".to_string(),
+        10 => "COOKIE ECHO".to_string(),
+        11 => "COOKIE ACK".to_string(),
+        14 => "SHUTDOWN COMPLETE".to_string(),
+        15 => "AUTH".to_string(),
+        64 => "I-DATA".to_string(),
+        128 => "ASCONF ACK".to_string(),
+        130 => "RE-CONFIG".to_string(),
+        192 => "FORWARD TSN".to_string(),
+        193 => "ASCONF".to_string(),
+        _ => format!("Unknown ({})", t)
+    }
+}
+
+/// Function that returns the name of the payload protocol identifier of an SCTP DATA chunk.
+fn sctp_payload_protocol(ppid: u32) -> String {
+    match ppid {
+        1 => "IUA".to_string(),
+        2 => "M2UA".to_string(),
+        3 => "M3UA".to_string(),
+        4 => "SUA".to_string(),
+        5 => "M2PA".to_string(),
+        18 => "S1AP".to_string(),
+        19 => "RUA".to_string(),
+        20 => "HNBAP".to_string(),
+        27 => "X2AP".to_string(),
+        46 => "Diameter".to_string(),
+        47 => "Diameter over DTLS".to_string(),
+        60 => "NGAP".to_string(),
+        61 => "XnAP".to_string(),
+        62 => "F1AP".to_string(),
+        _ => format!("PPID {}", ppid)
+    }
+}
+
+/// Function that parses the common header of an SCTP packet and the types of its chunks.
+pub fn parse_sctp(payload: &[u8]) -> Option<SctpHeader> {
+    if payload.len() < 12 {
+        return None;
+    }
+    let mut header = SctpHeader{
+        source_port: u16::from_be_bytes([payload[0], payload[1]]),
+        destination_port: u16::from_be_bytes([payload[2], payload[3]]),
+        verification_tag: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+        chunk_types: Vec::new(),
+        payload_protocols: Vec::new(),
+    };
+    let mut chunks = &payload[12..];
+    while chunks.len() >= 4 && header.chunk_types.len() < MAX_SCTP_CHUNKS {
+        let length = u16::from_be_bytes([chunks[2], chunks[3]]) as usize;
+        if length < 4 {
+            break;
+        }
+        let chunk_type = sctp_chunk_type(chunks[0]);
+        if chunks[0] == 0 && chunks.len() >= 16 {
+            let ppid = sctp_payload_protocol(u32::from_be_bytes([chunks[12], chunks[13], chunks[14], chunks[15]]));
+            if !header.payload_protocols.contains(&ppid) {
+                header.payload_protocols.push(ppid);
+            }
+        }
+        if !header.chunk_types.contains(&chunk_type) {
+            header.chunk_types.push(chunk_type);
+        }
+        // Chunks are padded to a multiple of 4 bytes
+        let padded = (length + 3) & !3;
+        chunks = match chunks.get(padded..) {
+            Some(rest) => rest,
+            None => break
+        };
+    }
+    Some(header)
+}
+
+/// Function that parses the generic header of a DCCP packet.
+pub fn parse_dccp(payload: &[u8]) -> Option<DccpHeader> {
+    if payload.len() < 12 {
+        return None;
+    }
+    let packet_type = match (payload[8] >> 1) & 0x0f {
+        0 => "Request",
+        1 => "Response",
+        2 => "Data",
+        3 => "Ack",
+        4 => "DataAck",
+        5 => "CloseReq",
+        6 => "Close",
+        7 => "Reset",
+        8 => "Sync",
+        9 => "SyncAck",
+        _ => "Unknown"
+    };
+    Some(DccpHeader{
+        source_port: u16::from_be_bytes([payload[0], payload[1]]),
+        destination_port: u16::from_be_bytes([payload[2], payload[3]]),
+        packet_type: packet_type.to_string(),
+    })
+}
+
+/// Function that performs the parsing of the transport protocols that etherparse reports as unknown, currently SCTP and DCCP,
+/// reading their header from `payload`. It returns the transport layer information, with the ports, together with
+/// a summary of the header.
+pub fn parse_other_transport(transport_value: &Option<TransportSlice>, payload: &[u8]) -> Option<(TransportInfo, String)> {
+    match transport_value {
+        Some(TransportSlice::Unknown(SCTP_PROTOCOL)) => {
+            let header = parse_sctp(payload)?;
+            let mut summary = format!("SCTP verification tag: 0x{:08x}; Chunks: {}", header.verification_tag, header.chunk_types.join(", "));
+            if !header.payload_protocols.is_empty() {
+                summary.push_str("; Payload protocols: ");
+                summary.push_str(header.payload_protocols.join(", ").as_str());
+            }
+            Some((TransportInfo{protocol: "SCTP".to_string(), source_port: Some(header.source_port.to_string()), destination_port: Some(header.destination_port.to_string()), icmp_type: None}, summary))
+        },
+        Some(TransportSlice::Unknown(DCCP_PROTOCOL)) => {
+            let header = parse_dccp(payload)?;
+            let summary = format!("DCCP {}", header.packet_type);
+            Some((TransportInfo{protocol: "DCCP".to_string(), source_port: Some(header.source_port.to_string()), destination_port: Some(header.destination_port.to_string()), icmp_type: None}, summary))
+        },
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an SCTP packet from port 2905 to port 3868 with the verification tag 0x01020304 and the given chunks.
+    fn sctp(chunks: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x0b, 0x59, 0x0f, 0x1c, 1, 2, 3, 4, 0, 0, 0, 0];
+        packet.extend_from_slice(chunks);
+        packet
+    }
+
+    /// Builds a DATA chunk carrying the given payload protocol identifier and `data`, padded to 4 bytes.
+    fn data_chunk(ppid: u32, data: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![0, 3];
+        chunk.extend_from_slice(&(16 + data.len() as u16).to_be_bytes());
+        chunk.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+        chunk.extend_from_slice(&ppid.to_be_bytes());
+        chunk.extend_from_slice(data);
+        chunk.resize((chunk.len() + 3) & !3, 0);
+        chunk
+    }
+
+    #[test]
+    fn parses_the_sctp_common_header() {
+        let header = parse_sctp(&sctp(&[1, 0, 0, 4])).unwrap();
+        assert_eq!((header.source_port, header.destination_port), (2905, 3868));
+        assert_eq!(header.verification_tag, 0x01020304);
+        assert_eq!(header.chunk_types, vec!["INIT"]);
+        assert!(parse_sctp(&[0; 11]).is_none());
+    }
+
+    #[test]
+    fn skips_the_padding_of_sctp_chunks() {
+        // A DATA chunk of 17 bytes is followed by 3 bytes of padding before the SACK
+        let mut chunks = data_chunk(46, &[0xaa]);
+        chunks.extend_from_slice(&[3, 0, 0, 4]);
+        let header = parse_sctp(&sctp(&chunks)).unwrap();
+        assert_eq!(header.chunk_types, vec!["DATA", "SACK"]);
+    }
+
+    #[test]
+    fn reads_the_payload_protocols_of_data_chunks() {
+        let mut chunks = data_chunk(46, &[0xaa; 4]);
+        chunks.extend(data_chunk(60, &[0xbb; 2]));
+        chunks.extend(data_chunk(46, &[0xcc; 4]));
+        chunks.extend(data_chunk(1000, &[]));
+        let header = parse_sctp(&sctp(&chunks)).unwrap();
+        assert_eq!(header.chunk_types, vec!["DATA"]);
+        assert_eq!(header.payload_protocols, vec!["Diameter", "NGAP", "PPID 1000"]);
+        let (_, summary) = parse_other_transport(&Some(TransportSlice::Unknown(SCTP_PROTOCOL)), &sctp(&chunks)).unwrap();
+        assert_eq!(summary, "SCTP verification tag: 0x01020304; Chunks: DATA; Payload protocols: Diameter, NGAP, PPID 1000");
+    }
+
+    #[test]
+    fn reads_at_most_max_sctp_chunks() {
+        let chunks: Vec<u8> = (20..40).flat_map(|t| [t, 0, 0, 4]).collect();
+        let header = parse_sctp(&sctp(&chunks)).unwrap();
+        assert_eq!(header.chunk_types.len(), MAX_SCTP_CHUNKS);
+        assert_eq!(header.chunk_types[0], "Unknown (20)");
+    }
+
+    #[test]
+    fn stops_at_an_sctp_chunk_shorter_than_its_header() {
+        let header = parse_sctp(&sctp(&[1, 0, 0, 4, 3, 0, 0, 3, 4, 0, 0, 4])).unwrap();
+        assert_eq!(header.chunk_types, vec!["INIT"]);
+        // A length past the end of the packet keeps the chunk but stops the reading
+        let header = parse_sctp(&sctp(&[6, 0, 0, 40, 4, 0, 0, 4])).unwrap();
+        assert_eq!(header.chunk_types, vec!["ABORT"]);
+    }
+
+    #[test]
+    fn parses_the_dccp_packet_types() {
+        let types = ["Request", "Response", "Data", "Ack", "DataAck", "CloseReq", "Close", "Reset", "Sync", "SyncAck", "Unknown"];
+        for (t, name) in types.iter().enumerate() {
+            // The type sits in bits 1 to 4 of the ninth byte, after the reserved bits and before the X bit
+            let packet = [0x13, 0x88, 0x00, 0x50, 4, 0, 0, 0, 0xe0 | ((t as u8) << 1) | 1, 0, 0, 0];
+            let header = parse_dccp(&packet).unwrap();
+            assert_eq!(header.packet_type, *name);
+            assert_eq!((header.source_port, header.destination_port), (5000, 80));
+        }
+        assert!(parse_dccp(&[0; 11]).is_none());
+        let (info, summary) = parse_other_transport(&Some(TransportSlice::Unknown(DCCP_PROTOCOL)), &[0x13, 0x88, 0, 0x50, 4, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!((info.protocol.as_str(), summary.as_str()), ("DCCP", "DCCP Request"));
+    }
+}
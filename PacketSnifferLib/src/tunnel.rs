@@ -0,0 +1,358 @@
+use etherparse::{InternetSlice, LinkSlice, SlicedPacket, TransportSlice, VlanSlice};
+use crate::report::{mac_address_to_string, MacAddress};
+
+/// Standard VXLAN port.
+pub const VXLAN_PORT: u16 = 4789;
+/// Port used for VXLAN by the Linux kernel before the standard one was assigned.
+pub const VXLAN_LINUX_PORT: u16 = 8472;
+/// Standard Geneve port.
+pub const GENEVE_PORT: u16 = 6081;
+/// IP protocol number of IPv4 encapsulated in IP.
+const IPIP_PROTOCOL: u8 = 4;
+/// IP protocol number of IPv6 encapsulated in IP.
+const IPV6_IN_IP_PROTOCOL: u8 = 41;
+/// IP protocol number of GRE.
+const GRE_PROTOCOL: u8 = 47;
+/// EtherTypes of the MPLS unicast and multicast label stacks.
+const MPLS_ETHER_TYPES: [u16; 2] = [0x8847, 0x8848];
+/// EtherType (and GRE or Geneve protocol type) of a bridged Ethernet frame.
+const TRANSPARENT_ETHERNET: u16 = 0x6558;
+/// Maximum number of labels read from an MPLS stack.
+const MAX_MPLS_LABELS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///Struct containing the metadata of a tunnel that carried a flow.
+pub struct TunnelInfo {
+    /// Type of the tunnel, e.g. "VXLAN"
+    pub kind: String,
+    /// Outer endpoints of the tunnel, in lexicographic order: IP addresses, or MAC addresses for MPLS over Ethernet
+    pub endpoints: (String, String),
+    /// VXLAN or Geneve network identifier
+    pub vni: Option<u32>,
+    /// GRE key
+    pub key: Option<u32>,
+    /// MPLS labels, from the top of the stack
+    pub labels: Vec<u32>,
+}
+
+impl TunnelInfo {
+    /// Create a new TunnelInfo struct without identifiers. The order of the endpoints does not matter.
+    pub fn new(kind: String, first_endpoint: String, second_endpoint: String) -> TunnelInfo {
+        let endpoints = if first_endpoint <= second_endpoint { (first_endpoint, second_endpoint) } else { (second_endpoint, first_endpoint) };
+        TunnelInfo{kind, endpoints, vni: None, key: None, labels: Vec::new()}
+    }
+}
+
+#[derive(Debug)]
+///Enum representing a packet carried by a tunnel.
+pub enum InnerPacket<'a> {
+    /// Ethernet frame
+    Ethernet(&'a [u8]),
+    /// IPv4 or IPv6 packet
+    Ip(&'a [u8]),
+}
+
+impl<'a> InnerPacket<'a> {
+    /// Bytes of the inner packet.
+    pub fn data(&self) -> &'a [u8] {
+        match self {
+            InnerPacket::Ethernet(data) => data,
+            InnerPacket::Ip(data) => data
+        }
+    }
+}
+
+/// Function that reads a big endian 24-bit network identifier.
+fn read_vni(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32
+}
+
+/// Function that parses a VXLAN header, returning the VNI and the encapsulated Ethernet frame.
+pub fn parse_vxlan(payload: &[u8]) -> Option<(u32, InnerPacket<'_>)> {
+    // The I flag signals a valid VNI
+    if payload.len() < 8 + 14 || payload[0] & 0x08 == 0 {
+        return None;
+    }
+    Some((read_vni(&payload[4..7]), InnerPacket::Ethernet(&payload[8..])))
+}
+
+/// Function that parses a Geneve header, skipping its options, and returns the VNI and the encapsulated packet.
+pub fn parse_geneve(payload: &[u8]) -> Option<(u32, InnerPacket<'_>)> {
+    if payload.len() < 8 || payload[0] >> 6 != 0 {
+        return None;
+    }
+    let start = 8 + (payload[0] & 0x3f) as usize * 4;
+    let inner = payload.get(start..)?;
+    let inner = match u16::from_be_bytes([payload[2], payload[3]]) {
+        TRANSPARENT_ETHERNET => InnerPacket::Ethernet(inner),
+        0x0800 | 0x86dd => InnerPacket::Ip(inner),
+        _ => return None
+    };
+    Some((read_vni(&payload[4..7]), inner))
+}
+
+/// Function that parses an MPLS label stack, returning the labels and the packet after the bottom of the stack.
+/// The packet is an IP packet, or an Ethernet frame preceded by a pseudowire control word.
+pub fn parse_mpls(payload: &[u8]) -> Option<(Vec<u32>, InnerPacket<'_>)> {
+    let mut labels = Vec::new();
+    let mut offset = 0;
+    loop {
+        let entry = u32::from_be_bytes(payload.get(offset..offset + 4)?.try_into().ok()?);
+        labels.push(entry >> 12);
+        offset += 4;
+        if entry & 0x100 != 0 {
+            break;
+        }
+        if labels.len() == MAX_MPLS_LABELS {
+            return None;
+        }
+    }
+    let inner = match payload.get(offset)? >> 4 {
+        4 | 6 => InnerPacket::Ip(&payload[offset..]),
+        0 => InnerPacket::Ethernet(payload.get(offset + 4..)?),
+        _ => return None
+    };
+    Some((labels, inner))
+}
+
+/// Function that parses a GRE header (version 0), returning the key, the MPLS labels if the payload is an MPLS
+/// stack, and the encapsulated packet.
+pub fn parse_gre(payload: &[u8]) -> Option<(Option<u32>, Vec<u32>, InnerPacket<'_>)> {
+    if payload.len() < 4 || payload[1] & 0x07 != 0 {
+        return None;
+    }
+    let flags = payload[0];
+    let mut offset = 4;
+    // Checksum and routing present
+    if flags & 0xc0 != 0 {
+        offset += 4;
+    }
+    let key = if flags & 0x20 != 0 {
+        let key = u32::from_be_bytes(payload.get(offset..offset + 4)?.try_into().ok()?);
+        offset += 4;
+        Some(key)
+    } else {
+        None
+    };
+    // Sequence number present
+    if flags & 0x10 != 0 {
+        offset += 4;
+    }
+    let inner = payload.get(offset..)?;
+    match u16::from_be_bytes([payload[2], payload[3]]) {
+        TRANSPARENT_ETHERNET => Some((key, Vec::new(), InnerPacket::Ethernet(inner))),
+        0x0800 | 0x86dd => Some((key, Vec::new(), InnerPacket::Ip(inner))),
+        protocol if MPLS_ETHER_TYPES.contains(&protocol) => {
+            let (labels, inner) = parse_mpls(inner)?;
+            Some((key, labels, inner))
+        },
+        _ => None
+    }
+}
+
+/// Function that returns the EtherType of the payload of an Ethernet frame, after the VLAN tags.
+fn payload_ether_type(packet: &SlicedPacket) -> Option<u16> {
+    match &packet.vlan {
+        Some(VlanSlice::SingleVlan(header)) => Some(header.ether_type()),
+        Some(VlanSlice::DoubleVlan(header)) => Some(header.inner().ether_type()),
+        None => packet.link.as_ref().map(|LinkSlice::Ethernet2(header)| header.ether_type())
+    }
+}
+
+/// Function that recognizes a tunnel in a sliced packet: GRE, IP-in-IP and 6in4 by IP protocol number, VXLAN and
+/// Geneve by UDP destination port, MPLS by EtherType. It returns the metadata of the tunnel and the encapsulated packet.
+pub fn decapsulate<'a>(packet: &SlicedPacket<'a>) -> Option<(TunnelInfo, InnerPacket<'a>)> {
+    let payload = packet.payload;
+    let (source, destination, is_ipv4) = match &packet.ip {
+        Some(InternetSlice::Ipv4(header, _)) => (header.source_addr().to_string(), header.destination_addr().to_string(), true),
+        Some(InternetSlice::Ipv6(header, _)) => (header.source_addr().to_string(), header.destination_addr().to_string(), false),
+        None => {
+            if !MPLS_ETHER_TYPES.contains(&payload_ether_type(packet)?) {
+                return None;
+            }
+            let (source, destination) = match &packet.link {
+                Some(LinkSlice::Ethernet2(header)) => (mac_address_to_string(MacAddress::new(header.source())), mac_address_to_string(MacAddress::new(header.destination()))),
+                None => return None
+            };
+            let (labels, inner) = parse_mpls(payload)?;
+            let mut tunnel = TunnelInfo::new("MPLS".to_string(), source, destination);
+            tunnel.labels = labels;
+            return Some((tunnel, inner));
+        }
+    };
+    match &packet.transport {
+        Some(TransportSlice::Udp(header)) => {
+            let port = header.destination_port();
+            let (kind, (vni, inner)) = if port == VXLAN_PORT || port == VXLAN_LINUX_PORT {
+                ("VXLAN", parse_vxlan(payload)?)
+            } else if port == GENEVE_PORT {
+                ("Geneve", parse_geneve(payload)?)
+            } else {
+                return None;
+            };
+            let mut tunnel = TunnelInfo::new(kind.to_string(), source, destination);
+            tunnel.vni = Some(vni);
+            Some((tunnel, inner))
+        },
+        Some(TransportSlice::Unknown(GRE_PROTOCOL)) => {
+            let (key, labels, inner) = parse_gre(payload)?;
+            let mut tunnel = TunnelInfo::new("GRE".to_string(), source, destination);
+            tunnel.key = key;
+            tunnel.labels = labels;
+            Some((tunnel, inner))
+        },
+        Some(TransportSlice::Unknown(IPIP_PROTOCOL)) => Some((TunnelInfo::new("IP-in-IP".to_string(), source, destination), InnerPacket::Ip(payload))),
+        Some(TransportSlice::Unknown(IPV6_IN_IP_PROTOCOL)) => {
+            let kind = if is_ipv4 { "6in4" } else { "IPv6-in-IPv6" };
+            Some((TunnelInfo::new(kind.to_string(), source, destination), InnerPacket::Ip(payload)))
+        },
+        _ => None
+    }
+}
+
+/// The function takes as parameter a `TunnelInfo` struct and generate a String containing its formatted informations.
+pub fn tunnel_info_to_string(info: &TunnelInfo) -> String {
+    let mut tunnel_string = info.kind.clone();
+    if let Some(vni) = info.vni {
+        tunnel_string.push_str(&format!("; VNI: {}", vni));
+    }
+    if let Some(key) = info.key {
+        tunnel_string.push_str(&format!("; Key: {}", key));
+    }
+    if !info.labels.is_empty() {
+        let labels: Vec<String> = info.labels.iter().map(|l| l.to_string()).collect();
+        tunnel_string.push_str("; Labels: ");
+        tunnel_string.push_str(labels.join("/").as_str());
+    }
+    tunnel_string.push_str(&format!("; Outer endpoints: {} - {}", info.endpoints.0, info.endpoints.1));
+    tunnel_string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Beginning of an IPv4 header, enough to be recognized as the packet after an MPLS stack.
+    const IPV4: [u8; 4] = [0x45, 0x00, 0x00, 0x14];
+
+    /// Returns an MPLS label stack entry with TTL 64.
+    fn label(label: u32, bottom: bool) -> [u8; 4] {
+        (label << 12 | if bottom { 0x100 } else { 0 } | 0x40).to_be_bytes()
+    }
+
+    fn ethernet_frame() -> Vec<u8> {
+        let mut frame = vec![0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01, 0x08, 0x00];
+        frame.extend(IPV4);
+        frame
+    }
+
+    #[test]
+    fn gre_optional_fields_move_the_payload() {
+        let packet = [[0x00, 0x00, 0x08, 0x00], IPV4].concat();
+        let (key, labels, inner) = parse_gre(&packet).unwrap();
+        assert_eq!((key, labels.len(), inner.data()), (None, 0, &IPV4[..]));
+        // Checksum, key and sequence number
+        let packet = [[0xb0, 0x00, 0x08, 0x00], [0xff; 4], [0, 0, 0, 7], [0, 0, 0, 1], IPV4].concat();
+        let (key, _, inner) = parse_gre(&packet).unwrap();
+        assert_eq!(key, Some(7));
+        assert!(matches!(inner, InnerPacket::Ip(data) if data == IPV4));
+        // Key only, with a bridged Ethernet frame
+        let packet = [vec![0x20, 0x00, 0x65, 0x58, 0, 0, 1, 0], ethernet_frame()].concat();
+        let (key, _, inner) = parse_gre(&packet).unwrap();
+        assert_eq!(key, Some(256));
+        assert!(matches!(inner, InnerPacket::Ethernet(data) if data == ethernet_frame()));
+        // Routing present without checksum takes the same 4 bytes
+        let packet = [[0x40, 0x00, 0x86, 0xdd], [0; 4], IPV4].concat();
+        let (_, _, inner) = parse_gre(&packet).unwrap();
+        assert_eq!(inner.data(), IPV4);
+    }
+
+    #[test]
+    fn rejects_invalid_gre() {
+        // Version 1 is the enhanced GRE of PPTP
+        assert!(parse_gre(&[[0x00, 0x01, 0x08, 0x00], IPV4].concat()).is_none());
+        // Key flag set but the key is truncated
+        assert!(parse_gre(&[0x20, 0x00, 0x08, 0x00, 0, 0]).is_none());
+        assert!(parse_gre(&[[0x00, 0x00, 0x12, 0x34], IPV4].concat()).is_none());
+    }
+
+    #[test]
+    fn gre_carries_mpls() {
+        let packet = [[0x00, 0x00, 0x88, 0x47], label(200, false), label(100, true), IPV4].concat();
+        let (key, labels, inner) = parse_gre(&packet).unwrap();
+        assert_eq!((key, labels), (None, vec![200, 100]));
+        assert_eq!(inner.data(), IPV4);
+    }
+
+    #[test]
+    fn mpls_stack_ends_at_bottom_of_stack() {
+        let packet = [label(16, false), label(17, false), label(18, true), IPV4].concat();
+        let (labels, inner) = parse_mpls(&packet).unwrap();
+        assert_eq!(labels, vec![16, 17, 18]);
+        assert!(matches!(inner, InnerPacket::Ip(data) if data == IPV4));
+        // A pseudowire control word precedes the Ethernet frame
+        let packet = [label(16, true).to_vec(), vec![0; 4], ethernet_frame()].concat();
+        let (_, inner) = parse_mpls(&packet).unwrap();
+        assert!(matches!(inner, InnerPacket::Ethernet(data) if data == ethernet_frame()));
+        // Neither IP nor a control word
+        assert!(parse_mpls(&[label(16, true), [0x80, 0, 0, 0]].concat()).is_none());
+        // Truncated stack
+        assert!(parse_mpls(&label(16, false)).is_none());
+    }
+
+    #[test]
+    fn mpls_stack_is_capped() {
+        let mut packet: Vec<u8> = (0..MAX_MPLS_LABELS as u32 - 1).flat_map(|l| label(l + 16, false)).collect();
+        packet.extend(label(99, true));
+        packet.extend(IPV4);
+        let (labels, _) = parse_mpls(&packet).unwrap();
+        assert_eq!(labels.len(), MAX_MPLS_LABELS);
+        let mut packet: Vec<u8> = (0..MAX_MPLS_LABELS as u32).flat_map(|l| label(l + 16, false)).collect();
+        packet.extend(label(99, true));
+        packet.extend(IPV4);
+        assert!(parse_mpls(&packet).is_none());
+    }
+
+    #[test]
+    fn vxlan_needs_the_i_flag() {
+        let packet = [vec![0x08, 0, 0, 0, 0x00, 0x01, 0x02, 0x00], ethernet_frame()].concat();
+        let (vni, inner) = parse_vxlan(&packet).unwrap();
+        assert_eq!(vni, 0x0102);
+        assert!(matches!(inner, InnerPacket::Ethernet(data) if data == ethernet_frame()));
+        let mut packet = packet;
+        packet[0] = 0x00;
+        assert!(parse_vxlan(&packet).is_none());
+        assert!(parse_vxlan(&[0x08, 0, 0, 0, 0, 0, 1, 0]).is_none());
+    }
+
+    #[test]
+    fn geneve_skips_options() {
+        // 2 words of options
+        let packet = [vec![0x02, 0x00, 0x65, 0x58, 0x00, 0x00, 0x2a, 0x00], vec![0xee; 8], ethernet_frame()].concat();
+        let (vni, inner) = parse_geneve(&packet).unwrap();
+        assert_eq!(vni, 42);
+        assert!(matches!(inner, InnerPacket::Ethernet(data) if data == ethernet_frame()));
+        let packet = [vec![0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x01, 0x00], IPV4.to_vec()].concat();
+        let (vni, inner) = parse_geneve(&packet).unwrap();
+        assert_eq!(vni, 1);
+        assert!(matches!(inner, InnerPacket::Ip(data) if data == IPV4));
+    }
+
+    #[test]
+    fn rejects_invalid_geneve() {
+        // Unknown protocol type
+        assert!(parse_geneve(&[[0x00, 0x00, 0x12, 0x34, 0, 0, 1, 0], [0x45, 0, 0, 0x14, 0, 0, 0, 0]].concat()).is_none());
+        // Version 1
+        assert!(parse_geneve(&[0x40, 0x00, 0x65, 0x58, 0, 0, 1, 0]).is_none());
+        // Options longer than the packet
+        assert!(parse_geneve(&[0x3f, 0x00, 0x65, 0x58, 0, 0, 1, 0]).is_none());
+    }
+
+    #[test]
+    fn formats_tunnel_metadata() {
+        let mut tunnel = TunnelInfo::new("GRE".to_string(), "10.0.0.2".to_string(), "10.0.0.1".to_string());
+        tunnel.key = Some(7);
+        tunnel.labels = vec![200, 100];
+        assert_eq!(tunnel_info_to_string(&tunnel), "GRE; Key: 7; Labels: 200/100; Outer endpoints: 10.0.0.1 - 10.0.0.2");
+    }
+}
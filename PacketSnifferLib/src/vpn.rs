@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use etherparse::{InternetSlice, IpAuthenticationHeaderSlice, Ipv6ExtensionSlice, SlicedPacket, TransportSlice};
+
+/// IP protocol number of ESP.
+pub const ESP_PROTOCOL: u8 = 50;
+/// IP protocol number of AH.
+pub const AH_PROTOCOL: u8 = 51;
+/// Port used by IKE.
+pub const IKE_PORT: u16 = 500;
+/// Port used by IKE and by ESP when a NAT is detected.
+pub const IKE_NAT_T_PORT: u16 = 4500;
+/// Time in seconds after which a security association without traffic is removed.
+const SA_IDLE_TIMEOUT_S: u64 = 600;
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to a packet of IPsec (ESP, AH, IKE) or WireGuard.
+pub struct VpnMessage {
+    /// Protocol, e.g. "ESP" or "WireGuard"
+    pub protocol: String,
+    /// Identifier of the security association or session: SPI, IKE SPIs or WireGuard index
+    pub id: String,
+    /// Type of the message, e.g. "IKE_SA_INIT request"
+    pub message_type: String,
+    /// Sequence number or counter of the packet
+    pub sequence: Option<u64>,
+    /// True if the message starts a new key exchange between peers that already had one
+    pub rekey: bool,
+    /// Index chosen by the responder, for WireGuard handshake responses
+    pub responder_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the traffic of a security association (ESP, AH), of an IKE SA or of a WireGuard session.
+pub struct SecurityAssociation {
+    /// Protocol, e.g. "ESP"
+    pub protocol: String,
+    /// Identifier, e.g. the SPI
+    pub id: String,
+    /// Address of the sender of the first packet
+    pub source: String,
+    /// Address of the receiver of the first packet
+    pub destination: String,
+    /// Packets of the association
+    pub packets: u64,
+    /// Bytes of the association
+    pub bytes: u64,
+    /// Highest sequence number observed
+    pub last_sequence: Option<u64>,
+    /// Timestamp (in seconds) of the first packet
+    pub first_seen: u64,
+    /// Timestamp (in seconds) of the last packet
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Default)]
+///Struct that aggregates the IPsec and WireGuard traffic per security association and counts the rekey events of each pair of peers.
+pub struct SaTable {
+    /// Associations by protocol and identifier
+    associations: HashMap<(String, String), SecurityAssociation>,
+    /// Rekey events of each pair of peers, in lexicographic order
+    rekeys: HashMap<(String, String), u32>,
+    /// Pairs of peers that completed a WireGuard handshake, with the index of the initiator of the last handshake and
+    /// its timestamp (in seconds)
+    wireguard_peers: HashMap<(String, String), (String, u64)>,
+    /// WireGuard indexes announced by the handshakes, with the timestamp (in seconds) of their last packet
+    wireguard_indexes: HashMap<String, u64>,
+}
+
+/// Function that parses the SPI and the sequence number of an ESP packet.
+pub fn parse_esp(payload: &[u8]) -> Option<VpnMessage> {
+    if payload.len() < 8 {
+        return None;
+    }
+    let spi = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+    // SPIs from 1 to 255 are reserved
+    if spi < 256 {
+        return None;
+    }
+    let sequence = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+    Some(VpnMessage{protocol: "ESP".to_string(), id: format!("0x{:08x}", spi), message_type: "ESP".to_string(), sequence: Some(sequence as u64), rekey: false, responder_id: None})
+}
+
+/// Function that returns the name of an IKE exchange type.
+fn ike_exchange_type(t: u8) -> String {
+    match t {
+        2 => "Identity Protection".to_string(),
+        4 => "Aggressive".to_string(),
+        5 => "Informational".to_string(),
+        32 => "Quick Mode".to_string(),
+        34 => "IKE_SA_INIT".to_string(),
+        35 => "IKE_AUTH".to_string(),
+        36 => "CREATE_CHILD_SA".to_string(),
+        37 => "INFORMATIONAL".to_string(),
+        _ => format!("Exchange {}", t)
+    }
+}
+
+/// Function that parses the header of an IKEv1 or IKEv2 message.
+pub fn parse_ike(payload: &[u8]) -> Option<VpnMessage> {
+    if payload.len() < 28 {
+        return None;
+    }
+    let protocol = match payload[17] >> 4 {
+        1 => "IKEv1",
+        2 => "IKEv2",
+        _ => return None
+    };
+    let length = u32::from_be_bytes([payload[24], payload[25], payload[26], payload[27]]) as usize;
+    if length < 28 || length > payload.len() {
+        return None;
+    }
+    let exchange = ike_exchange_type(payload[18]);
+    let is_response = protocol == "IKEv2" && payload[19] & 0x20 != 0;
+    let message_type = format!("{} {}", exchange, if is_response { "response" } else { "request" });
+    let initiator_spi = u64::from_be_bytes(payload[0..8].try_into().ok()?);
+    let message_id = u32::from_be_bytes([payload[20], payload[21], payload[22], payload[23]]);
+    Some(VpnMessage{
+        protocol: protocol.to_string(),
+        id: format!("0x{:016x}", initiator_spi),
+        rekey: payload[18] == 36 && !is_response,
+        message_type,
+        sequence: Some(message_id as u64),
+        responder_id: None,
+    })
+}
+
+/// Function that parses a WireGuard message: handshake initiation, handshake response, cookie reply or transport data.
+pub fn parse_wireguard(payload: &[u8]) -> Option<VpnMessage> {
+    if payload.len() < 16 || payload[1..4] != [0, 0, 0] {
+        return None;
+    }
+    let index = |offset: usize| u32::from_le_bytes([payload[offset], payload[offset + 1], payload[offset + 2], payload[offset + 3]]);
+    let (message_type, id, sequence, responder_id) = match (payload[0], payload.len()) {
+        (1, 148) => ("Handshake Initiation", index(4), None, None),
+        (2, 92) => ("Handshake Response", index(8), None, Some(index(4))),
+        (3, 64) => ("Cookie Reply", index(4), None, None),
+        // Transport data is padded to 16 bytes and followed by a 16-byte authentication tag
+        (4, len) if len >= 32 && len & 0x0f == 0 => {
+            let counter = u64::from_le_bytes(payload[8..16].try_into().ok()?);
+            ("Transport Data", index(4), Some(counter), None)
+        },
+        _ => return None
+    };
+    Some(VpnMessage{
+        protocol: "WireGuard".to_string(),
+        id: format!("0x{:08x}", id),
+        message_type: message_type.to_string(),
+        sequence,
+        rekey: false,
+        responder_id: responder_id.map(|id| format!("0x{:08x}", id)),
+    })
+}
+
+/// Function that returns the message of an AH packet with index `spi` and sequence number `sequence`.
+fn ah_message(spi: u32, sequence: u32) -> VpnMessage {
+    VpnMessage{protocol: "AH".to_string(), id: format!("0x{:08x}", spi), message_type: "AH".to_string(), sequence: Some(sequence as u64), rekey: false, responder_id: None}
+}
+
+/// Function that returns the AH header of a packet, when the IPv4 or IPv6 slicing read it as an extension header.
+fn authentication_header<'a>(packet: &SlicedPacket<'a>) -> Option<IpAuthenticationHeaderSlice<'a>> {
+    match &packet.ip {
+        Some(InternetSlice::Ipv4(_, extensions)) => extensions.auth.clone(),
+        Some(InternetSlice::Ipv6(_, extensions)) => extensions.clone().into_iter().find_map(|extension| match extension {
+            Ipv6ExtensionSlice::Authentication(auth) => Some(auth),
+            _ => None
+        }),
+        None => None
+    }
+}
+
+/// Function that recognizes IPsec and WireGuard packets: ESP and AH by IP protocol number (AH also as IPv4 or IPv6
+/// extension header), IKE and ESP in UDP on ports 500 and 4500, WireGuard by the format of its messages on any UDP port.
+/// WireGuard transport data is recognized only for the indexes announced by a handshake in `sessions`, or on one of
+/// `wireguard_ports`, because its content cannot be told apart from random bytes.
+pub fn parse_vpn(packet: &SlicedPacket, sessions: &SaTable, wireguard_ports: &[u16]) -> Option<VpnMessage> {
+    if let Some(auth) = authentication_header(packet) {
+        return Some(ah_message(auth.spi(), auth.sequence_number()));
+    }
+    let payload = packet.payload;
+    match &packet.transport {
+        Some(TransportSlice::Unknown(ESP_PROTOCOL)) => parse_esp(payload),
+        Some(TransportSlice::Unknown(AH_PROTOCOL)) => {
+            if payload.len() < 12 {
+                return None;
+            }
+            let spi = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+            let sequence = u32::from_be_bytes([payload[8], payload[9], payload[10], payload[11]]);
+            Some(ah_message(spi, sequence))
+        },
+        Some(TransportSlice::Udp(header)) => {
+            let ports = [header.source_port(), header.destination_port()];
+            if ports.contains(&IKE_NAT_T_PORT) {
+                // IKE messages are preceded by the 4-byte non-ESP marker, a single 0xff byte is a NAT keepalive
+                if payload.len() >= 4 && payload[0..4] == [0, 0, 0, 0] {
+                    return parse_ike(&payload[4..]);
+                }
+                return parse_esp(payload).map(|mut message| {
+                    message.protocol = "ESP-in-UDP".to_string();
+                    message
+                });
+            }
+            if ports.contains(&IKE_PORT) {
+                return parse_ike(payload);
+            }
+            let message = parse_wireguard(payload)?;
+            if message.message_type == "Transport Data" && !sessions.is_wireguard_session(&message.id) && !ports.iter().any(|p| wireguard_ports.contains(p)) {
+                return None;
+            }
+            Some(message)
+        },
+        _ => None
+    }
+}
+
+/// The function takes as parameter a `VpnMessage` struct and generate a String containing its formatted informations.
+pub fn vpn_message_to_string(message: &VpnMessage) -> String {
+    let mut vpn_string = format!("{} {}", message.protocol, message.id);
+    if message.message_type != message.protocol {
+        vpn_string.push_str("; ");
+        vpn_string.push_str(&message.message_type);
+    }
+    vpn_string
+}
+
+/// Function that returns a pair of peers in lexicographic order.
+fn peers(first: &str, second: &str) -> (String, String) {
+    if first <= second { (first.to_string(), second.to_string()) } else { (second.to_string(), first.to_string()) }
+}
+
+impl SaTable {
+    /// Create a new empty SaTable struct.
+    pub fn new() -> SaTable {
+        SaTable{associations: HashMap::new(), rekeys: HashMap::new(), wireguard_peers: HashMap::new(), wireguard_indexes: HashMap::new()}
+    }
+
+    /// Registers a packet of `bytes` bytes sent from `source` to `destination` at timestamp `ts` (in seconds).
+    pub fn register(&mut self, message: &VpnMessage, source: &str, destination: &str, bytes: u32, ts: u64) {
+        let pair = peers(source, destination);
+        let mut rekey = message.rekey;
+        if message.protocol == "WireGuard" {
+            self.wireguard_indexes.insert(message.id.clone(), ts);
+            if let Some(responder_id) = &message.responder_id {
+                self.wireguard_indexes.insert(responder_id.clone(), ts);
+            }
+            // A new handshake completed between peers that already completed one replaces the session keys. The
+            // retransmitted initiations and the duplicated responses are not rekeys.
+            if message.message_type == "Handshake Response" {
+                rekey = matches!(self.wireguard_peers.insert(pair.clone(), (message.id.clone(), ts)), Some((id, _)) if id != message.id);
+            }
+        }
+        if rekey {
+            *self.rekeys.entry(pair).or_insert(0) += 1;
+        }
+        let sa = self.associations.entry((message.protocol.clone(), message.id.clone())).or_insert_with(|| SecurityAssociation{
+            protocol: message.protocol.clone(),
+            id: message.id.clone(),
+            source: source.to_string(),
+            destination: destination.to_string(),
+            packets: 0,
+            bytes: 0,
+            last_sequence: None,
+            first_seen: ts,
+            last_seen: ts,
+        });
+        sa.packets += 1;
+        sa.bytes += bytes as u64;
+        sa.last_seen = ts;
+        if let Some(sequence) = message.sequence {
+            sa.last_sequence = Some(sa.last_sequence.map_or(sequence, |s| s.max(sequence)));
+        }
+    }
+
+    /// Removes, at timestamp `now` (in seconds), the associations and the WireGuard sessions without traffic for more
+    /// than the idle timeout.
+    pub fn purge_expired(&mut self, now: u64) {
+        let deadline = now.saturating_sub(SA_IDLE_TIMEOUT_S);
+        self.associations.retain(|_, sa| sa.last_seen >= deadline);
+        self.wireguard_peers.retain(|_, (_, ts)| *ts >= deadline);
+        self.wireguard_indexes.retain(|_, ts| *ts >= deadline);
+    }
+
+    /// Checks if the WireGuard index `id` was announced by a handshake and is still in use.
+    pub fn is_wireguard_session(&self, id: &str) -> bool {
+        self.wireguard_indexes.contains_key(id)
+    }
+
+    /// Returns the security associations and sessions, sorted by peers, protocol and identifier.
+    pub fn associations(&self) -> Vec<SecurityAssociation> {
+        let mut associations: Vec<SecurityAssociation> = self.associations.values().cloned().collect();
+        associations.sort_by(|a, b| peers(&a.source, &a.destination).cmp(&peers(&b.source, &b.destination))
+            .then(a.protocol.cmp(&b.protocol))
+            .then(a.id.cmp(&b.id)));
+        associations
+    }
+
+    /// Returns the number of rekey events observed between two peers.
+    pub fn rekeys(&self, first: &str, second: &str) -> u32 {
+        self.rekeys.get(&peers(first, second)).cloned().unwrap_or(0)
+    }
+
+    /// Generates the VPN section of a report: for each pair of peers the rekey events, then each of their associations
+    /// with its traffic.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----VPN security associations-----\n");
+        let mut current_pair = None;
+        for sa in self.associations() {
+            let pair = peers(&sa.source, &sa.destination);
+            if current_pair.as_ref() != Some(&pair) {
+                s.push_str(&format!("Tunnel {} - {}; Rekeys: {}\n", pair.0, pair.1, self.rekeys(&pair.0, &pair.1)));
+                current_pair = Some(pair);
+            }
+            s.push_str(&format!("    {} {}: {} -> {}; Packets: {}; Bytes: {}; Last sequence: {}; First seen: {}; Last seen: {}\n",
+                                sa.protocol,
+                                sa.id,
+                                sa.source,
+                                sa.destination,
+                                sa.packets,
+                                sa.bytes,
+                                sa.last_sequence.map(|n| n.to_string()).unwrap_or_else(|| "None".to_string()),
+                                sa.first_seen,
+                                sa.last_seen));
+        }
+        s.push('\n');
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::{Ipv4ExtensionsSlice, Ipv4HeaderSlice, UdpHeaderSlice};
+
+    /// IPv4 header from 10.0.0.1 to 10.0.0.2 carrying AH.
+    const IPV4_AH_HEADER: [u8; 20] = [0x45, 0, 0, 72, 0, 0, 0x40, 0, 64, AH_PROTOCOL, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+
+    /// Builds an IKE header with the given version byte, exchange type, flags and length, without payloads.
+    fn ike(version: u8, exchange: u8, flags: u8, length: u32) -> Vec<u8> {
+        let mut message = vec![0x11; 8];
+        message.extend_from_slice(&[0; 8]);
+        message.extend_from_slice(&[33, version, exchange, flags, 0, 0, 0, 5]);
+        message.extend_from_slice(&length.to_be_bytes());
+        message
+    }
+
+    /// Builds a packet sliced as UDP from port 4500 to port 4500 with the given payload.
+    fn nat_t_packet<'a>(udp: &'a [u8], payload: &'a [u8]) -> SlicedPacket<'a> {
+        SlicedPacket{link: None, vlan: None, ip: None, transport: Some(TransportSlice::Udp(UdpHeaderSlice::from_slice(udp).unwrap())), payload}
+    }
+
+    fn initiation(sender: u32) -> Vec<u8> {
+        let mut message = vec![1, 0, 0, 0];
+        message.extend_from_slice(&sender.to_le_bytes());
+        message.resize(148, 0xaa);
+        message
+    }
+
+    fn response(sender: u32, receiver: u32) -> Vec<u8> {
+        let mut message = vec![2, 0, 0, 0];
+        message.extend_from_slice(&sender.to_le_bytes());
+        message.extend_from_slice(&receiver.to_le_bytes());
+        message.resize(92, 0xaa);
+        message
+    }
+
+    fn transport_data(receiver: u32) -> Vec<u8> {
+        let mut message = vec![4, 0, 0, 0];
+        message.extend_from_slice(&receiver.to_le_bytes());
+        message.extend_from_slice(&7u64.to_le_bytes());
+        message.resize(48, 0xaa);
+        message
+    }
+
+    fn register(table: &mut SaTable, payload: &[u8], source: &str, destination: &str, ts: u64) {
+        let message = parse_wireguard(payload).unwrap();
+        table.register(&message, source, destination, payload.len() as u32, ts);
+    }
+
+    #[test]
+    fn parses_esp_headers() {
+        let message = parse_esp(&[0, 0, 1, 0, 0, 0, 0, 9]).unwrap();
+        assert_eq!((message.id.as_str(), message.sequence), ("0x00000100", Some(9)));
+        // SPIs below 256 are reserved
+        assert!(parse_esp(&[0, 0, 0, 0xff, 0, 0, 0, 9]).is_none());
+        assert!(parse_esp(&[0, 0, 1, 0, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn parses_ike_headers() {
+        let request = parse_ike(&ike(0x20, 34, 0x08, 28)).unwrap();
+        assert_eq!((request.protocol.as_str(), request.message_type.as_str()), ("IKEv2", "IKE_SA_INIT request"));
+        assert_eq!((request.id.as_str(), request.sequence), ("0x1111111111111111", Some(5)));
+        assert!(!request.rekey);
+        let response = parse_ike(&ike(0x20, 34, 0x20, 28)).unwrap();
+        assert_eq!(response.message_type, "IKE_SA_INIT response");
+        // The response flag is not defined for IKEv1
+        let ikev1 = parse_ike(&ike(0x10, 2, 0x20, 28)).unwrap();
+        assert_eq!((ikev1.protocol.as_str(), ikev1.message_type.as_str()), ("IKEv1", "Identity Protection request"));
+        assert!(parse_ike(&ike(0x30, 34, 0, 28)).is_none());
+    }
+
+    #[test]
+    fn only_create_child_sa_requests_are_rekeys() {
+        assert!(parse_ike(&ike(0x20, 36, 0x08, 28)).unwrap().rekey);
+        assert!(!parse_ike(&ike(0x20, 36, 0x20, 28)).unwrap().rekey);
+        let mut table = SaTable::new();
+        for flags in [0x08, 0x20] {
+            table.register(&parse_ike(&ike(0x20, 36, flags, 28)).unwrap(), "10.0.0.1", "10.0.0.2", 28, 0);
+        }
+        assert_eq!(table.rekeys("10.0.0.2", "10.0.0.1"), 1);
+    }
+
+    #[test]
+    fn ike_length_must_fit_the_packet() {
+        assert!(parse_ike(&ike(0x20, 34, 0x08, 27)).is_none());
+        assert!(parse_ike(&ike(0x20, 34, 0x08, 29)).is_none());
+        let mut message = ike(0x20, 34, 0x08, 32);
+        message.extend_from_slice(&[0; 4]);
+        assert!(parse_ike(&message).is_some());
+        assert!(parse_ike(&message[..27]).is_none());
+    }
+
+    #[test]
+    fn reads_ah_from_the_ipv4_extension_headers() {
+        let auth = [17, 4, 0, 0, 0, 0, 0x12, 0x34, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let packet = SlicedPacket{
+            link: None,
+            vlan: None,
+            ip: Some(InternetSlice::Ipv4(Ipv4HeaderSlice::from_slice(&IPV4_AH_HEADER).unwrap(), Ipv4ExtensionsSlice{auth: Some(IpAuthenticationHeaderSlice::from_slice(&auth).unwrap())})),
+            transport: Some(TransportSlice::Unknown(ESP_PROTOCOL)),
+            payload: &[0, 0, 1, 0, 0, 0, 0, 9],
+        };
+        let message = parse_vpn(&packet, &SaTable::new(), &[]).unwrap();
+        assert_eq!((message.protocol.as_str(), message.id.as_str(), message.sequence), ("AH", "0x00001234", Some(3)));
+    }
+
+    #[test]
+    fn non_esp_marker_separates_ike_from_esp_on_port_4500() {
+        let udp = [0x11, 0x94, 0x11, 0x94, 0, 0, 0, 0];
+        let mut payload = vec![0, 0, 0, 0];
+        payload.extend(ike(0x20, 35, 0x08, 28));
+        let message = parse_vpn(&nat_t_packet(&udp, &payload), &SaTable::new(), &[]).unwrap();
+        assert_eq!((message.protocol.as_str(), message.message_type.as_str()), ("IKEv2", "IKE_AUTH request"));
+        let message = parse_vpn(&nat_t_packet(&udp, &[0, 0, 1, 0, 0, 0, 0, 9]), &SaTable::new(), &[]).unwrap();
+        assert_eq!((message.protocol.as_str(), message.id.as_str()), ("ESP-in-UDP", "0x00000100"));
+        // NAT keepalive
+        assert!(parse_vpn(&nat_t_packet(&udp, &[0xff]), &SaTable::new(), &[]).is_none());
+    }
+
+    #[test]
+    fn associations_expire_without_traffic() {
+        let mut table = SaTable::new();
+        table.register(&parse_esp(&[0, 0, 1, 0, 0, 0, 0, 1]).unwrap(), "10.0.0.1", "10.0.0.2", 100, 0);
+        table.register(&parse_esp(&[0, 0, 2, 0, 0, 0, 0, 1]).unwrap(), "10.0.0.2", "10.0.0.1", 100, 10);
+        table.purge_expired(SA_IDLE_TIMEOUT_S);
+        assert_eq!(table.associations().len(), 2);
+        table.purge_expired(SA_IDLE_TIMEOUT_S + 1);
+        assert_eq!(table.associations()[0].id, "0x00000200");
+    }
+
+    #[test]
+    fn parses_wireguard_messages() {
+        let message = parse_wireguard(&response(0x22, 0x11)).unwrap();
+        assert_eq!(message.message_type, "Handshake Response");
+        assert_eq!(message.id, "0x00000011");
+        assert_eq!(message.responder_id.as_deref(), Some("0x00000022"));
+        let message = parse_wireguard(&transport_data(0x22)).unwrap();
+        assert_eq!(message.message_type, "Transport Data");
+        assert_eq!(message.sequence, Some(7));
+        assert!(parse_wireguard(&transport_data(0x22)[..47]).is_none());
+    }
+
+    #[test]
+    fn handshake_announces_the_session_indexes() {
+        let mut table = SaTable::new();
+        assert!(!table.is_wireguard_session("0x00000011"));
+        register(&mut table, &initiation(0x11), "10.0.0.1", "10.0.0.2", 0);
+        register(&mut table, &response(0x22, 0x11), "10.0.0.2", "10.0.0.1", 0);
+        assert!(table.is_wireguard_session("0x00000011"));
+        assert!(table.is_wireguard_session("0x00000022"));
+        register(&mut table, &transport_data(0x22), "10.0.0.1", "10.0.0.2", SA_IDLE_TIMEOUT_S);
+        register(&mut table, &initiation(0x33), "10.0.0.3", "10.0.0.4", SA_IDLE_TIMEOUT_S + 1);
+        table.purge_expired(SA_IDLE_TIMEOUT_S + 1);
+        assert!(!table.is_wireguard_session("0x00000011"));
+        assert!(table.is_wireguard_session("0x00000022"));
+    }
+
+    #[test]
+    fn only_completed_handshakes_are_rekeys() {
+        let mut table = SaTable::new();
+        register(&mut table, &initiation(0x11), "10.0.0.1", "10.0.0.2", 0);
+        // Initiations retransmitted without a response
+        register(&mut table, &initiation(0x12), "10.0.0.1", "10.0.0.2", 5);
+        register(&mut table, &initiation(0x13), "10.0.0.1", "10.0.0.2", 10);
+        register(&mut table, &response(0x21, 0x13), "10.0.0.2", "10.0.0.1", 10);
+        register(&mut table, &response(0x21, 0x13), "10.0.0.2", "10.0.0.1", 10);
+        assert_eq!(table.rekeys("10.0.0.1", "10.0.0.2"), 0);
+        register(&mut table, &initiation(0x14), "10.0.0.1", "10.0.0.2", 120);
+        register(&mut table, &response(0x22, 0x14), "10.0.0.2", "10.0.0.1", 120);
+        assert_eq!(table.rekeys("10.0.0.1", "10.0.0.2"), 1);
+    }
+}
@@ -0,0 +1,588 @@
+use std::collections::{HashMap, HashSet};
+use crate::report::{mac_address_to_string, MacAddress};
+
+/// Link type of the captures of 802.11 frames without any pseudo-header.
+pub const LINKTYPE_IEEE802_11: i32 = 105;
+/// Link type of the captures of 802.11 frames preceded by a radiotap header, used by the interfaces in monitor mode.
+pub const LINKTYPE_IEEE802_11_RADIOTAP: i32 = 127;
+/// LLC/SNAP header that precedes the EtherType of the data frames.
+const LLC_SNAP_HEADER: [u8; 6] = [0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00];
+/// Maximum number of information elements read from a single management frame.
+const MAX_INFORMATION_ELEMENTS: usize = 64;
+/// Time in seconds after which a network, or a station associated with a network, without frames is removed.
+const WIFI_IDLE_TIMEOUT_S: u64 = 300;
+/// Time in seconds after which a station never associated with a network, e.g. one that only sent probe requests from a
+/// randomized address, is removed when it sends no frames.
+const UNASSOCIATED_IDLE_TIMEOUT_S: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default)]
+///Struct containing the radio informations of a radiotap header.
+pub struct RadiotapInfo {
+    /// Signal strength at the antenna, in dBm
+    pub signal_dbm: Option<i8>,
+    /// Noise at the antenna, in dBm
+    pub noise_dbm: Option<i8>,
+    /// Frequency of the channel, in MHz
+    pub frequency: Option<u16>,
+    /// Data rate, in units of 500 kb/s
+    pub rate: Option<u8>,
+    /// True if the frame ends with the 4-byte frame check sequence
+    pub has_fcs: bool,
+}
+
+#[derive(Debug, Clone)]
+///Struct which reports info relative to an 802.11 frame.
+pub struct WifiFrame<'a> {
+    /// Radio informations, if the frame was captured with a radiotap header
+    pub radio: Option<RadiotapInfo>,
+    /// Type of the frame, e.g. "Beacon" or "QoS Data"
+    pub frame_type: String,
+    /// Address of the station that transmitted the frame
+    pub transmitter: Option<[u8; 6]>,
+    /// Original source of the frame
+    pub source: Option<[u8; 6]>,
+    /// Final destination of the frame
+    pub destination: [u8; 6],
+    /// BSSID of the network the frame belongs to
+    pub bssid: Option<[u8; 6]>,
+    /// SSID announced by beacons, probes and association requests
+    pub ssid: Option<String>,
+    /// True if the body of the frame is encrypted
+    pub protected: bool,
+    /// EtherType and content of the LLC/SNAP payload of the unencrypted data frames
+    pub payload: Option<(u16, &'a [u8])>,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the traffic of a wireless station.
+pub struct StationEntry {
+    /// MAC address of the station
+    pub mac: String,
+    /// BSSID the station last communicated with
+    pub bssid: Option<String>,
+    /// Frames transmitted by the station
+    pub packets: u64,
+    /// Bytes transmitted by the station
+    pub bytes: u64,
+    /// Radio informations of the last frame transmitted by the station
+    pub radio: Option<RadiotapInfo>,
+    /// Timestamp (in seconds) of the first frame
+    pub first_seen: u64,
+    /// Timestamp (in seconds) of the last frame
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Clone)]
+///Struct containing the informations and the traffic of a wireless network (BSS).
+pub struct BssEntry {
+    /// BSSID of the network
+    pub bssid: String,
+    /// SSID announced by the access point
+    pub ssid: Option<String>,
+    /// Frequency of the channel, in MHz
+    pub frequency: Option<u16>,
+    /// Beacons received
+    pub beacons: u64,
+    /// Frames that belong to the network
+    pub packets: u64,
+    /// Bytes of the frames that belong to the network
+    pub bytes: u64,
+    /// Stations that exchanged data frames with the network
+    pub stations: HashSet<String>,
+    /// Timestamp (in seconds) of the last frame
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Default)]
+///Struct that aggregates the 802.11 traffic per station and per BSSID. The counters are cumulative from the first frame
+///of the station or network, and are not reset at each report; the stations and networks are removed when idle.
+pub struct WifiTable {
+    /// Stations by MAC address
+    stations: HashMap<String, StationEntry>,
+    /// Networks by BSSID
+    networks: HashMap<String, BssEntry>,
+}
+
+/// Function that returns the MAC address at `offset` in `data`.
+fn address(data: &[u8], offset: usize) -> Option<[u8; 6]> {
+    data.get(offset..offset + 6)?.try_into().ok()
+}
+
+/// Function that parses a radiotap header, returning the radio informations and the 802.11 frame that follows it.
+pub fn parse_radiotap(data: &[u8]) -> Option<(RadiotapInfo, &[u8])> {
+    if data.len() < 8 || data[0] != 0 {
+        return None;
+    }
+    let length = u16::from_le_bytes([data[2], data[3]]) as usize;
+    if length < 8 || length > data.len() {
+        return None;
+    }
+    let present = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    // Bit 31 announces another presence bitmap, the fields start after the last one
+    let mut offset = 8;
+    let mut word = present;
+    while word & 0x8000_0000 != 0 && offset + 4 <= length {
+        word = u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+        offset += 4;
+    }
+    let mut info = RadiotapInfo::default();
+    // Fields in the order of their bit, with their alignment and size; only the first 7 are decoded
+    let fields: [(usize, usize); 7] = [(8, 8), (1, 1), (1, 1), (2, 4), (2, 2), (1, 1), (1, 1)];
+    for (bit, (align, size)) in fields.iter().enumerate() {
+        if present & (1 << bit) == 0 {
+            continue;
+        }
+        offset = (offset + align - 1) & !(align - 1);
+        let field = match data.get(offset..offset + size) {
+            Some(field) if offset + size <= length => field,
+            _ => break
+        };
+        match bit {
+            1 => info.has_fcs = field[0] & 0x10 != 0,
+            2 => info.rate = Some(field[0]),
+            3 => info.frequency = Some(u16::from_le_bytes([field[0], field[1]])),
+            5 => info.signal_dbm = Some(field[0] as i8),
+            6 => info.noise_dbm = Some(field[0] as i8),
+            _ => {}
+        }
+        offset += size;
+    }
+    Some((info, &data[length..]))
+}
+
+/// Function that returns the name of an 802.11 frame given its type and subtype.
+fn frame_type_name(frame_type: u8, subtype: u8) -> String {
+    match (frame_type, subtype) {
+        (0, 0) => "Association Request".to_string(),
+        (0, 1) => "Association Response".to_string(),
+        (0, 2) => "Reassociation Request".to_string(),
+        (0, 3) => "Reassociation Response".to_string(),
+        (0, 4) => "Probe Request".to_string(),
+        (0, 5) => "Probe Response".to_string(),
+        (0, 8) => "Beacon".to_string(),
+        (0, 10) => "Disassociation".to_string(),
+        (0, 11) => "Authentication".to_string(),
+        (0, 12) => "Deauthentication".to_string(),
+        (0, 13) => "Action".to_string(),
+        (1, 8) => "Block Ack Request".to_string(),
+        (1, 9) => "Block Ack".to_string(),
+        (1, 10) => "PS-Poll".to_string(),
+        (1, 11) => "RTS".to_string(),
+        (1, 12) => "CTS".to_string(),
+        (1, 13) => "ACK".to_string(),
+        (2, 0) => "Data".to_string(),
+        (2, 4) => "Null".to_string(),
+        (2, 8) => "QoS Data".to_string(),
+        (2, 12) => "QoS Null".to_string(),
+        (t, s) => format!("Type {} subtype {}", t, s)
+    }
+}
+
+/// Function that returns the SSID contained in the information elements of a management frame.
+fn parse_ssid(mut elements: &[u8]) -> Option<String> {
+    for _ in 0..MAX_INFORMATION_ELEMENTS {
+        if elements.len() < 2 {
+            break;
+        }
+        let value = elements.get(2..2 + elements[1] as usize)?;
+        if elements[0] == 0 {
+            // A hidden network announces an empty SSID
+            return Some(String::from_utf8_lossy(value).trim_end_matches('\0').to_string());
+        }
+        elements = &elements[2 + value.len()..];
+    }
+    None
+}
+
+/// Function that parses an 802.11 frame: the addresses of management and data frames, the SSID of beacons, probes and
+/// association requests and the LLC/SNAP payload of unencrypted data frames. `radio` contains the informations of the
+/// radiotap header, if any.
+pub fn parse_80211(data: &[u8], radio: Option<RadiotapInfo>) -> Option<WifiFrame<'_>> {
+    let data = match radio {
+        Some(info) if info.has_fcs => data.get(..data.len().checked_sub(4)?)?,
+        _ => data
+    };
+    if data.len() < 10 {
+        return None;
+    }
+    let frame_type = (data[0] >> 2) & 0x03;
+    let subtype = data[0] >> 4;
+    let flags = data[1];
+    let to_ds = flags & 0x01 != 0;
+    let from_ds = flags & 0x02 != 0;
+    let protected = flags & 0x40 != 0;
+    let mut frame = WifiFrame{
+        radio,
+        frame_type: frame_type_name(frame_type, subtype),
+        transmitter: None,
+        source: None,
+        destination: address(data, 4)?,
+        bssid: None,
+        ssid: None,
+        protected,
+        payload: None,
+    };
+    match frame_type {
+        0 => {
+            frame.transmitter = address(data, 10);
+            frame.source = frame.transmitter;
+            frame.bssid = address(data, 16);
+            let body = data.get(24..).unwrap_or(&[]);
+            // Length of the fixed parameters that precede the information elements
+            let fixed = match subtype {
+                0 => Some(4),
+                2 => Some(10),
+                4 => Some(0),
+                5 | 8 => Some(12),
+                _ => None
+            };
+            if let Some(elements) = fixed.and_then(|f| body.get(f..)) {
+                frame.ssid = parse_ssid(elements);
+            }
+        },
+        2 => {
+            let addr1 = address(data, 4)?;
+            let addr2 = address(data, 10)?;
+            let addr3 = address(data, 16)?;
+            frame.transmitter = Some(addr2);
+            let (destination, source, bssid) = match (to_ds, from_ds) {
+                (false, false) => (addr1, Some(addr2), Some(addr3)),
+                (true, false) => (addr3, Some(addr2), Some(addr1)),
+                (false, true) => (addr1, Some(addr3), Some(addr2)),
+                (true, true) => (addr3, address(data, 24), None)
+            };
+            frame.destination = destination;
+            frame.source = source;
+            frame.bssid = bssid;
+            let mut header_len = if to_ds && from_ds { 30 } else { 24 };
+            // QoS data frames have a QoS control field, followed by an HT control field when the order flag is set
+            if subtype & 0x08 != 0 {
+                header_len += 2;
+                if flags & 0x80 != 0 {
+                    header_len += 4;
+                }
+            }
+            // Null frames carry no data
+            if !protected && subtype & 0x04 == 0 {
+                if let Some(body) = data.get(header_len..) {
+                    if body.len() >= 8 && body[0..6] == LLC_SNAP_HEADER {
+                        frame.payload = Some((u16::from_be_bytes([body[6], body[7]]), &body[8..]));
+                    }
+                }
+            }
+        },
+        _ => {
+            // Control frames only have the receiver address, and RTS, PS-Poll and Block Ack also the transmitter one
+            frame.transmitter = address(data, 10);
+        }
+    }
+    Some(frame)
+}
+
+/// The function takes as parameter a `RadiotapInfo` struct and generate a String containing its formatted informations.
+pub fn radiotap_info_to_string(info: &RadiotapInfo) -> String {
+    let mut radio_string = "".to_owned();
+    if let Some(frequency) = info.frequency {
+        radio_string.push_str(&format!("; Frequency: {} MHz", frequency));
+    }
+    if let Some(rate) = info.rate {
+        radio_string.push_str(&format!("; Rate: {:.1} Mb/s", rate as f32 / 2.0));
+    }
+    if let Some(signal) = info.signal_dbm {
+        radio_string.push_str(&format!("; Signal: {} dBm", signal));
+    }
+    if let Some(noise) = info.noise_dbm {
+        radio_string.push_str(&format!("; Noise: {} dBm", noise));
+    }
+    radio_string.trim_start_matches("; ").to_string()
+}
+
+impl WifiTable {
+    /// Create a new empty WifiTable struct.
+    pub fn new() -> WifiTable {
+        WifiTable{stations: HashMap::new(), networks: HashMap::new()}
+    }
+
+    /// Registers an 802.11 frame of `bytes` bytes captured at timestamp `ts` (in seconds).
+    pub fn register(&mut self, frame: &WifiFrame, bytes: u32, ts: u64) {
+        let frequency = frame.radio.and_then(|r| r.frequency);
+        // Probe requests use the broadcast BSSID
+        let bssid = frame.bssid.filter(|b| *b != [0xff; 6]).map(|b| mac_address_to_string(MacAddress::new(b)));
+        if let Some(bssid) = &bssid {
+            let network = self.networks.entry(bssid.clone()).or_insert_with(|| BssEntry{
+                bssid: bssid.clone(), ssid: None, frequency: None, beacons: 0, packets: 0, bytes: 0, stations: HashSet::new(), last_seen: ts});
+            network.packets += 1;
+            network.bytes += bytes as u64;
+            network.last_seen = ts;
+            if frame.frame_type == "Beacon" || frame.frame_type == "Probe Response" {
+                if frame.frame_type == "Beacon" {
+                    network.beacons += 1;
+                }
+                if frame.ssid.is_some() {
+                    network.ssid = frame.ssid.clone();
+                }
+                network.frequency = frequency.or(network.frequency);
+            }
+        }
+        if let Some(transmitter) = frame.transmitter {
+            let mac = mac_address_to_string(MacAddress::new(transmitter));
+            let station = self.stations.entry(mac.clone()).or_insert_with(|| StationEntry{
+                mac: mac.clone(), bssid: None, packets: 0, bytes: 0, radio: None, first_seen: ts, last_seen: ts});
+            station.packets += 1;
+            station.bytes += bytes as u64;
+            station.last_seen = ts;
+            station.radio = frame.radio.or(station.radio);
+            // The access point transmits with its BSSID, the other stations are its clients
+            if let Some(bssid) = bssid.filter(|b| *b != mac) {
+                if frame.frame_type.contains("Data") || frame.frame_type.contains("Association") {
+                    if let Some(network) = self.networks.get_mut(&bssid) {
+                        network.stations.insert(mac.clone());
+                    }
+                    station.bssid = Some(bssid);
+                }
+            }
+        }
+    }
+
+    /// Removes, at timestamp `now` (in seconds), the networks and stations without frames for longer than their idle timeout.
+    pub fn purge_expired(&mut self, now: u64) {
+        self.stations.retain(|_, station| {
+            let timeout = if station.bssid.is_some() { WIFI_IDLE_TIMEOUT_S } else { UNASSOCIATED_IDLE_TIMEOUT_S };
+            station.last_seen + timeout >= now
+        });
+        let deadline = now.saturating_sub(WIFI_IDLE_TIMEOUT_S);
+        self.networks.retain(|_, network| network.last_seen >= deadline);
+        let stations = &self.stations;
+        for network in self.networks.values_mut() {
+            network.stations.retain(|mac| stations.contains_key(mac));
+        }
+    }
+
+    /// Returns the wireless stations, sorted by MAC address.
+    pub fn stations(&self) -> Vec<StationEntry> {
+        let mut stations: Vec<StationEntry> = self.stations.values().cloned().collect();
+        stations.sort_by(|a, b| a.mac.cmp(&b.mac));
+        stations
+    }
+
+    /// Returns the wireless networks, sorted by BSSID.
+    pub fn networks(&self) -> Vec<BssEntry> {
+        let mut networks: Vec<BssEntry> = self.networks.values().cloned().collect();
+        networks.sort_by(|a, b| a.bssid.cmp(&b.bssid));
+        networks
+    }
+
+    /// Generates the 802.11 section of a report, listing the networks with their stations and then each station.
+    pub fn to_report_string(&self) -> String {
+        let mut s = "".to_owned();
+        s.push_str("-----Wi-Fi networks and stations-----\n");
+        for network in self.networks() {
+            let mut stations: Vec<&str> = network.stations.iter().map(|m| m.as_str()).collect();
+            stations.sort();
+            s.push_str(&format!("BSSID {}; SSID: {}; Frequency: {}; Beacons: {}; Packets: {}; Bytes: {}; Stations: {}\n",
+                                network.bssid,
+                                network.ssid.as_deref().map(|ssid| if ssid.is_empty() { "<hidden>" } else { ssid }).unwrap_or("Unknown"),
+                                network.frequency.map(|f| format!("{} MHz", f)).unwrap_or_else(|| "Unknown".to_string()),
+                                network.beacons,
+                                network.packets,
+                                network.bytes,
+                                stations.join(", ")));
+        }
+        for station in self.stations() {
+            s.push_str(&format!("Station {}; BSSID: {}; Radio: {}; Packets: {}; Bytes: {}; First seen: {}; Last seen: {}\n",
+                                station.mac,
+                                station.bssid.as_deref().unwrap_or("None"),
+                                station.radio.map(|r| radiotap_info_to_string(&r)).unwrap_or_else(|| "Unknown".to_string()),
+                                station.packets,
+                                station.bytes,
+                                station.first_seen,
+                                station.last_seen));
+        }
+        s.push('\n');
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AP: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+    const CLIENT: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+    const SERVER: [u8; 6] = [0x02, 0, 0, 0, 0, 0x03];
+    const REMOTE: [u8; 6] = [0x02, 0, 0, 0, 0, 0x04];
+
+    /// Returns an 802.11 header with the frame control `type_subtype` and `flags`, followed by `addresses`, with the
+    /// sequence control field after the third one.
+    fn header(type_subtype: u8, flags: u8, addresses: &[[u8; 6]]) -> Vec<u8> {
+        let mut frame = vec![type_subtype, flags, 0, 0];
+        for (i, address) in addresses.iter().enumerate() {
+            if i == 3 {
+                frame.extend_from_slice(&[0, 0]);
+            }
+            frame.extend_from_slice(address);
+        }
+        if addresses.len() == 3 {
+            frame.extend_from_slice(&[0, 0]);
+        }
+        frame
+    }
+
+    /// Returns an LLC/SNAP encapsulated IPv4 payload.
+    fn snap_payload() -> Vec<u8> {
+        let mut body = LLC_SNAP_HEADER.to_vec();
+        body.extend_from_slice(&[0x08, 0x00, 0x45, 0x00]);
+        body
+    }
+
+    #[test]
+    fn radiotap_with_extended_bitmaps_and_alignment() {
+        // TSFT, Flags (with FCS), Channel and Antenna signal, and a second presence bitmap
+        let mut data = vec![0, 0, 31, 0];
+        data.extend_from_slice(&(0x8000_002bu32).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        // TSFT is aligned to 8 bytes, after 12 bytes of header and bitmaps
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&[0x11; 8]);
+        data.push(0x10);
+        // Channel is aligned to 2 bytes
+        data.push(0);
+        data.extend_from_slice(&2437u16.to_le_bytes());
+        data.extend_from_slice(&0x00a0u16.to_le_bytes());
+        data.push(-42i8 as u8);
+        assert_eq!(data.len(), 31);
+        data.extend_from_slice(b"frame");
+        let (info, frame) = parse_radiotap(&data).unwrap();
+        assert!(info.has_fcs);
+        assert_eq!(info.frequency, Some(2437));
+        assert_eq!(info.signal_dbm, Some(-42));
+        assert_eq!(info.rate, None);
+        assert_eq!(frame, b"frame");
+    }
+
+    #[test]
+    fn radiotap_with_rate_and_noise() {
+        // Rate, Antenna signal and Antenna noise, without FCS
+        let data = [0, 0, 11, 0, 0x64, 0, 0, 0, 12, (-60i8) as u8, (-95i8) as u8];
+        let (info, frame) = parse_radiotap(&data).unwrap();
+        assert!(!info.has_fcs);
+        assert_eq!(info.rate, Some(12));
+        assert_eq!(info.signal_dbm, Some(-60));
+        assert_eq!(info.noise_dbm, Some(-95));
+        assert!(frame.is_empty());
+        assert!(parse_radiotap(&[0, 0, 12, 0, 0, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn data_frame_addresses_for_each_ds_direction() {
+        // (ToDS, FromDS) -> addresses of the header, expected destination, source and BSSID
+        let cases: [(u8, Vec<[u8; 6]>, [u8; 6], Option<[u8; 6]>, Option<[u8; 6]>); 4] = [
+            (0x00, vec![SERVER, CLIENT, AP], SERVER, Some(CLIENT), Some(AP)),
+            (0x01, vec![AP, CLIENT, SERVER], SERVER, Some(CLIENT), Some(AP)),
+            (0x02, vec![CLIENT, AP, SERVER], CLIENT, Some(SERVER), Some(AP)),
+            (0x03, vec![AP, CLIENT, SERVER, REMOTE], SERVER, Some(REMOTE), None),
+        ];
+        for (flags, addresses, destination, source, bssid) in cases {
+            let mut data = header(0x08, flags, &addresses);
+            assert_eq!(data.len(), if flags == 0x03 { 30 } else { 24 });
+            data.extend_from_slice(&snap_payload());
+            let frame = parse_80211(&data, None).unwrap();
+            assert_eq!(frame.frame_type, "Data");
+            assert_eq!(frame.transmitter, Some(addresses[1]));
+            assert_eq!(frame.destination, destination);
+            assert_eq!(frame.source, source);
+            assert_eq!(frame.bssid, bssid);
+            assert_eq!(frame.payload, Some((0x0800, &[0x45, 0x00][..])));
+        }
+    }
+
+    #[test]
+    fn qos_data_with_ht_control() {
+        let mut data = header(0x88, 0x81, &[AP, CLIENT, SERVER]);
+        // QoS control, then HT control because of the order flag
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&snap_payload());
+        let frame = parse_80211(&data, None).unwrap();
+        assert_eq!(frame.frame_type, "QoS Data");
+        assert_eq!(frame.payload, Some((0x0800, &[0x45, 0x00][..])));
+
+        // Without the order flag, the payload follows the QoS control field
+        let mut data = header(0x88, 0x01, &[AP, CLIENT, SERVER]);
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&snap_payload());
+        assert_eq!(parse_80211(&data, None).unwrap().payload, Some((0x0800, &[0x45, 0x00][..])));
+    }
+
+    #[test]
+    fn protected_and_null_frames_have_no_payload() {
+        let mut data = header(0x08, 0x41, &[AP, CLIENT, SERVER]);
+        data.extend_from_slice(&snap_payload());
+        let frame = parse_80211(&data, None).unwrap();
+        assert!(frame.protected);
+        assert!(frame.payload.is_none());
+
+        let mut data = header(0x48, 0x01, &[AP, CLIENT, AP]);
+        data.extend_from_slice(&snap_payload());
+        let frame = parse_80211(&data, None).unwrap();
+        assert_eq!(frame.frame_type, "Null");
+        assert!(frame.payload.is_none());
+    }
+
+    #[test]
+    fn frame_check_sequence_is_removed() {
+        let mut data = header(0x08, 0x01, &[AP, CLIENT, SERVER]);
+        data.extend_from_slice(&snap_payload());
+        data.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let radio = RadiotapInfo{has_fcs: true, ..Default::default()};
+        let frame = parse_80211(&data, Some(radio)).unwrap();
+        assert_eq!(frame.payload, Some((0x0800, &[0x45, 0x00][..])));
+    }
+
+    /// Returns a beacon of `AP` whose information elements start with the SSID element containing `ssid`.
+    fn beacon(ssid: &[u8]) -> Vec<u8> {
+        let mut data = header(0x80, 0x00, &[[0xff; 6], AP, AP]);
+        // Timestamp, beacon interval and capabilities
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&[0, ssid.len() as u8]);
+        data.extend_from_slice(ssid);
+        // Supported rates
+        data.extend_from_slice(&[1, 2, 0x82, 0x84]);
+        data
+    }
+
+    #[test]
+    fn beacon_ssid() {
+        let data = beacon(b"Office");
+        let frame = parse_80211(&data, None).unwrap();
+        assert_eq!(frame.frame_type, "Beacon");
+        assert_eq!(frame.ssid.as_deref(), Some("Office"));
+        assert_eq!(frame.bssid, Some(AP));
+        // Hidden networks announce an empty SSID, or one made of null bytes
+        assert_eq!(parse_80211(&beacon(b""), None).unwrap().ssid.as_deref(), Some(""));
+        assert_eq!(parse_80211(&beacon(&[0; 6]), None).unwrap().ssid.as_deref(), Some(""));
+        // An element longer than the frame is not read
+        let mut truncated = beacon(b"Office");
+        truncated.truncate(24 + 12 + 4);
+        assert!(parse_80211(&truncated, None).unwrap().ssid.is_none());
+    }
+
+    #[test]
+    fn idle_stations_and_networks_expire() {
+        let mut table = WifiTable::new();
+        table.register(&parse_80211(&beacon(b"Office"), None).unwrap(), 100, 0);
+        let mut data = header(0x08, 0x01, &[AP, CLIENT, SERVER]);
+        data.extend_from_slice(&snap_payload());
+        table.register(&parse_80211(&data, None).unwrap(), 100, 0);
+        let probe = header(0x40, 0x00, &[[0xff; 6], REMOTE, [0xff; 6]]);
+        table.register(&parse_80211(&probe, None).unwrap(), 100, 0);
+        assert_eq!(table.stations().len(), 3);
+        table.purge_expired(UNASSOCIATED_IDLE_TIMEOUT_S + 1);
+        // The probing station is removed first; the access point only transmits beacons
+        let stations: Vec<String> = table.stations().into_iter().map(|s| s.mac).collect();
+        assert_eq!(stations, vec![mac_address_to_string(MacAddress::new(CLIENT))]);
+        assert_eq!(table.networks()[0].stations.len(), 1);
+        table.purge_expired(WIFI_IDLE_TIMEOUT_S + 1);
+        assert!(table.stations().is_empty());
+        assert!(table.networks().is_empty());
+    }
+}
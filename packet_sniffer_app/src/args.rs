@@ -1,3 +1,5 @@
+use PacketSnifferLib::classify::AppSignature;
+use PacketSnifferLib::rollup::NamedSubnet;
 use clap::{
     Args,
     Parser,
@@ -28,7 +30,22 @@ pub struct CaptureCommand {
     /// The interval after which a new report is generated in the file
     pub interval: u64,
     /// (Optional) The filter that needs to be applied to the packets, following the Berkeley Packet Filter Syntax
-    pub filter: Option<String>
+    pub filter: Option<String>,
+    /// (Optional) Additional port on which traffic is decoded as DNS, besides 53, 5353 (mDNS) and 5355 (LLMNR). It can be repeated
+    #[clap(long = "dns-port")]
+    pub dns_ports: Vec<u16>,
+    /// (Optional) Port on which WireGuard transport data is recognized also without a captured handshake, e.g. 51820. It can be repeated
+    #[clap(long = "wireguard-port")]
+    pub wireguard_ports: Vec<u16>,
+    /// (Optional) User-defined application signature, with format NAME:OFFSET:HEX[:TRANSPORT[:PORT]]. It can be repeated
+    #[clap(long = "signature")]
+    pub signatures: Vec<AppSignature>,
+    /// (Optional) Write the traffic aggregated per host in each report
+    #[clap(long = "per-host")]
+    pub per_host: bool,
+    /// (Optional) Named subnet the traffic is aggregated per, with format [NAME=]ADDRESS/PREFIX, e.g. office=10.1.0.0/16. It can be repeated
+    #[clap(long = "subnet")]
+    pub subnets: Vec<NamedSubnet>
 }
 
 #[derive(Debug, Args)]
@@ -4,15 +4,15 @@ extern crate core;
 use args::MyCommand;
 use std::{thread};
 use PacketSnifferLib::PacketCatcher;
+use PacketSnifferLib::options::AggregationOptions;
 use std::string::String;
 use std::thread::{JoinHandle};
 use clap::Parser;
 use crate::args::Comms::{Capture, Devices};
 
-pub fn main_capture(device_name: String, file_name: String, interval: u64, filter: Option<String>) -> JoinHandle<()> {
+pub fn main_capture(mut p: PacketCatcher, device_name: String, file_name: String, interval: u64, filter: Option<String>) -> JoinHandle<()> {
 
     let t1 = thread::spawn(move || {
-        let mut p = PacketCatcher::new();
         let mut paused = false;
         let x = p.capture(device_name, file_name, interval, filter);
         let mut success = true;
@@ -79,7 +79,15 @@ fn main() {
         Capture(cap_values) => {
             let mut file_txt = cap_values.file_name.clone();
             file_txt.push_str(".txt");
-            let h = main_capture(cap_values.device_name, file_txt, cap_values.interval, cap_values.filter);
+            let aggregation = AggregationOptions{hosts: cap_values.per_host, subnets: cap_values.subnets, ..Default::default()};
+            let mut p = PacketCatcher::new();
+            p.set_extra_dns_ports(cap_values.dns_ports);
+            p.set_wireguard_ports(cap_values.wireguard_ports);
+            for signature in cap_values.signatures {
+                p.add_signature(signature);
+            }
+            p.set_aggregation(aggregation);
+            let h = main_capture(p, cap_values.device_name, file_txt, cap_values.interval, cap_values.filter);
             h.join().unwrap();
         },
         Devices(_) => {